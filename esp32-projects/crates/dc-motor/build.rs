@@ -0,0 +1,6 @@
+//! crate dc-motor
+
+use embuild::espidf;
+fn main() {
+    espidf::sysenv::output();
+}
@@ -0,0 +1,95 @@
+use embedded_hal::pwm::SetDutyCycle;
+
+use crate::{set_duty, DcError, DcMotor};
+
+/// How the motor current decays during the off time of the PWM cycle
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Decay {
+    /// Let the motor coast between pulses, speed follows the duty cycle less linearly
+    Fast,
+    /// Brake the motor between pulses, speed is close to proportional to the duty cycle
+    #[default]
+    Slow,
+}
+
+/// One channel of a driver without an enable pin, like the DRV8833 or DRV8871,
+/// with a PWM signal on both inputs
+pub struct Drv8833<A: SetDutyCycle, B: SetDutyCycle> {
+    in1: A,
+    in2: B,
+    decay: Decay,
+    speed: f32,
+}
+
+impl<A: SetDutyCycle, B: SetDutyCycle> Drv8833<A, B> {
+    /// Create a new `Drv8833` with slow decay, coasting until a speed is set
+    pub fn new(in1: A, in2: B) -> Result<Self, DcError> {
+        let mut driver = Self {
+            in1,
+            in2,
+            decay: Decay::default(),
+            speed: 0.0,
+        };
+        driver.coast()?;
+        Ok(driver)
+    }
+
+    /// Use a different decay mode, takes effect from the next speed change
+    #[must_use]
+    pub const fn with_decay(mut self, decay: Decay) -> Self {
+        self.decay = decay;
+        self
+    }
+
+    /// Returns the decay mode
+    pub const fn decay(&self) -> Decay {
+        self.decay
+    }
+
+    /// Stop using the driver and get the pins back
+    pub fn into_inner(self) -> (A, B) {
+        (self.in1, self.in2)
+    }
+
+    fn set_inputs(&mut self, in1: f32, in2: f32) -> Result<(), DcError> {
+        set_duty(&mut self.in1, "in1", in1)?;
+        set_duty(&mut self.in2, "in2", in2)
+    }
+}
+
+impl<A: SetDutyCycle, B: SetDutyCycle> DcMotor for Drv8833<A, B> {
+    fn set_speed(&mut self, speed: f32) -> Result<(), DcError> {
+        let speed = speed.clamp(-1.0, 1.0);
+        if speed == 0.0 {
+            return self.coast();
+        }
+        // slow decay drives with both inputs high and brakes with the inverted duty on the other
+        let (drive, off) = match self.decay {
+            Decay::Fast => (speed.abs(), 0.0),
+            Decay::Slow => (1.0, 1.0 - speed.abs()),
+        };
+        if speed > 0.0 {
+            self.set_inputs(drive, off)?;
+        } else {
+            self.set_inputs(off, drive)?;
+        }
+        self.speed = speed;
+        Ok(())
+    }
+
+    fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    fn coast(&mut self) -> Result<(), DcError> {
+        self.set_inputs(0.0, 0.0)?;
+        self.speed = 0.0;
+        Ok(())
+    }
+
+    fn brake(&mut self) -> Result<(), DcError> {
+        self.set_inputs(1.0, 1.0)?;
+        self.speed = 0.0;
+        Ok(())
+    }
+}
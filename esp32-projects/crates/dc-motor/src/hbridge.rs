@@ -0,0 +1,81 @@
+use embedded_hal::{
+    digital::{OutputPin, PinState},
+    pwm::SetDutyCycle,
+};
+
+use crate::{set_duty, set_state, DcError, DcMotor};
+
+/// An H-bridge with a PWM enable and two direction inputs, like the L298N or TB6612FNG
+///
+/// The speed is set through the duty cycle of the enable pin, called ENA on
+/// the L298N and PWMA on the TB6612FNG, while IN1 and IN2 pick the direction.
+pub struct HBridge<P: SetDutyCycle, A: OutputPin, B: OutputPin> {
+    enable: P,
+    in1: A,
+    in2: B,
+    speed: f32,
+}
+
+impl<P: SetDutyCycle, A: OutputPin, B: OutputPin> HBridge<P, A, B> {
+    /// Create a new `HBridge`, coasting until a speed is set
+    pub fn new(enable: P, in1: A, in2: B) -> Result<Self, DcError> {
+        let mut bridge = Self {
+            enable,
+            in1,
+            in2,
+            speed: 0.0,
+        };
+        bridge.coast()?;
+        Ok(bridge)
+    }
+
+    /// Stop using the driver and get the pins back
+    pub fn into_inner(self) -> (P, A, B) {
+        (self.enable, self.in1, self.in2)
+    }
+
+    fn set_inputs(&mut self, in1: PinState, in2: PinState) -> Result<(), DcError> {
+        set_state(&mut self.in1, "in1", in1)?;
+        set_state(&mut self.in2, "in2", in2)
+    }
+}
+
+impl<P: SetDutyCycle, A: OutputPin, B: OutputPin> DcMotor for HBridge<P, A, B> {
+    fn set_speed(&mut self, speed: f32) -> Result<(), DcError> {
+        let speed = speed.clamp(-1.0, 1.0);
+        if speed == 0.0 {
+            return self.coast();
+        }
+        // only switch the direction inputs on a reversal, and without driving the motor
+        if self.speed == 0.0 || self.speed.is_sign_positive() != speed.is_sign_positive() {
+            set_duty(&mut self.enable, "enable", 0.0)?;
+            if speed > 0.0 {
+                self.set_inputs(PinState::High, PinState::Low)?;
+            } else {
+                self.set_inputs(PinState::Low, PinState::High)?;
+            }
+        }
+        set_duty(&mut self.enable, "enable", speed.abs())?;
+        self.speed = speed;
+        Ok(())
+    }
+
+    fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    fn coast(&mut self) -> Result<(), DcError> {
+        set_duty(&mut self.enable, "enable", 0.0)?;
+        self.set_inputs(PinState::Low, PinState::Low)?;
+        self.speed = 0.0;
+        Ok(())
+    }
+
+    fn brake(&mut self) -> Result<(), DcError> {
+        set_duty(&mut self.enable, "enable", 0.0)?;
+        self.set_inputs(PinState::High, PinState::High)?;
+        set_duty(&mut self.enable, "enable", 1.0)?;
+        self.speed = 0.0;
+        Ok(())
+    }
+}
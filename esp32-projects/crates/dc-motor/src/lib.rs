@@ -0,0 +1,85 @@
+use core::fmt;
+use embedded_hal::digital::{self, OutputPin, PinState};
+use embedded_hal::pwm::{self, SetDutyCycle};
+
+mod drv8833;
+mod hbridge;
+pub mod runner;
+
+pub use drv8833::{Decay, Drv8833};
+pub use hbridge::HBridge;
+
+// === DcError ===
+
+/// A type detailing the errors that can happen while driving the H-bridge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DcError {
+    /// Received a low-level error from the HAL while setting a direction pin, e.g. `in1`
+    Pin {
+        pin: &'static str,
+        kind: digital::ErrorKind,
+    },
+    /// Received a low-level error from the HAL while setting a PWM duty cycle
+    Pwm {
+        pin: &'static str,
+        kind: pwm::ErrorKind,
+    },
+    /// An acceleration that is not a positive speed change per second was requested
+    InvalidAcceleration,
+}
+
+impl fmt::Display for DcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pin { pin, kind } => write!(f, "HAL pin error on {pin}: {kind}"),
+            Self::Pwm { pin, kind } => write!(f, "HAL pwm error on {pin}: {kind:?}"),
+            Self::InvalidAcceleration => f.write_str("Acceleration must be positive"),
+        }
+    }
+}
+
+impl std::error::Error for DcError {}
+
+// === DcMotor ===
+
+/// trait to prevent having to pass around the struct with all the generic arguments
+pub trait DcMotor {
+    /// Run at a signed speed from -1.0 to 1.0, the fraction of the supply voltage.
+    /// Negative speeds run in reverse, 0 coasts.
+    fn set_speed(&mut self, speed: f32) -> Result<(), DcError>;
+    /// Returns the speed last set, 0 while coasting or braking
+    fn speed(&self) -> f32;
+    /// Run forward at a duty cycle from 0.0 to 1.0
+    fn forward(&mut self, duty: f32) -> Result<(), DcError> {
+        self.set_speed(duty.abs())
+    }
+    /// Run in reverse at a duty cycle from 0.0 to 1.0
+    fn reverse(&mut self, duty: f32) -> Result<(), DcError> {
+        self.set_speed(-duty.abs())
+    }
+    /// Disconnect the motor and let it spin down freely
+    fn coast(&mut self) -> Result<(), DcError>;
+    /// Short the motor windings, stopping it quickly and holding it against turning
+    fn brake(&mut self) -> Result<(), DcError>;
+}
+
+fn set_state<P: OutputPin>(
+    pin: &mut P,
+    name: &'static str,
+    state: PinState,
+) -> Result<(), DcError> {
+    pin.set_state(state).map_err(|err| DcError::Pin {
+        pin: name,
+        kind: digital::Error::kind(&err),
+    })
+}
+
+/// Set a duty cycle as a fraction from 0.0 to 1.0
+fn set_duty<P: SetDutyCycle>(pwm: &mut P, name: &'static str, duty: f32) -> Result<(), DcError> {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let duty = (f32::from(pwm.max_duty_cycle()) * duty.clamp(0.0, 1.0)).round() as u16;
+    pwm.set_duty_cycle(duty).map_err(|err| DcError::Pwm {
+        pin: name,
+        kind: pwm::Error::kind(&err),
+    })
+}
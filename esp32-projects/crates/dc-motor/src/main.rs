@@ -0,0 +1,50 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    gpio::PinDriver,
+    ledc::{config::TimerConfig, LedcDriver, LedcTimerDriver},
+    prelude::Peripherals,
+    units::Hertz,
+};
+use log::info;
+
+use dc_motor::{
+    runner::{DcRunner, SoftStart},
+    HBridge,
+};
+
+fn main() -> Result<()> {
+    esp_idf_svc::sys::link_patches();
+    esp_idf_svc::log::EspLogger::initialize_default();
+
+    let peripherals = Peripherals::take().unwrap();
+    // above the audible range so the motor does not whine
+    let timer = LedcTimerDriver::new(
+        peripherals.ledc.timer0,
+        &TimerConfig::new().frequency(Hertz(20_000)),
+    )?;
+    let enable = LedcDriver::new(peripherals.ledc.channel0, &timer, peripherals.pins.gpio5)?;
+    let in1 = PinDriver::output(peripherals.pins.gpio6)?;
+    let in2 = PinDriver::output(peripherals.pins.gpio7)?;
+
+    let bridge = HBridge::new(enable, in1, in2)?;
+    let mut pump = DcRunner::new(bridge, 0.5)?
+        .with_soft_start(SoftStart::new(0.3, Duration::from_millis(500)));
+    info!("L298N setup on pins 5, 6 and 7");
+
+    let start = Instant::now();
+    loop {
+        let elapsed = start.elapsed();
+        // run the pump for 10s every 30s
+        if elapsed.as_secs() % 30 < 10 {
+            pump.set_target(0.8);
+        } else {
+            pump.stop();
+        }
+        if let Err(err) = pump.poll(elapsed) {
+            info!("error driving the pump: {err}");
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
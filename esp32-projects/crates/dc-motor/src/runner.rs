@@ -0,0 +1,321 @@
+//! Non-blocking speed ramping for a firmware main loop
+
+use core::time::Duration;
+
+use crate::{DcError, DcMotor};
+
+// === SoftStart ===
+
+/// Caps the duty cycle while a motor starts from standstill
+///
+/// A stalled DC motor only has its winding resistance to limit the current,
+/// so starting at full duty draws a large inrush current. The cap rises
+/// linearly from `limit` to full duty over `duration`, giving the motor time
+/// to build up speed and back EMF.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftStart {
+    limit: f32,
+    duration: Duration,
+}
+
+impl SoftStart {
+    /// Create a new `SoftStart`, `limit` is the duty cycle from 0.0 to 1.0 allowed at standstill
+    pub const fn new(limit: f32, duration: Duration) -> Self {
+        Self { limit, duration }
+    }
+
+    /// Returns the duty cycle allowed at standstill
+    pub const fn limit(&self) -> f32 {
+        self.limit
+    }
+
+    /// Returns how long it takes to lift the cap to full duty
+    pub const fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the duty cycle allowed `elapsed` after starting
+    pub fn cap(&self, elapsed: Duration) -> f32 {
+        if elapsed >= self.duration {
+            return 1.0;
+        }
+        let progress = elapsed.as_secs_f32() / self.duration.as_secs_f32();
+        (1.0 - self.limit)
+            .mul_add(progress, self.limit)
+            .clamp(0.0, 1.0)
+    }
+}
+
+// === DcRunner ===
+
+/// Ramps a `DcMotor` towards a target speed from a loop without blocking
+///
+/// `poll` is called as often as possible with the current time and moves the
+/// speed towards the target by at most the acceleration, so a reversal first
+/// slows down to a stop. The time can be any monotonic clock, e.g. time since boot.
+pub struct DcRunner<M: DcMotor> {
+    motor: M,
+    /// speed change per second, a full range change from 0 to 1 takes `1 / acceleration` s
+    acceleration: f32,
+    soft_start: Option<SoftStart>,
+    target: f32,
+    last_poll: Option<Duration>,
+    /// when the motor last left standstill
+    started: Option<Duration>,
+}
+
+impl<M: DcMotor> DcRunner<M> {
+    /// Create a new `DcRunner`, keeping the current speed of the motor as the target.
+    /// An infinite `acceleration` changes the speed at once, one that is not
+    /// positive fails with `DcError::InvalidAcceleration`.
+    pub fn new(motor: M, acceleration: f32) -> Result<Self, DcError> {
+        let target = motor.speed();
+        Ok(Self {
+            motor,
+            acceleration: check(acceleration)?,
+            soft_start: None,
+            target,
+            last_poll: None,
+            started: None,
+        })
+    }
+
+    /// Limit the duty cycle while starting from standstill
+    #[must_use]
+    pub const fn with_soft_start(mut self, soft_start: SoftStart) -> Self {
+        self.soft_start = Some(soft_start);
+        self
+    }
+
+    /// Set the speed to ramp to, from -1.0 to 1.0
+    pub fn set_target(&mut self, speed: f32) {
+        self.target = speed.clamp(-1.0, 1.0);
+    }
+
+    /// Returns the speed the motor ramps to
+    pub const fn target(&self) -> f32 {
+        self.target
+    }
+
+    /// Change the acceleration, takes effect from the next poll. Fails like `new`
+    /// for an acceleration that is not positive, keeping the old one.
+    pub fn set_acceleration(&mut self, acceleration: f32) -> Result<(), DcError> {
+        self.acceleration = check(acceleration)?;
+        Ok(())
+    }
+
+    /// Returns the acceleration, in speed change per second
+    pub const fn acceleration(&self) -> f32 {
+        self.acceleration
+    }
+
+    /// Ramp down to a stop, the motor coasts once it is reached
+    pub fn stop(&mut self) {
+        self.target = 0.0;
+    }
+
+    /// Brake the motor at once, dropping the target
+    pub fn brake(&mut self) -> Result<(), DcError> {
+        self.target = 0.0;
+        self.started = None;
+        self.motor.brake()
+    }
+
+    /// Returns true while the motor has not reached the target speed
+    pub fn is_running(&self) -> bool {
+        (self.motor.speed() - self.target).abs() > f32::EPSILON
+    }
+
+    /// Returns the current speed of the motor
+    pub fn speed(&self) -> f32 {
+        self.motor.speed()
+    }
+
+    /// Returns the driven motor
+    pub const fn motor(&self) -> &M {
+        &self.motor
+    }
+
+    /// Returns the driven motor mutably. Changing its speed directly makes
+    /// the runner ramp on from wherever it ends up.
+    pub fn motor_mut(&mut self) -> &mut M {
+        &mut self.motor
+    }
+
+    /// Stop using the runner and get the motor back
+    pub fn into_inner(self) -> M {
+        self.motor
+    }
+
+    /// Move the speed towards the target. Returns true while the target is not reached.
+    pub fn poll(&mut self, now: Duration) -> Result<bool, DcError> {
+        let elapsed = self
+            .last_poll
+            .map_or(Duration::ZERO, |last| now.saturating_sub(last));
+        self.last_poll = Some(now);
+
+        let speed = self.motor.speed();
+        if speed == 0.0 {
+            self.started = None;
+        }
+        if !self.is_running() {
+            return Ok(false);
+        }
+
+        let max_change = if self.acceleration.is_infinite() {
+            f32::INFINITY
+        } else {
+            self.acceleration * elapsed.as_secs_f32()
+        };
+        let mut next = speed + (self.target - speed).clamp(-max_change, max_change);
+        // never cross zero in one poll, a reversal restarts from standstill
+        if speed * next < 0.0 {
+            next = 0.0;
+        }
+
+        if next != 0.0 {
+            let started = *self.started.get_or_insert(now);
+            if let Some(soft_start) = self.soft_start {
+                let cap = soft_start.cap(now.saturating_sub(started));
+                next = next.clamp(-cap, cap);
+            }
+        }
+
+        self.motor.set_speed(next)?;
+        Ok(self.is_running())
+    }
+}
+
+/// Rejects accelerations the ramp can not use, NaN included
+fn check(acceleration: f32) -> Result<f32, DcError> {
+    if acceleration > 0.0 {
+        Ok(acceleration)
+    } else {
+        Err(DcError::InvalidAcceleration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps the speed last set, like a driver does
+    #[derive(Default)]
+    struct FakeMotor {
+        speed: f32,
+        brakes: u32,
+    }
+
+    impl DcMotor for FakeMotor {
+        fn set_speed(&mut self, speed: f32) -> Result<(), DcError> {
+            self.speed = speed.clamp(-1.0, 1.0);
+            Ok(())
+        }
+
+        fn speed(&self) -> f32 {
+            self.speed
+        }
+
+        fn coast(&mut self) -> Result<(), DcError> {
+            self.speed = 0.0;
+            Ok(())
+        }
+
+        fn brake(&mut self) -> Result<(), DcError> {
+            self.brakes += 1;
+            self.coast()
+        }
+    }
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{actual} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn soft_start_lifts_the_cap_linearly() {
+        let soft_start = SoftStart::new(0.2, ms(1000));
+        assert_near(soft_start.cap(Duration::ZERO), 0.2);
+        assert_near(soft_start.cap(ms(500)), 0.6);
+        assert_near(soft_start.cap(ms(1000)), 1.0);
+        assert_near(soft_start.cap(ms(5000)), 1.0);
+        assert_near(SoftStart::new(0.2, Duration::ZERO).cap(Duration::ZERO), 1.0);
+    }
+
+    #[test]
+    fn ramps_by_the_acceleration() {
+        let mut runner = DcRunner::new(FakeMotor::default(), 2.0).unwrap();
+        runner.set_target(1.0);
+        // the first poll only starts the clock
+        assert!(runner.poll(Duration::ZERO).unwrap());
+        assert_near(runner.speed(), 0.0);
+        for (poll, expected) in (1..=4).zip([0.2, 0.4, 0.6, 0.8]) {
+            assert!(runner.poll(ms(100 * poll)).unwrap());
+            assert_near(runner.speed(), expected);
+        }
+        assert!(!runner.poll(ms(500)).unwrap());
+        assert_near(runner.speed(), 1.0);
+
+        runner.stop();
+        assert!(runner.poll(ms(750)).unwrap());
+        assert_near(runner.speed(), 0.5);
+        assert!(!runner.poll(ms(1000)).unwrap());
+        assert_near(runner.speed(), 0.0);
+    }
+
+    #[test]
+    fn reverses_through_standstill() {
+        let mut runner = DcRunner::new(FakeMotor::default(), f32::INFINITY).unwrap();
+        runner.set_target(0.5);
+        assert!(!runner.poll(Duration::ZERO).unwrap());
+        runner.set_target(-0.5);
+        // a change of any size stops at zero first
+        assert!(runner.poll(ms(10)).unwrap());
+        assert_near(runner.speed(), 0.0);
+        assert!(!runner.poll(ms(20)).unwrap());
+        assert_near(runner.speed(), -0.5);
+    }
+
+    #[test]
+    fn caps_the_duty_while_starting() {
+        let soft_start = SoftStart::new(0.3, ms(1000));
+        let mut runner = DcRunner::new(FakeMotor::default(), f32::INFINITY)
+            .unwrap()
+            .with_soft_start(soft_start);
+        runner.set_target(-1.0);
+        assert!(runner.poll(ms(2000)).unwrap());
+        assert_near(runner.speed(), -0.3);
+        assert!(runner.poll(ms(2500)).unwrap());
+        assert_near(runner.speed(), -0.65);
+        assert!(!runner.poll(ms(3000)).unwrap());
+
+        // braking ends the start, the next one is capped again
+        runner.brake().unwrap();
+        assert_eq!(runner.motor().brakes, 1);
+        runner.set_target(1.0);
+        assert!(runner.poll(ms(3100)).unwrap());
+        assert_near(runner.speed(), 0.3);
+    }
+
+    #[test]
+    fn rejects_accelerations_that_are_not_positive() {
+        for acceleration in [0.0, -1.0, f32::NAN, f32::NEG_INFINITY] {
+            assert!(matches!(
+                DcRunner::new(FakeMotor::default(), acceleration),
+                Err(DcError::InvalidAcceleration)
+            ));
+        }
+        let mut runner = DcRunner::new(FakeMotor::default(), 2.0).unwrap();
+        assert_eq!(
+            runner.set_acceleration(-2.0),
+            Err(DcError::InvalidAcceleration)
+        );
+        assert_near(runner.acceleration(), 2.0);
+    }
+}
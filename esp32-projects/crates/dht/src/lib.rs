@@ -3,6 +3,7 @@ use embedded_hal::{
     delay::DelayNs,
     digital::{InputPin, OutputPin, PinState},
 };
+use sensor::Sensor;
 
 // === Reading ===
 
@@ -262,6 +263,17 @@ impl<HE, ID: InterruptControl, D: DelayNs, P: InputPin<Error = HE> + OutputPin<E
     }
 }
 
+impl<HE, ID: InterruptControl, D: DelayNs, P: InputPin<Error = HE> + OutputPin<Error = HE>> Sensor
+    for Dht11<HE, ID, D, P>
+{
+    type Reading = Reading;
+    type Error = DhtError<HE>;
+
+    fn read(&mut self) -> Result<Reading, DhtError<HE>> {
+        self.dht.read(Self::parse_data)
+    }
+}
+
 // === Dht22 ===
 
 /// A DHT22 sensor
@@ -300,3 +312,14 @@ impl<HE, ID: InterruptControl, D: DelayNs, P: InputPin<Error = HE> + OutputPin<E
         self.dht.read(Self::parse_data)
     }
 }
+
+impl<HE, ID: InterruptControl, D: DelayNs, P: InputPin<Error = HE> + OutputPin<Error = HE>> Sensor
+    for Dht22<HE, ID, D, P>
+{
+    type Reading = Reading;
+    type Error = DhtError<HE>;
+
+    fn read(&mut self) -> Result<Reading, DhtError<HE>> {
+        self.dht.read(Self::parse_data)
+    }
+}
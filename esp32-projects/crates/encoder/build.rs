@@ -0,0 +1,6 @@
+//! crate encoder
+
+use embuild::espidf;
+fn main() {
+    espidf::sysenv::output();
+}
@@ -0,0 +1,396 @@
+//! Comparing the commanded position of a stepper with the one measured by an encoder
+
+use core::fmt;
+
+use stepper::{profile::MotionProfile, StepError, StepperMotor};
+
+use crate::Encoder;
+
+// === ClosedLoopError ===
+
+/// A type detailing the ways a closed-loop move can fail
+#[derive(Debug)]
+pub enum ClosedLoopError<E> {
+    /// The motor fell behind by more than the stall limit or could not be corrected
+    Stalled { commanded: i32, measured: i32 },
+    /// Reading the encoder failed
+    Encoder(E),
+    /// Stepping the motor failed
+    Step(StepError),
+}
+
+impl<E> From<StepError> for ClosedLoopError<E> {
+    fn from(error: StepError) -> Self {
+        Self::Step(error)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for ClosedLoopError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stalled {
+                commanded,
+                measured,
+            } => write!(f, "Motor stalled at {measured}, commanded {commanded}"),
+            Self::Encoder(err) => write!(f, "Encoder error: {err:?}"),
+            Self::Step(err) => write!(f, "Step error: {err}"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for ClosedLoopError<E> {}
+
+// === ClosedLoop ===
+
+/// A stepper with an encoder watching that it actually got where it was sent
+///
+/// The position of the motor is what was commanded, the encoder tells where
+/// the shaft really is, both in motor steps. A small difference comes from
+/// skipped steps and is corrected by stepping the difference. A large one,
+/// above the stall limit, means the motor is blocked and is reported instead.
+pub struct ClosedLoop<M: StepperMotor, E: Encoder> {
+    motor: M,
+    encoder: E,
+    counts_per_step: f32,
+    tolerance: u32,
+    stall_limit: u32,
+    max_corrections: u8,
+}
+
+impl<M: StepperMotor, E: Encoder> ClosedLoop<M, E> {
+    /// Create a new `ClosedLoop`, `counts_per_step` is the ratio of encoder counts to motor steps.
+    /// Defaults to a tolerance of 2 steps, a stall limit of 50 steps and up to 3 corrections per move.
+    ///
+    /// Call `sync` once the motor is at a known position.
+    pub const fn new(motor: M, encoder: E, counts_per_step: f32) -> Self {
+        Self {
+            motor,
+            encoder,
+            counts_per_step,
+            tolerance: 2,
+            stall_limit: 50,
+            max_corrections: 3,
+        }
+    }
+
+    /// Set how many steps the measured position may be off without a correction
+    #[must_use]
+    pub const fn with_tolerance(mut self, steps: u32) -> Self {
+        self.tolerance = steps;
+        self
+    }
+
+    /// Set how many steps the measured position may be off before the motor counts as stalled
+    #[must_use]
+    pub const fn with_stall_limit(mut self, steps: u32) -> Self {
+        self.stall_limit = steps;
+        self
+    }
+
+    /// Set how many times a move is corrected before giving up with `Stalled`
+    #[must_use]
+    pub const fn with_max_corrections(mut self, corrections: u8) -> Self {
+        self.max_corrections = corrections;
+        self
+    }
+
+    /// Returns the number of steps the measured position may be off without a correction
+    pub const fn tolerance(&self) -> u32 {
+        self.tolerance
+    }
+
+    /// Returns the number of steps the measured position may be off before reporting a stall
+    pub const fn stall_limit(&self) -> u32 {
+        self.stall_limit
+    }
+
+    /// Make the encoder agree with the commanded position, e.g. after homing
+    pub fn sync(&mut self) -> Result<(), ClosedLoopError<E::Error>> {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        let counts = (self.motor.current_position() as f32 * self.counts_per_step).round() as i32;
+        self.encoder
+            .set_position(counts)
+            .map_err(ClosedLoopError::Encoder)
+    }
+
+    /// Returns the position measured by the encoder, in motor steps
+    pub fn measured_position(&mut self) -> Result<i32, ClosedLoopError<E::Error>> {
+        let counts = self.encoder.position().map_err(ClosedLoopError::Encoder)?;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        let steps = (counts as f32 / self.counts_per_step).round() as i32;
+        Ok(steps)
+    }
+
+    /// Returns how many steps the motor is behind the commanded position,
+    /// or `Stalled` if that is more than the stall limit
+    pub fn check(&mut self) -> Result<i32, ClosedLoopError<E::Error>> {
+        let commanded = self.motor.current_position();
+        let measured = self.measured_position()?;
+        let error = commanded.wrapping_sub(measured);
+        if error.unsigned_abs() > self.stall_limit {
+            return Err(ClosedLoopError::Stalled {
+                commanded,
+                measured,
+            });
+        }
+        Ok(error)
+    }
+
+    /// Step the difference to the commanded position if it is outside the tolerance
+    pub fn correct(&mut self, profile: &MotionProfile) -> Result<(), ClosedLoopError<E::Error>> {
+        let error = self.check()?;
+        if error.unsigned_abs() <= self.tolerance {
+            return Ok(());
+        }
+        let commanded = self.motor.current_position();
+        self.motor.set_position(commanded.wrapping_sub(error));
+        self.motor.move_to_profile(commanded, profile)?;
+        Ok(())
+    }
+
+    /// Move to an absolute position and correct any skipped steps, blocking until done.
+    /// Fails with `Stalled` if the motor is still off after the allowed corrections.
+    pub fn move_to(
+        &mut self,
+        target: i32,
+        profile: &MotionProfile,
+    ) -> Result<(), ClosedLoopError<E::Error>> {
+        self.motor.move_to_profile(target, profile)?;
+        for _ in 0..self.max_corrections {
+            self.correct(profile)?;
+        }
+        let error = self.check()?;
+        if error.unsigned_abs() > self.tolerance {
+            return Err(ClosedLoopError::Stalled {
+                commanded: self.motor.current_position(),
+                measured: self.motor.current_position().wrapping_sub(error),
+            });
+        }
+        Ok(())
+    }
+
+    /// Move by a signed number of steps, see `move_to`
+    pub fn move_by(
+        &mut self,
+        delta: i32,
+        profile: &MotionProfile,
+    ) -> Result<(), ClosedLoopError<E::Error>> {
+        self.move_to(self.motor.current_position().wrapping_add(delta), profile)
+    }
+
+    /// Returns the motor
+    pub const fn motor(&self) -> &M {
+        &self.motor
+    }
+
+    /// Returns the motor mutably, e.g. to drive it from a `StepperRunner` and `check` in between
+    pub fn motor_mut(&mut self) -> &mut M {
+        &mut self.motor
+    }
+
+    /// Returns the encoder mutably
+    pub fn encoder_mut(&mut self) -> &mut E {
+        &mut self.encoder
+    }
+
+    /// Stop using closed-loop control and get the motor and encoder back
+    pub fn into_inner(self) -> (M, E) {
+        (self.motor, self.encoder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::VecDeque, convert::Infallible, rc::Rc};
+
+    use stepper::{idle::IdlePolicy, stop::StopToken, Direction, SoftLimits};
+
+    use super::*;
+
+    const PROFILE: MotionProfile = MotionProfile::constant(1000.0);
+
+    /// A motor that loses the steps queued in `skips` on its next moves,
+    /// or all of them while `blocked`. Its shaft is what the encoder sees.
+    #[derive(Default)]
+    struct SlippingMotor {
+        position: i32,
+        shaft: Rc<Cell<i32>>,
+        skips: VecDeque<i32>,
+        blocked: bool,
+        moves: u32,
+        token: Option<StopToken>,
+        reverse: bool,
+    }
+
+    impl StepperMotor for SlippingMotor {
+        fn step(&mut self) -> Result<(), StepError> {
+            self.step_for(1, 0)
+        }
+
+        fn step_for(&mut self, steps: i32, delay: u32) -> Result<(), StepError> {
+            let delta = if self.reverse { -steps } else { steps };
+            self.move_by(delta, delay)
+        }
+
+        // slips the same whatever the speed
+        fn move_by(&mut self, delta: i32, _delay: u32) -> Result<(), StepError> {
+            self.move_by_profile(delta, &PROFILE)
+        }
+
+        fn move_by_profile(
+            &mut self,
+            delta: i32,
+            _profile: &MotionProfile,
+        ) -> Result<(), StepError> {
+            self.moves += 1;
+            self.position += delta;
+            let skipped = self.skips.pop_front().unwrap_or(0).min(delta.abs());
+            let moved = if self.blocked {
+                0
+            } else {
+                delta - skipped * delta.signum()
+            };
+            self.shaft.set(self.shaft.get() + moved);
+            Ok(())
+        }
+
+        fn current_position(&self) -> i32 {
+            self.position
+        }
+
+        fn set_position(&mut self, position: i32) {
+            self.position = position;
+        }
+
+        fn set_soft_limits(&mut self, _limits: Option<SoftLimits>) {}
+
+        fn soft_limits(&self) -> Option<SoftLimits> {
+            None
+        }
+
+        fn set_idle_policy(&mut self, _policy: IdlePolicy) {}
+
+        fn idle_policy(&self) -> IdlePolicy {
+            IdlePolicy::Hold
+        }
+
+        fn set_stop_token(&mut self, token: Option<StopToken>) {
+            self.token = token;
+        }
+
+        fn stop_token(&self) -> Option<&StopToken> {
+            self.token.as_ref()
+        }
+
+        fn set_direction(&mut self, dir: Direction) {
+            self.reverse = dir == Direction::Reverse;
+        }
+
+        fn stop(&mut self) -> Result<(), StepError> {
+            Ok(())
+        }
+    }
+
+    /// An encoder on the shaft of a `SlippingMotor`, with 4 counts per step
+    struct ShaftEncoder {
+        shaft: Rc<Cell<i32>>,
+        offset: i32,
+    }
+
+    impl Encoder for ShaftEncoder {
+        type Error = Infallible;
+
+        fn position(&mut self) -> Result<i32, Infallible> {
+            Ok(self.shaft.get() * 4 + self.offset)
+        }
+
+        fn set_position(&mut self, position: i32) -> Result<(), Infallible> {
+            self.offset = position - self.shaft.get() * 4;
+            Ok(())
+        }
+    }
+
+    fn closed_loop(skips: &[i32]) -> ClosedLoop<SlippingMotor, ShaftEncoder> {
+        let motor = SlippingMotor {
+            skips: skips.iter().copied().collect(),
+            ..SlippingMotor::default()
+        };
+        let encoder = ShaftEncoder {
+            shaft: Rc::clone(&motor.shaft),
+            offset: 0,
+        };
+        ClosedLoop::new(motor, encoder, 4.0)
+    }
+
+    #[test]
+    fn moves_without_corrections_when_no_step_is_lost() {
+        let mut closed_loop = closed_loop(&[]);
+        closed_loop.move_to(100, &PROFILE).unwrap();
+        closed_loop.move_by(-30, &PROFILE).unwrap();
+        assert_eq!(closed_loop.measured_position().unwrap(), 70);
+        assert_eq!(closed_loop.motor().moves, 2);
+    }
+
+    #[test]
+    fn tolerates_small_errors() {
+        let mut closed_loop = closed_loop(&[2]);
+        closed_loop.move_to(100, &PROFILE).unwrap();
+        assert_eq!(closed_loop.check().unwrap(), 2);
+        assert_eq!(closed_loop.motor().moves, 1);
+    }
+
+    #[test]
+    fn corrects_skipped_steps() {
+        let mut closed_loop = closed_loop(&[5]);
+        closed_loop.move_to(100, &PROFILE).unwrap();
+        assert_eq!(closed_loop.motor().current_position(), 100);
+        assert_eq!(closed_loop.measured_position().unwrap(), 100);
+        assert_eq!(closed_loop.motor().moves, 2);
+
+        // backwards as well
+        let mut closed_loop = self::closed_loop(&[8, 3]);
+        closed_loop.move_by(-100, &PROFILE).unwrap();
+        assert_eq!(closed_loop.measured_position().unwrap(), -100);
+        assert_eq!(closed_loop.motor().moves, 3);
+    }
+
+    #[test]
+    fn gives_up_after_the_allowed_corrections() {
+        let mut closed_loop = closed_loop(&[5; 4]).with_max_corrections(3);
+        let result = closed_loop.move_to(100, &PROFILE);
+        assert!(matches!(
+            result,
+            Err(ClosedLoopError::Stalled {
+                commanded: 100,
+                measured: 95
+            })
+        ));
+        assert_eq!(closed_loop.motor().moves, 4);
+    }
+
+    #[test]
+    fn reports_a_blocked_motor_as_stalled() {
+        let mut closed_loop = closed_loop(&[]);
+        closed_loop.motor_mut().blocked = true;
+        let result = closed_loop.move_to(100, &PROFILE);
+        assert!(matches!(
+            result,
+            Err(ClosedLoopError::Stalled {
+                commanded: 100,
+                measured: 0
+            })
+        ));
+        // no correction is tried past the stall limit
+        assert_eq!(closed_loop.motor().moves, 1);
+    }
+
+    #[test]
+    fn sync_makes_the_encoder_agree() {
+        let mut closed_loop = closed_loop(&[]);
+        closed_loop.motor_mut().set_position(250);
+        assert!(closed_loop.check().is_err());
+        closed_loop.sync().unwrap();
+        assert_eq!(closed_loop.encoder_mut().position().unwrap(), 1000);
+        assert_eq!(closed_loop.check().unwrap(), 0);
+    }
+}
@@ -0,0 +1,56 @@
+use embedded_hal::digital::{Error, ErrorKind, InputPin};
+
+use crate::{quadrature::Quadrature, Encoder};
+
+/// An encoder on two input pins, decoded by polling them
+///
+/// `poll` has to run at least once per edge, so this only keeps up with
+/// slow encoders, e.g. a knob or a low resolution disc on a geared motor.
+/// Faster ones need `InterruptEncoder` or `PcntEncoder`.
+pub struct GpioEncoder<A: InputPin, B: InputPin> {
+    a: A,
+    b: B,
+    quadrature: Quadrature,
+}
+
+impl<A: InputPin, B: InputPin> GpioEncoder<A, B> {
+    /// Create a new `GpioEncoder` at position 0
+    pub fn new(mut a: A, mut b: B) -> Result<Self, ErrorKind> {
+        let quadrature = Quadrature::new(
+            a.is_high().map_err(|err| err.kind())?,
+            b.is_high().map_err(|err| err.kind())?,
+        );
+        Ok(Self { a, b, quadrature })
+    }
+
+    /// Read the pins and count the edge since the last poll, returns the change in count
+    pub fn poll(&mut self) -> Result<i8, ErrorKind> {
+        let a = self.a.is_high().map_err(|err| err.kind())?;
+        let b = self.b.is_high().map_err(|err| err.kind())?;
+        Ok(self.quadrature.update(a, b))
+    }
+
+    /// Returns how many edges were missed because `poll` ran too rarely
+    pub const fn errors(&self) -> u32 {
+        self.quadrature.errors()
+    }
+
+    /// Stop using the encoder and get the pins back
+    pub fn into_inner(self) -> (A, B) {
+        (self.a, self.b)
+    }
+}
+
+impl<A: InputPin, B: InputPin> Encoder for GpioEncoder<A, B> {
+    type Error = ErrorKind;
+
+    fn position(&mut self) -> Result<i32, ErrorKind> {
+        self.poll()?;
+        Ok(self.quadrature.count())
+    }
+
+    fn set_position(&mut self, position: i32) -> Result<(), ErrorKind> {
+        self.quadrature.set_count(position);
+        Ok(())
+    }
+}
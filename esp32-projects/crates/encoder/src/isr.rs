@@ -0,0 +1,79 @@
+use core::convert::Infallible;
+use std::sync::Arc;
+
+use esp_idf_svc::{
+    hal::gpio::{Input, InputPin, InterruptType, PinDriver},
+    sys::{self, EspError},
+};
+
+use crate::{quadrature::AtomicQuadrature, Encoder};
+
+/// An encoder on two GPIOs, decoded in the interrupt handlers of both pins
+///
+/// Every edge of A and B raises an interrupt that reads both levels, so no
+/// edge is lost to a busy main loop. At several kHz of edges the interrupt
+/// load adds up, use a `PcntEncoder` for fast encoders.
+pub struct InterruptEncoder<'d, A: InputPin, B: InputPin> {
+    _a: PinDriver<'d, A, Input>,
+    _b: PinDriver<'d, B, Input>,
+    quadrature: Arc<AtomicQuadrature>,
+}
+
+impl<'d, A: InputPin, B: InputPin> InterruptEncoder<'d, A, B> {
+    /// Create a new `InterruptEncoder` at position 0, taking over the interrupts of both pins
+    pub fn new(
+        mut a: PinDriver<'d, A, Input>,
+        mut b: PinDriver<'d, B, Input>,
+    ) -> Result<Self, EspError> {
+        let (a_pin, b_pin) = (a.pin(), b.pin());
+        let quadrature = Arc::new(AtomicQuadrature::new(a.is_high(), b.is_high()));
+
+        let handler = move |quadrature: Arc<AtomicQuadrature>, own: i32| {
+            move || {
+                quadrature.update(level(a_pin), level(b_pin));
+                // the HAL disables the interrupt once it fired, arm it again for the next edge
+                // SAFETY: `own` is the pin this handler was subscribed on, and
+                // enabling its interrupt is IRAM safe
+                unsafe { sys::gpio_intr_enable(own) };
+            }
+        };
+        a.set_interrupt_type(InterruptType::AnyEdge)?;
+        b.set_interrupt_type(InterruptType::AnyEdge)?;
+        // SAFETY: the handlers only touch atomics and IRAM safe GPIO functions
+        unsafe {
+            a.subscribe(handler(quadrature.clone(), a_pin))?;
+            b.subscribe(handler(quadrature.clone(), b_pin))?;
+        }
+        a.enable_interrupt()?;
+        b.enable_interrupt()?;
+
+        Ok(Self {
+            _a: a,
+            _b: b,
+            quadrature,
+        })
+    }
+
+    /// Returns how many edges were missed, e.g. through bouncing contacts
+    pub fn errors(&self) -> u32 {
+        self.quadrature.errors()
+    }
+}
+
+impl<A: InputPin, B: InputPin> Encoder for InterruptEncoder<'_, A, B> {
+    type Error = Infallible;
+
+    fn position(&mut self) -> Result<i32, Infallible> {
+        Ok(self.quadrature.count())
+    }
+
+    fn set_position(&mut self, position: i32) -> Result<(), Infallible> {
+        self.quadrature.set_count(position);
+        Ok(())
+    }
+}
+
+fn level(pin: i32) -> bool {
+    // SAFETY: reading the level of a configured input has no side effects and is IRAM safe
+    unsafe { sys::gpio_get_level(pin) != 0 }
+}
@@ -0,0 +1,28 @@
+//! Incremental quadrature encoders and closed-loop control of steppers
+
+mod gpio;
+#[cfg(target_os = "espidf")]
+mod isr;
+#[cfg(target_os = "espidf")]
+mod pcnt;
+
+pub mod closed_loop;
+pub mod quadrature;
+
+pub use gpio::GpioEncoder;
+#[cfg(target_os = "espidf")]
+pub use isr::InterruptEncoder;
+#[cfg(target_os = "espidf")]
+pub use pcnt::PcntEncoder;
+
+// === Encoder ===
+
+/// Anything that tracks the position of a shaft in encoder counts
+pub trait Encoder {
+    type Error;
+
+    /// Returns the position in counts
+    fn position(&mut self) -> Result<i32, Self::Error>;
+    /// Overwrite the position without moving, e.g. to zero it at a known reference
+    fn set_position(&mut self, position: i32) -> Result<(), Self::Error>;
+}
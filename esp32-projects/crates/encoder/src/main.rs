@@ -0,0 +1,61 @@
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    delay::Delay,
+    gpio::{OutputPin, PinDriver},
+    prelude::Peripherals,
+};
+use log::{info, warn};
+
+use encoder::{
+    closed_loop::{ClosedLoop, ClosedLoopError},
+    PcntEncoder,
+};
+use motor_controller_uln2003::{profile::MotionProfile, ULN2003};
+
+/// A 600 pulses per revolution encoder on the output shaft, counting every edge
+const COUNTS_PER_REVOLUTION: f64 = 2400.0;
+
+fn main() -> Result<()> {
+    esp_idf_svc::sys::link_patches();
+    esp_idf_svc::log::EspLogger::initialize_default();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let motor = ULN2003::new(
+        PinDriver::output(peripherals.pins.gpio23.downgrade_output())?,
+        PinDriver::output(peripherals.pins.gpio22.downgrade_output())?,
+        PinDriver::output(peripherals.pins.gpio21.downgrade_output())?,
+        PinDriver::output(peripherals.pins.gpio20.downgrade_output())?,
+        Some(Delay::new(10000)), /* 10 ms */
+    );
+    let encoder = PcntEncoder::new(
+        peripherals.pcnt0,
+        peripherals.pins.gpio18,
+        peripherals.pins.gpio19,
+    )?;
+    info!("Encoder setup on pins 18 and 19");
+
+    #[allow(clippy::cast_possible_truncation)]
+    let counts_per_step =
+        (COUNTS_PER_REVOLUTION / motor.motor_config().steps_per_revolution()) as f32;
+    let mut axis = ClosedLoop::new(motor, encoder, counts_per_step).with_stall_limit(100);
+    axis.sync()?;
+
+    let profile = MotionProfile::trapezoidal(500.0, 1000.0);
+    loop {
+        for target in [2048, 0] {
+            match axis.move_to(target, &profile) {
+                Ok(()) => info!("reached {target}, measured {}", axis.measured_position()?),
+                Err(ClosedLoopError::Stalled {
+                    commanded,
+                    measured,
+                }) => {
+                    warn!("stalled at {measured} on the way to {commanded}, resyncing");
+                    axis.motor_mut().set_position(measured);
+                }
+                Err(err) => return Err(err.into()),
+            }
+            std::thread::sleep(std::time::Duration::from_secs(1));
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::sync::{
+    atomic::{AtomicI32, Ordering},
+    Arc,
+};
+
+use esp_idf_svc::{
+    hal::{
+        gpio::{AnyInputPin, InputPin},
+        pcnt::{
+            Pcnt, PcntChannel, PcntChannelConfig, PcntControlMode, PcntCountMode, PcntDriver,
+            PcntEvent, PcntEventType, PinIndex,
+        },
+        peripheral::Peripheral,
+    },
+    sys::EspError,
+};
+
+use crate::Encoder;
+
+/// The hardware counter wraps at these limits, adding to the overflow count
+const HIGH_LIMIT: i16 = 10_000;
+const LOW_LIMIT: i16 = -10_000;
+
+/// An encoder decoded by a pulse counter unit, without any CPU load
+///
+/// The PCNT peripheral counts every edge of both channels in hardware and
+/// filters out glitches shorter than about 10 µs. Its 16 bit counter is
+/// extended to 32 bits in an interrupt every 10000 counts.
+pub struct PcntEncoder<'d> {
+    unit: PcntDriver<'d>,
+    overflow: Arc<AtomicI32>,
+    offset: i32,
+}
+
+impl<'d> PcntEncoder<'d> {
+    /// Create a new `PcntEncoder` at position 0
+    pub fn new<PCNT: Pcnt>(
+        pcnt: impl Peripheral<P = PCNT> + 'd,
+        a: impl Peripheral<P = impl InputPin> + 'd,
+        b: impl Peripheral<P = impl InputPin> + 'd,
+    ) -> Result<Self, EspError> {
+        let mut unit = PcntDriver::new(
+            pcnt,
+            Some(a),
+            Some(b),
+            Option::<AnyInputPin>::None,
+            Option::<AnyInputPin>::None,
+        )?;
+        // each channel counts the edges of one signal, the level of the other gives the direction
+        unit.channel_config(
+            PcntChannel::Channel0,
+            PinIndex::Pin0,
+            PinIndex::Pin1,
+            &PcntChannelConfig {
+                lctrl_mode: PcntControlMode::Reverse,
+                hctrl_mode: PcntControlMode::Keep,
+                pos_mode: PcntCountMode::Decrement,
+                neg_mode: PcntCountMode::Increment,
+                counter_h_lim: HIGH_LIMIT,
+                counter_l_lim: LOW_LIMIT,
+            },
+        )?;
+        unit.channel_config(
+            PcntChannel::Channel1,
+            PinIndex::Pin1,
+            PinIndex::Pin0,
+            &PcntChannelConfig {
+                lctrl_mode: PcntControlMode::Reverse,
+                hctrl_mode: PcntControlMode::Keep,
+                pos_mode: PcntCountMode::Increment,
+                neg_mode: PcntCountMode::Decrement,
+                counter_h_lim: HIGH_LIMIT,
+                counter_l_lim: LOW_LIMIT,
+            },
+        )?;
+        // in APB clock cycles of 12.5 ns
+        unit.set_filter_value(800)?;
+        unit.filter_enable()?;
+
+        let overflow = Arc::new(AtomicI32::new(0));
+        let counted = overflow.clone();
+        // SAFETY: the handler only touches an atomic
+        unsafe {
+            unit.subscribe(move |status| {
+                let status = PcntEventType::from_repr_truncated(status);
+                if status.contains(PcntEvent::HighLimit) {
+                    counted.fetch_add(i32::from(HIGH_LIMIT), Ordering::SeqCst);
+                }
+                if status.contains(PcntEvent::LowLimit) {
+                    counted.fetch_add(i32::from(LOW_LIMIT), Ordering::SeqCst);
+                }
+            })?;
+        }
+        unit.event_enable(PcntEvent::HighLimit)?;
+        unit.event_enable(PcntEvent::LowLimit)?;
+        unit.counter_pause()?;
+        unit.counter_clear()?;
+        unit.counter_resume()?;
+
+        Ok(Self {
+            unit,
+            overflow,
+            offset: 0,
+        })
+    }
+
+    fn raw(&self) -> Result<i32, EspError> {
+        let counter = self.unit.get_counter_value()?;
+        Ok(self
+            .overflow
+            .load(Ordering::SeqCst)
+            .wrapping_add(i32::from(counter)))
+    }
+}
+
+impl Encoder for PcntEncoder<'_> {
+    type Error = EspError;
+
+    fn position(&mut self) -> Result<i32, EspError> {
+        Ok(self.raw()?.wrapping_sub(self.offset))
+    }
+
+    fn set_position(&mut self, position: i32) -> Result<(), EspError> {
+        self.offset = self.raw()?.wrapping_sub(position);
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+//! Decoding the two phase shifted signals of an incremental encoder
+//!
+//! The A and B channels form a Gray code, only one of them changes per edge.
+//! With A leading B the code runs 00, 10, 11, 01 and counts up, the other
+//! way round it counts down. Every edge of both channels is counted, so an
+//! encoder with `n` pulses per revolution gives `4 * n` counts.
+
+use core::sync::atomic::{AtomicI32, AtomicU32, AtomicU8, Ordering};
+
+/// Change in count from the previous to the current state, indexed by `previous << 2 | current`.
+/// `None` where both channels changed at once, meaning an edge was missed.
+const TRANSITIONS: [Option<i8>; 16] = [
+    Some(0),
+    Some(-1),
+    Some(1),
+    None,
+    Some(1),
+    Some(0),
+    None,
+    Some(-1),
+    Some(-1),
+    None,
+    Some(0),
+    Some(1),
+    None,
+    Some(1),
+    Some(-1),
+    Some(0),
+];
+
+const fn state(a: bool, b: bool) -> u8 {
+    match (a, b) {
+        (false, false) => 0b00,
+        (false, true) => 0b01,
+        (true, false) => 0b10,
+        (true, true) => 0b11,
+    }
+}
+
+/// Returns the change in count, `None` for an invalid transition
+const fn decode(previous: u8, current: u8) -> Option<i8> {
+    TRANSITIONS[((previous << 2) | current) as usize]
+}
+
+// === Quadrature ===
+
+/// Counts the edges of a quadrature signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quadrature {
+    state: u8,
+    count: i32,
+    errors: u32,
+}
+
+impl Quadrature {
+    /// Create a new `Quadrature` at count 0, starting from the current levels of A and B
+    pub const fn new(a: bool, b: bool) -> Self {
+        Self {
+            state: state(a, b),
+            count: 0,
+            errors: 0,
+        }
+    }
+
+    /// Feed the current levels of A and B, returns the change in count.
+    /// If both changed since the last update an edge was missed, the count
+    /// is kept and the error counter goes up instead.
+    pub fn update(&mut self, a: bool, b: bool) -> i8 {
+        let current = state(a, b);
+        let previous = core::mem::replace(&mut self.state, current);
+        let Some(delta) = decode(previous, current) else {
+            self.errors = self.errors.wrapping_add(1);
+            return 0;
+        };
+        self.count = self.count.wrapping_add(i32::from(delta));
+        delta
+    }
+
+    /// Returns the counted edges
+    pub const fn count(&self) -> i32 {
+        self.count
+    }
+
+    /// Overwrite the count, e.g. to zero it at a reference mark
+    pub fn set_count(&mut self, count: i32) {
+        self.count = count;
+    }
+
+    /// Returns how many times both channels changed at once
+    pub const fn errors(&self) -> u32 {
+        self.errors
+    }
+}
+
+// === AtomicQuadrature ===
+
+/// A `Quadrature` that can be shared with an interrupt handler
+///
+/// Only one context may call `update`, e.g. the interrupt handlers of both
+/// pins on a single core, while any number of them read the count.
+#[derive(Debug)]
+pub struct AtomicQuadrature {
+    state: AtomicU8,
+    count: AtomicI32,
+    errors: AtomicU32,
+}
+
+impl AtomicQuadrature {
+    /// Create a new `AtomicQuadrature` at count 0, starting from the current levels of A and B
+    pub const fn new(a: bool, b: bool) -> Self {
+        Self {
+            state: AtomicU8::new(state(a, b)),
+            count: AtomicI32::new(0),
+            errors: AtomicU32::new(0),
+        }
+    }
+
+    /// Feed the current levels of A and B, see `Quadrature::update`
+    pub fn update(&self, a: bool, b: bool) -> i8 {
+        let current = state(a, b);
+        let previous = self.state.swap(current, Ordering::Relaxed);
+        let Some(delta) = decode(previous, current) else {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+            return 0;
+        };
+        self.count.fetch_add(i32::from(delta), Ordering::Relaxed);
+        delta
+    }
+
+    /// Returns the counted edges
+    pub fn count(&self) -> i32 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Overwrite the count, e.g. to zero it at a reference mark
+    pub fn set_count(&self, count: i32) {
+        self.count.store(count, Ordering::Relaxed);
+    }
+
+    /// Returns how many times both channels changed at once
+    pub fn errors(&self) -> u32 {
+        self.errors.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A and B over one cycle with A leading, i.e. forward
+    const FORWARD: [(bool, bool); 4] = [(true, false), (true, true), (false, true), (false, false)];
+
+    fn feed(quadrature: &mut Quadrature, levels: impl IntoIterator<Item = (bool, bool)>) -> i32 {
+        levels
+            .into_iter()
+            .map(|(a, b)| i32::from(quadrature.update(a, b)))
+            .sum()
+    }
+
+    #[test]
+    fn counts_every_edge_forward() {
+        let mut quadrature = Quadrature::new(false, false);
+        assert_eq!(feed(&mut quadrature, FORWARD), 4);
+        assert_eq!(feed(&mut quadrature, FORWARD.repeat(9)), 36);
+        assert_eq!(quadrature.count(), 40);
+        assert_eq!(quadrature.errors(), 0);
+    }
+
+    #[test]
+    fn counts_every_edge_in_reverse() {
+        let mut quadrature = Quadrature::new(false, false);
+        let reverse = FORWARD
+            .iter()
+            .rev()
+            .skip(1)
+            .chain([&(false, false)])
+            .copied();
+        assert_eq!(feed(&mut quadrature, reverse.clone()), -4);
+        assert_eq!(feed(&mut quadrature, reverse), -4);
+        assert_eq!(quadrature.count(), -8);
+        assert_eq!(quadrature.errors(), 0);
+    }
+
+    #[test]
+    fn turning_back_undoes_the_count() {
+        let mut quadrature = Quadrature::new(false, false);
+        feed(&mut quadrature, [(true, false), (true, true)]);
+        feed(
+            &mut quadrature,
+            [(true, false), (false, false), (false, true)],
+        );
+        assert_eq!(quadrature.count(), -1);
+    }
+
+    #[test]
+    fn unchanged_levels_do_not_count() {
+        let mut quadrature = Quadrature::new(true, true);
+        assert_eq!(feed(&mut quadrature, [(true, true); 5]), 0);
+        assert_eq!((quadrature.count(), quadrature.errors()), (0, 0));
+    }
+
+    #[test]
+    fn missed_edges_are_errors() {
+        let mut quadrature = Quadrature::new(false, false);
+        // both channels changing at once, the direction is unknown
+        assert_eq!(quadrature.update(true, true), 0);
+        assert_eq!(quadrature.update(false, false), 0);
+        assert_eq!((quadrature.count(), quadrature.errors()), (0, 2));
+        // counting goes on from the new state
+        assert_eq!(quadrature.update(true, false), 1);
+        assert_eq!((quadrature.count(), quadrature.errors()), (1, 2));
+    }
+
+    #[test]
+    fn set_count_keeps_the_state() {
+        let mut quadrature = Quadrature::new(false, false);
+        feed(&mut quadrature, FORWARD);
+        quadrature.set_count(-100);
+        assert_eq!(quadrature.update(true, false), 1);
+        assert_eq!(quadrature.count(), -99);
+    }
+
+    #[test]
+    fn atomic_quadrature_counts_the_same() {
+        let atomic = AtomicQuadrature::new(false, false);
+        let mut quadrature = Quadrature::new(false, false);
+        let levels = [
+            (true, false),
+            (true, true),
+            (true, false),
+            (false, true),
+            (false, false),
+            (false, true),
+        ];
+        for (a, b) in levels {
+            assert_eq!(atomic.update(a, b), quadrature.update(a, b));
+        }
+        assert_eq!(atomic.count(), quadrature.count());
+        assert_eq!(atomic.errors(), quadrature.errors());
+        atomic.set_count(7);
+        assert_eq!(atomic.count(), 7);
+    }
+}
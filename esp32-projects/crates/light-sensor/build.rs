@@ -0,0 +1,6 @@
+//! crate light-sensor
+
+use embuild::espidf;
+fn main() {
+    espidf::sysenv::output();
+}
@@ -0,0 +1,280 @@
+use embedded_hal::{delay::DelayNs, i2c::I2c};
+use sensor::Sensor;
+
+use crate::{LightError, Reading};
+
+const POWER_ON: u8 = 0x01;
+const ONE_TIME_H_RES: u8 = 0x20;
+const ONE_TIME_H_RES2: u8 = 0x21;
+
+/// Measurement time register range, 69 being the datasheet default
+const MTREG_MIN: u8 = 31;
+const MTREG_DEFAULT: u8 = 69;
+const MTREG_MAX: u8 = 254;
+
+/// Worst case conversion time at the default measurement time
+const CONVERSION_MS: u32 = 180;
+
+/// Counts above this are close to saturation so the measurement time is shortened
+const HIGH_COUNTS: u16 = 60_000;
+/// Counts below this give poor resolution so the measurement time is lengthened
+const LOW_COUNTS: u16 = 1_000;
+/// Doubling or halving `MTreg` crosses its whole range in four steps
+const MAX_RANGING_STEPS: u8 = 4;
+
+/// I2C address of the sensor, selected by the level of the ADDR pin
+#[derive(Copy, Clone, Debug)]
+pub enum Address {
+    Low,
+    High,
+}
+
+impl Address {
+    const fn bits(self) -> u8 {
+        match self {
+            Self::Low => 0x23,
+            Self::High => 0x5c,
+        }
+    }
+}
+
+/// A BH1750 ambient light sensor
+///
+/// Uses one-time measurements so the sensor powers down between reads. With
+/// auto-ranging enabled the measurement time register (`MTreg`) is adjusted
+/// until the count lands in a usable range, which extends the range to
+/// roughly 0.1 lx - 120 klx.
+pub struct Bh1750<I2C, D> {
+    i2c: I2C,
+    delay: D,
+    address: Address,
+    mtreg: u8,
+    auto_range: bool,
+}
+
+impl<I2C: I2c, D: DelayNs> Bh1750<I2C, D> {
+    /// Create a new `Bh1750` with auto-ranging enabled
+    pub const fn new(i2c: I2C, delay: D, address: Address) -> Self {
+        Self {
+            i2c,
+            delay,
+            address,
+            mtreg: MTREG_DEFAULT,
+            auto_range: true,
+        }
+    }
+
+    /// Enable or disable auto-ranging. When disabled the current measurement time is kept.
+    pub fn set_auto_range(&mut self, auto_range: bool) {
+        self.auto_range = auto_range;
+    }
+
+    /// Set the measurement time register, clamped to the 31..=254 range the sensor supports
+    pub fn set_measurement_time(&mut self, mtreg: u8) {
+        self.mtreg = mtreg.clamp(MTREG_MIN, MTREG_MAX);
+    }
+
+    /// Returns the current measurement time register
+    pub const fn measurement_time(&self) -> u8 {
+        self.mtreg
+    }
+
+    /// Longer measurement times use the 0.5 lx resolution mode to make use of the extra counts
+    const fn high_resolution2(&self) -> bool {
+        self.mtreg > MTREG_DEFAULT
+    }
+
+    fn measure(&mut self) -> Result<u16, LightError<I2C::Error>> {
+        let mode = if self.high_resolution2() {
+            ONE_TIME_H_RES2
+        } else {
+            ONE_TIME_H_RES
+        };
+
+        let address = self.address.bits();
+        self.i2c.write(address, &[POWER_ON])?;
+        // MTreg is written in two halves: 01000_[7:5] and 011_[4:0]
+        self.i2c
+            .write(address, &[0b0100_0000 | (self.mtreg >> 5)])?;
+        self.i2c
+            .write(address, &[0b0110_0000 | (self.mtreg & 0b1_1111)])?;
+        self.i2c.write(address, &[mode])?;
+
+        self.delay
+            .delay_ms(CONVERSION_MS * u32::from(self.mtreg) / u32::from(MTREG_DEFAULT) + 1);
+
+        let mut buf = [0; 2];
+        self.i2c.read(address, &mut buf)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    fn to_lux(&self, raw: u16) -> f32 {
+        let mut lux = f32::from(raw) / 1.2 * f32::from(MTREG_DEFAULT) / f32::from(self.mtreg);
+        if self.high_resolution2() {
+            lux /= 2.0;
+        }
+        lux
+    }
+
+    /// Returns the measurement time to retry with, if the count is out of range
+    fn next_mtreg(&self, raw: u16) -> Option<u8> {
+        if raw >= HIGH_COUNTS && self.mtreg > MTREG_MIN {
+            Some((self.mtreg / 2).max(MTREG_MIN))
+        } else if raw < LOW_COUNTS && self.mtreg < MTREG_MAX {
+            Some(self.mtreg.saturating_mul(2).min(MTREG_MAX))
+        } else {
+            None
+        }
+    }
+}
+
+impl<I2C: I2c, D: DelayNs> Sensor for Bh1750<I2C, D> {
+    type Reading = Reading;
+    type Error = LightError<I2C::Error>;
+
+    fn read(&mut self) -> Result<Reading, LightError<I2C::Error>> {
+        let mut raw = self.measure()?;
+        if self.auto_range {
+            for _ in 0..MAX_RANGING_STEPS {
+                let Some(mtreg) = self.next_mtreg(raw) else {
+                    break;
+                };
+                self.mtreg = mtreg;
+                raw = self.measure()?;
+            }
+        }
+
+        if raw == u16::MAX && self.mtreg == MTREG_MIN {
+            return Err(LightError::Saturated);
+        }
+        Ok(Reading {
+            lux: self.to_lux(raw),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+    use embedded_hal::i2c::{ErrorType, Operation};
+
+    use super::*;
+
+    /// A BH1750 under a fixed illuminance, counting as the datasheet says
+    struct FakeSensor {
+        lux: f32,
+        mtreg: u8,
+        mode: u8,
+        measurements: u32,
+    }
+
+    impl FakeSensor {
+        const fn new(lux: f32) -> Self {
+            Self {
+                lux,
+                mtreg: MTREG_DEFAULT,
+                mode: 0,
+                measurements: 0,
+            }
+        }
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        fn counts(&self) -> u16 {
+            let resolution = if self.mode == ONE_TIME_H_RES2 {
+                2.0
+            } else {
+                1.0
+            };
+            let counts = self.lux * 1.2 * resolution * f32::from(self.mtreg) / 69.0;
+            // the float to int cast saturates at the full scale
+            counts.round() as u16
+        }
+    }
+
+    impl ErrorType for FakeSensor {
+        type Error = Infallible;
+    }
+
+    impl I2c for FakeSensor {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Infallible> {
+            assert_eq!(address, 0x23);
+            for operation in operations {
+                match operation {
+                    Operation::Write(&[byte]) => match byte >> 5 {
+                        0b010 => self.mtreg = (self.mtreg & 0b1_1111) | ((byte & 0b111) << 5),
+                        0b011 => self.mtreg = (self.mtreg & !0b1_1111) | (byte & 0b1_1111),
+                        _ if byte == POWER_ON => {}
+                        _ => {
+                            self.mode = byte;
+                            self.measurements += 1;
+                        }
+                    },
+                    Operation::Write(bytes) => panic!("unexpected write {bytes:?}"),
+                    Operation::Read(buf) => buf.copy_from_slice(&self.counts().to_be_bytes()),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn read(lux: f32, auto_range: bool) -> (Bh1750<FakeSensor, NoDelay>, Result<f32, ()>) {
+        let mut sensor = Bh1750::new(FakeSensor::new(lux), NoDelay, Address::Low);
+        sensor.set_auto_range(auto_range);
+        let reading = sensor.read().map(|reading| reading.lux()).map_err(|_| ());
+        (sensor, reading)
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() <= expected * 0.01,
+            "{actual} lx is not {expected} lx"
+        );
+    }
+
+    #[test]
+    fn converts_counts_to_lux() {
+        let (sensor, lux) = read(500.0, false);
+        assert_near(lux.unwrap(), 500.0);
+        assert_eq!(sensor.i2c.mode, ONE_TIME_H_RES);
+        assert_eq!(sensor.i2c.measurements, 1);
+    }
+
+    #[test]
+    fn lengthens_the_measurement_time_in_the_dark() {
+        let (sensor, lux) = read(10.0, true);
+        assert_near(lux.unwrap(), 10.0);
+        // 69, 138 and then the maximum, in the 0.5 lx mode
+        assert_eq!(sensor.measurement_time(), MTREG_MAX);
+        assert_eq!(sensor.i2c.mtreg, MTREG_MAX);
+        assert_eq!(sensor.i2c.mode, ONE_TIME_H_RES2);
+        assert_eq!(sensor.i2c.measurements, 3);
+    }
+
+    #[test]
+    fn shortens_the_measurement_time_in_bright_light() {
+        let (sensor, lux) = read(80_000.0, true);
+        assert_near(lux.unwrap(), 80_000.0);
+        assert_eq!(sensor.measurement_time(), 34);
+        assert_eq!(sensor.i2c.measurements, 2);
+    }
+
+    #[test]
+    fn reports_saturation() {
+        let (sensor, lux) = read(200_000.0, true);
+        assert_eq!(lux, Err(()));
+        assert_eq!(sensor.measurement_time(), MTREG_MIN);
+        // without auto-ranging a full count is just a reading
+        let (_, lux) = read(200_000.0, false);
+        assert!(lux.is_ok());
+    }
+}
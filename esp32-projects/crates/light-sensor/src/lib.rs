@@ -0,0 +1,56 @@
+use core::fmt;
+
+mod bh1750;
+mod veml7700;
+
+pub use bh1750::{Address, Bh1750};
+pub use veml7700::{Gain, IntegrationTime, Veml7700};
+
+// === Reading ===
+
+/// A sensor reading
+#[derive(Debug, Clone, Copy)]
+pub struct Reading {
+    lux: f32,
+}
+
+impl Reading {
+    /// Returns the ambient illuminance, in lux
+    pub const fn lux(&self) -> f32 {
+        self.lux
+    }
+}
+
+impl fmt::Display for Reading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Illuminance: {} lx", self.lux)
+    }
+}
+
+// === LightError ===
+
+/// A type detailing various errors the light sensors can return
+#[derive(Debug, Clone)]
+pub enum LightError<E> {
+    /// The sensor is at full scale even in its least sensitive range
+    Saturated,
+    /// Received a low-level error from the HAL while talking to the sensor
+    I2cError(E),
+}
+
+impl<E> From<E> for LightError<E> {
+    fn from(error: E) -> Self {
+        Self::I2cError(error)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for LightError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Saturated => f.write_str("Sensor saturated, too bright to measure"),
+            Self::I2cError(err) => write!(f, "HAL i2c error: {:?}", err),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for LightError<E> {}
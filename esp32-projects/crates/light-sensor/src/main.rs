@@ -0,0 +1,38 @@
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    delay::Delay,
+    i2c::{I2cConfig, I2cDriver},
+    prelude::*,
+};
+use log::info;
+
+use light_sensor::{Address, Bh1750};
+use sensor::Sensor;
+
+fn main() -> Result<()> {
+    esp_idf_svc::sys::link_patches();
+    esp_idf_svc::log::EspLogger::initialize_default();
+
+    let peripherals = Peripherals::take().unwrap();
+    let i2c = I2cDriver::new(
+        peripherals.i2c0,
+        peripherals.pins.gpio6,
+        peripherals.pins.gpio7,
+        &I2cConfig::new().baudrate(100.kHz().into()),
+    )?;
+
+    let mut light = Bh1750::new(i2c, Delay::new_default(), Address::Low);
+    info!("BH1750 setup on sda 6, scl 7");
+
+    loop {
+        match light.read() {
+            Ok(res) => {
+                info!("BH1750 read: {res}");
+            }
+            Err(err) => {
+                info!("error during read: {}", err);
+            }
+        };
+        std::thread::sleep(std::time::Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,396 @@
+use embedded_hal::{delay::DelayNs, i2c::I2c};
+use sensor::Sensor;
+
+use crate::{LightError, Reading};
+
+const ADDRESS: u8 = 0x10;
+
+const REG_ALS_CONF: u8 = 0x00;
+const REG_ALS: u8 = 0x04;
+
+/// Counts at or below this give poor resolution so the sensor is made more sensitive
+const LOW_COUNTS: u16 = 100;
+/// Counts above this are outside the linear range so the sensor is made less sensitive
+const HIGH_COUNTS: u16 = 10_000;
+/// Enough steps to walk from the most to the least sensitive setting
+const MAX_RANGING_STEPS: u8 = 10;
+
+/// Resolution in lx/count at gain x1 and 1 ms integration time,
+/// the datasheet gives 0.0036 lx/count at x2 and 800 ms
+const LUX_PER_COUNT: f32 = 5.76;
+
+// === Gain ===
+
+/// ALS gain setting, ordered from least to most sensitive
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Gain {
+    OneEighth,
+    OneQuarter,
+    One,
+    Two,
+}
+
+impl Gain {
+    const fn bits(self) -> u16 {
+        match self {
+            Self::One => 0b00,
+            Self::Two => 0b01,
+            Self::OneEighth => 0b10,
+            Self::OneQuarter => 0b11,
+        }
+    }
+
+    const fn factor(self) -> f32 {
+        match self {
+            Self::OneEighth => 0.125,
+            Self::OneQuarter => 0.25,
+            Self::One => 1.0,
+            Self::Two => 2.0,
+        }
+    }
+
+    const fn higher(self) -> Option<Self> {
+        match self {
+            Self::OneEighth => Some(Self::OneQuarter),
+            Self::OneQuarter => Some(Self::One),
+            Self::One => Some(Self::Two),
+            Self::Two => None,
+        }
+    }
+
+    const fn lower(self) -> Option<Self> {
+        match self {
+            Self::OneEighth => None,
+            Self::OneQuarter => Some(Self::OneEighth),
+            Self::One => Some(Self::OneQuarter),
+            Self::Two => Some(Self::One),
+        }
+    }
+}
+
+// === IntegrationTime ===
+
+/// ALS integration time, ordered from shortest to longest
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IntegrationTime {
+    Ms25,
+    Ms50,
+    Ms100,
+    Ms200,
+    Ms400,
+    Ms800,
+}
+
+impl IntegrationTime {
+    const fn bits(self) -> u16 {
+        match self {
+            Self::Ms25 => 0b1100,
+            Self::Ms50 => 0b1000,
+            Self::Ms100 => 0b0000,
+            Self::Ms200 => 0b0001,
+            Self::Ms400 => 0b0010,
+            Self::Ms800 => 0b0011,
+        }
+    }
+
+    /// Returns the integration time in milliseconds
+    pub const fn millis(self) -> u32 {
+        match self {
+            Self::Ms25 => 25,
+            Self::Ms50 => 50,
+            Self::Ms100 => 100,
+            Self::Ms200 => 200,
+            Self::Ms400 => 400,
+            Self::Ms800 => 800,
+        }
+    }
+
+    const fn longer(self) -> Option<Self> {
+        match self {
+            Self::Ms25 => Some(Self::Ms50),
+            Self::Ms50 => Some(Self::Ms100),
+            Self::Ms100 => Some(Self::Ms200),
+            Self::Ms200 => Some(Self::Ms400),
+            Self::Ms400 => Some(Self::Ms800),
+            Self::Ms800 => None,
+        }
+    }
+
+    const fn shorter(self) -> Option<Self> {
+        match self {
+            Self::Ms25 => None,
+            Self::Ms50 => Some(Self::Ms25),
+            Self::Ms100 => Some(Self::Ms50),
+            Self::Ms200 => Some(Self::Ms100),
+            Self::Ms400 => Some(Self::Ms200),
+            Self::Ms800 => Some(Self::Ms400),
+        }
+    }
+}
+
+// === Veml7700 ===
+
+/// A VEML7700 ambient light sensor
+///
+/// Auto-ranging follows the Vishay application note: start at gain x1/8 and
+/// 100 ms, raise the gain and then the integration time while the count is
+/// too low, and shorten the integration time while it is too high.
+pub struct Veml7700<I2C, D> {
+    i2c: I2C,
+    delay: D,
+    gain: Gain,
+    integration_time: IntegrationTime,
+    auto_range: bool,
+    configured: bool,
+}
+
+impl<I2C: I2c, D: DelayNs> Veml7700<I2C, D> {
+    /// Create a new `Veml7700` with auto-ranging enabled
+    pub const fn new(i2c: I2C, delay: D) -> Self {
+        Self {
+            i2c,
+            delay,
+            gain: Gain::OneEighth,
+            integration_time: IntegrationTime::Ms100,
+            auto_range: true,
+            configured: false,
+        }
+    }
+
+    /// Enable or disable auto-ranging. When disabled the current gain and integration time are kept.
+    pub fn set_auto_range(&mut self, auto_range: bool) {
+        self.auto_range = auto_range;
+    }
+
+    /// Set the gain and integration time used for the next read
+    pub fn set_range(&mut self, gain: Gain, integration_time: IntegrationTime) {
+        self.gain = gain;
+        self.integration_time = integration_time;
+        self.configured = false;
+    }
+
+    /// Returns the current gain and integration time
+    pub const fn range(&self) -> (Gain, IntegrationTime) {
+        (self.gain, self.integration_time)
+    }
+
+    fn configure(&mut self) -> Result<(), LightError<I2C::Error>> {
+        // persistence and interrupts are left at 0, clearing ALS_SD powers the sensor on
+        let conf = (self.gain.bits() << 11) | (self.integration_time.bits() << 6);
+        let [lo, hi] = conf.to_le_bytes();
+        self.i2c.write(ADDRESS, &[REG_ALS_CONF, lo, hi])?;
+        self.configured = true;
+
+        // wait for a full conversion with the new settings, one may already be in flight
+        self.delay.delay_ms(2 * self.integration_time.millis() + 5);
+        Ok(())
+    }
+
+    fn read_counts(&mut self) -> Result<u16, LightError<I2C::Error>> {
+        let mut buf = [0; 2];
+        self.i2c.write_read(ADDRESS, &[REG_ALS], &mut buf)?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    /// Moves one step towards a better range, returns false if the count is fine or no step is left
+    fn adjust_range(&mut self, raw: u16) -> bool {
+        if raw <= LOW_COUNTS {
+            if let Some(gain) = self.gain.higher() {
+                self.gain = gain;
+                return true;
+            }
+            if let Some(integration_time) = self.integration_time.longer() {
+                self.integration_time = integration_time;
+                return true;
+            }
+        } else if raw > HIGH_COUNTS {
+            if let Some(integration_time) = self.integration_time.shorter() {
+                self.integration_time = integration_time;
+                return true;
+            }
+            if let Some(gain) = self.gain.lower() {
+                self.gain = gain;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn to_lux(&self, raw: u16) -> f32 {
+        #[allow(clippy::cast_precision_loss)]
+        let resolution =
+            LUX_PER_COUNT / (self.integration_time.millis() as f32 * self.gain.factor());
+        let lux = f32::from(raw) * resolution;
+        if lux > 1000.0 {
+            // non-linearity correction from the application note
+            6.0135e-13_f32
+                .mul_add(lux, -9.3924e-9)
+                .mul_add(lux, 8.1488e-5)
+                .mul_add(lux, 1.0023)
+                * lux
+        } else {
+            lux
+        }
+    }
+}
+
+impl<I2C: I2c, D: DelayNs> Sensor for Veml7700<I2C, D> {
+    type Reading = Reading;
+    type Error = LightError<I2C::Error>;
+
+    fn read(&mut self) -> Result<Reading, LightError<I2C::Error>> {
+        if !self.configured {
+            self.configure()?;
+        }
+
+        let mut raw = self.read_counts()?;
+        if self.auto_range {
+            for _ in 0..MAX_RANGING_STEPS {
+                if !self.adjust_range(raw) {
+                    break;
+                }
+                self.configure()?;
+                raw = self.read_counts()?;
+            }
+        }
+
+        if raw == u16::MAX
+            && self.gain == Gain::OneEighth
+            && self.integration_time == IntegrationTime::Ms25
+        {
+            return Err(LightError::Saturated);
+        }
+        Ok(Reading {
+            lux: self.to_lux(raw),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+    use embedded_hal::i2c::{ErrorType, Operation};
+
+    use super::*;
+
+    /// A VEML7700 under a fixed illuminance, with the resolutions of the datasheet
+    struct FakeSensor {
+        lux: f32,
+        conf: u16,
+        configured: u32,
+    }
+
+    impl FakeSensor {
+        const fn new(lux: f32) -> Self {
+            Self {
+                lux,
+                conf: 0,
+                configured: 0,
+            }
+        }
+
+        /// Returns the gain and integration time in ms set in `ALS_CONF`
+        fn range(&self) -> (f32, f32) {
+            let gain = match (self.conf >> 11) & 0b11 {
+                0b00 => 1.0,
+                0b01 => 2.0,
+                0b10 => 0.125,
+                _ => 0.25,
+            };
+            let millis = match (self.conf >> 6) & 0b1111 {
+                0b1100 => 25.0,
+                0b1000 => 50.0,
+                0b0000 => 100.0,
+                0b0001 => 200.0,
+                0b0010 => 400.0,
+                0b0011 => 800.0,
+                bits => panic!("invalid integration time {bits:04b}"),
+            };
+            (gain, millis)
+        }
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        fn counts(&self) -> u16 {
+            let (gain, millis) = self.range();
+            // 0.0036 lx/count at gain x2 and 800 ms
+            let counts = self.lux / (0.0036 * 2.0 * 800.0 / (gain * millis));
+            // the float to int cast saturates at the full scale
+            counts.round() as u16
+        }
+    }
+
+    impl ErrorType for FakeSensor {
+        type Error = Infallible;
+    }
+
+    impl I2c for FakeSensor {
+        fn transaction(
+            &mut self,
+            address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), Infallible> {
+            assert_eq!(address, ADDRESS);
+            match operations {
+                [Operation::Write(&[REG_ALS_CONF, lo, hi])] => {
+                    self.conf = u16::from_le_bytes([lo, hi]);
+                    self.configured += 1;
+                }
+                [Operation::Write(&[REG_ALS]), Operation::Read(buf)] => {
+                    buf.copy_from_slice(&self.counts().to_le_bytes());
+                }
+                _ => panic!("unexpected transaction"),
+            }
+            Ok(())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() <= expected * 0.01,
+            "{actual} lx is not {expected} lx"
+        );
+    }
+
+    #[test]
+    fn converts_counts_to_lux() {
+        let mut sensor = Veml7700::new(FakeSensor::new(100.0), NoDelay);
+        sensor.set_auto_range(false);
+        sensor.set_range(Gain::One, IntegrationTime::Ms100);
+        assert_near(sensor.read().unwrap().lux(), 100.0);
+        assert_eq!(sensor.i2c.range(), (1.0, 100.0));
+        // the settings are only written once
+        sensor.read().unwrap();
+        assert_eq!(sensor.i2c.configured, 1);
+    }
+
+    #[test]
+    fn raises_the_gain_before_the_integration_time_in_the_dark() {
+        let mut sensor = Veml7700::new(FakeSensor::new(1.0), NoDelay);
+        assert_near(sensor.read().unwrap().lux(), 1.0);
+        assert_eq!(sensor.range(), (Gain::Two, IntegrationTime::Ms400));
+        assert_eq!(sensor.i2c.range(), (2.0, 400.0));
+        // the initial setting, three gain and two integration time steps
+        assert_eq!(sensor.i2c.configured, 6);
+    }
+
+    #[test]
+    fn shortens_the_integration_time_in_bright_light() {
+        let mut sensor = Veml7700::new(FakeSensor::new(800.0), NoDelay);
+        sensor.set_range(Gain::Two, IntegrationTime::Ms800);
+        assert_near(sensor.read().unwrap().lux(), 800.0);
+        assert_eq!(sensor.range(), (Gain::Two, IntegrationTime::Ms25));
+    }
+
+    #[test]
+    fn reports_saturation() {
+        let mut sensor = Veml7700::new(FakeSensor::new(2_000_000.0), NoDelay);
+        assert!(matches!(sensor.read(), Err(LightError::Saturated)));
+        assert_eq!(sensor.range(), (Gain::OneEighth, IntegrationTime::Ms25));
+    }
+}
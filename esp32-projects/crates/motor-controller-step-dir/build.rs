@@ -0,0 +1,6 @@
+//! crate motor-controller-step-dir
+
+use embuild::espidf;
+fn main() {
+    espidf::sysenv::output();
+}
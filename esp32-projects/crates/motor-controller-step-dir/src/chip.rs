@@ -0,0 +1,100 @@
+use embedded_hal::digital::PinState::{self, High, Low};
+
+// === Timing ===
+
+/// Minimum timings of the STEP and DIR inputs of a driver chip, in ns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    step_high: u32,
+    step_low: u32,
+    dir_setup: u32,
+    dir_hold: u32,
+}
+
+impl Timing {
+    /// Create new `Timing` from the datasheet values
+    pub const fn new(step_high: u32, step_low: u32, dir_setup: u32, dir_hold: u32) -> Self {
+        Self {
+            step_high,
+            step_low,
+            dir_setup,
+            dir_hold,
+        }
+    }
+
+    /// Returns the minimum STEP high time
+    pub const fn step_high(&self) -> u32 {
+        self.step_high
+    }
+
+    /// Returns the minimum STEP low time
+    pub const fn step_low(&self) -> u32 {
+        self.step_low
+    }
+
+    /// Returns the minimum time between a change on DIR or the microstep pins and the next STEP rising edge
+    pub const fn dir_setup(&self) -> u32 {
+        self.dir_setup
+    }
+
+    /// Returns the minimum time DIR has to stay the same after a STEP rising edge
+    pub const fn dir_hold(&self) -> u32 {
+        self.dir_hold
+    }
+}
+
+// === Chip ===
+
+/// Supported step/dir driver chips
+///
+/// Microstep selection by chip, x marks the pins pulled high
+///
+/// | steps | A4988 MS1-3 | DRV8825 M0-2 | TMC2208 MS1-2 |
+/// | ----- | ----------- | ------------ | ------------- |
+/// |   1   |             |              |               |
+/// |  1/2  | x           | x            | x             |
+/// |  1/4  |   x         |   x          |   x           |
+/// |  1/8  | x x         | x x          |               |
+/// |  1/16 | x x x       |     x        | x x           |
+/// |  1/32 |             | x   x        |               |
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Chip {
+    A4988,
+    Drv8825,
+    /// In standalone mode, the TMC2208 interpolates every microstep setting to 1/256
+    Tmc2208,
+}
+
+impl Chip {
+    /// Returns the minimum timings from the datasheet
+    pub const fn timing(self) -> Timing {
+        match self {
+            Self::A4988 => Timing::new(1_000, 1_000, 200, 200),
+            Self::Drv8825 => Timing::new(1_900, 1_900, 650, 650),
+            Self::Tmc2208 => Timing::new(100, 100, 20, 20),
+        }
+    }
+
+    /// Returns the microsteps per full step with all microstep pins low
+    pub const fn default_microsteps(self) -> u16 {
+        match self {
+            Self::A4988 | Self::Drv8825 => 1,
+            Self::Tmc2208 => 8,
+        }
+    }
+
+    /// Returns the levels of the microstep pins for a resolution, `None` if the chip does not support it
+    pub const fn microstep_pins(self, microsteps: u16) -> Option<[PinState; 3]> {
+        let pins = match (self, microsteps) {
+            (Self::A4988 | Self::Drv8825, 1) | (Self::Tmc2208, 8) => [Low, Low, Low],
+            (_, 2) => [High, Low, Low],
+            (_, 4) => [Low, High, Low],
+            (Self::A4988 | Self::Drv8825, 8) | (Self::Tmc2208, 16) => [High, High, Low],
+            (Self::A4988, 16) => [High, High, High],
+            (Self::Drv8825, 16) => [Low, Low, High],
+            (Self::Drv8825, 32) => [High, Low, High],
+            _ => return None,
+        };
+        Some(pins)
+    }
+}
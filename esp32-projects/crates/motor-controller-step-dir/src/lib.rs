@@ -0,0 +1,353 @@
+use core::{convert::Infallible, time::Duration};
+use embedded_hal::delay::DelayNs;
+
+use embedded_hal::digital::PinState::{High, Low};
+use embedded_hal::digital::{ErrorType, OutputPin, PinState};
+use stepper::{
+    axis::MotorConfig, idle::IdlePolicy, micros, persist::Checkpoint, profile::MotionProfile,
+    rescale, stop::StopToken,
+};
+
+pub use stepper::{
+    asynch, axis, backlash, blocking, coordinator, homing, idle, persist, profile, queue, runner,
+    stop, Direction, HalError, SoftLimits, StepError, StepperMotor,
+};
+
+mod chip;
+
+pub use chip::{Chip, Timing};
+
+/// Placeholder for the optional ENABLE and microstep pins when they are hard-wired
+pub struct NoPin;
+
+impl ErrorType for NoPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for NoPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Struct representing a stepper motor on a step/dir driver like the A4988
+///
+/// Every STEP pulse and DIR change is timed to the minimum timings of the
+/// chip, so the delay should be able to wait for a few hundred ns, e.g. by
+/// busy waiting.
+pub struct StepDir<SP, DP, D, EP = NoPin, MP = NoPin>
+where
+    SP: OutputPin,
+    DP: OutputPin,
+    D: DelayNs,
+    EP: OutputPin,
+    MP: OutputPin,
+{
+    chip: Chip,
+    timing: Timing,
+    step_pin: SP,
+    dir_pin: DP,
+    /// active low on all supported chips
+    enable_pin: Option<EP>,
+    microstep_pins: [Option<MP>; 3],
+    delay: D,
+    microsteps: u16,
+    enabled: bool,
+    /// level of the DIR pin, `None` before the first step
+    applied_dir: Option<Direction>,
+    /// signed step count, `Direction::Normal` counts up
+    position: i32,
+    limits: Option<SoftLimits>,
+    idle: IdlePolicy,
+    stop: Option<StopToken>,
+    dir: Direction,
+}
+
+impl<SP: OutputPin, DP: OutputPin, D: DelayNs> StepDir<SP, DP, D> {
+    /// Create a new `StepDir` from the STEP and DIR pins connected to the driver.
+    /// Assumes the microstep pins are low, use `set_microsteps` otherwise.
+    pub const fn new(chip: Chip, step_pin: SP, dir_pin: DP, delay: D) -> Self {
+        Self {
+            chip,
+            timing: chip.timing(),
+            step_pin,
+            dir_pin,
+            enable_pin: None,
+            microstep_pins: [None, None, None],
+            delay,
+            microsteps: chip.default_microsteps(),
+            enabled: false,
+            applied_dir: None,
+            position: 0,
+            limits: None,
+            idle: IdlePolicy::Hold,
+            stop: None,
+            dir: Direction::Normal,
+        }
+    }
+}
+
+impl<SP: OutputPin, DP: OutputPin, D: DelayNs, EP: OutputPin, MP: OutputPin>
+    StepDir<SP, DP, D, EP, MP>
+where
+    SP::Error: Send + Sync + 'static,
+    DP::Error: Send + Sync + 'static,
+    EP::Error: Send + Sync + 'static,
+    MP::Error: Send + Sync + 'static,
+{
+    /// Use the ENABLE pin, so the motor can be released with `stop`
+    pub fn with_enable_pin<E: OutputPin>(self, enable_pin: E) -> StepDir<SP, DP, D, E, MP> {
+        StepDir {
+            chip: self.chip,
+            timing: self.timing,
+            step_pin: self.step_pin,
+            dir_pin: self.dir_pin,
+            enable_pin: Some(enable_pin),
+            microstep_pins: self.microstep_pins,
+            delay: self.delay,
+            microsteps: self.microsteps,
+            enabled: false,
+            applied_dir: self.applied_dir,
+            position: self.position,
+            limits: self.limits,
+            idle: self.idle,
+            stop: self.stop,
+            dir: self.dir,
+        }
+    }
+
+    /// Use the microstep pins, so the resolution can be changed with `set_microsteps`.
+    /// The TMC2208 only has two.
+    pub fn with_microstep_pins<M: OutputPin>(
+        self,
+        ms1: M,
+        ms2: M,
+        ms3: Option<M>,
+    ) -> StepDir<SP, DP, D, EP, M> {
+        StepDir {
+            chip: self.chip,
+            timing: self.timing,
+            step_pin: self.step_pin,
+            dir_pin: self.dir_pin,
+            enable_pin: self.enable_pin,
+            microstep_pins: [Some(ms1), Some(ms2), ms3],
+            delay: self.delay,
+            microsteps: self.microsteps,
+            enabled: self.enabled,
+            applied_dir: self.applied_dir,
+            position: self.position,
+            limits: self.limits,
+            idle: self.idle,
+            stop: self.stop,
+            dir: self.dir,
+        }
+    }
+
+    /// Use other timings than the datasheet minimums of the chip, e.g. for long wires
+    #[must_use]
+    pub const fn with_timing(mut self, timing: Timing) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Use a different `IdlePolicy` than `IdlePolicy::Hold`
+    #[must_use]
+    pub const fn with_idle_policy(mut self, policy: IdlePolicy) -> Self {
+        self.idle = policy;
+        self
+    }
+
+    /// Returns the driver chip
+    pub const fn chip(&self) -> Chip {
+        self.chip
+    }
+
+    /// Returns the microsteps per full step
+    pub const fn microsteps(&self) -> u16 {
+        self.microsteps
+    }
+
+    /// Change the microsteps per full step. The position and soft limits are scaled
+    /// to the new resolution, which is only exact on a full step.
+    /// Without microstep pins this only records the resolution set by jumpers.
+    pub fn set_microsteps(&mut self, microsteps: u16) -> Result<(), StepError> {
+        let Some(levels) = self.chip.microstep_pins(microsteps) else {
+            return Err(StepError::UnsupportedMicrosteps { microsteps });
+        };
+        if self.microstep_pins.iter().any(Option::is_some) {
+            for ((pin, name), level) in self
+                .microstep_pins
+                .iter_mut()
+                .zip(["ms1", "ms2", "ms3"])
+                .zip(levels)
+            {
+                if let Some(pin) = pin {
+                    set_state(pin, name, level)?;
+                }
+            }
+            self.delay.delay_ns(self.timing.dir_setup());
+        }
+
+        let (from, to) = (u32::from(self.microsteps), u32::from(microsteps));
+        self.position = rescale(self.position, from, to);
+        self.limits = self.limits.map(|limits| limits.rescale(from, to));
+        self.microsteps = microsteps;
+        Ok(())
+    }
+
+    /// Unit configuration for use with `axis::Axis`, for a motor with
+    /// `full_steps_per_revolution` full steps, e.g. 200 for a 1.8° NEMA 17
+    pub const fn motor_config(&self, full_steps_per_revolution: u32) -> MotorConfig {
+        MotorConfig::new(full_steps_per_revolution).with_microsteps(self.microsteps as u32)
+    }
+
+    /// Energise the motor, stepping does this automatically
+    pub fn enable(&mut self) -> Result<(), StepError> {
+        if let Some(pin) = self.enable_pin.as_mut() {
+            set_state(pin, "enable", Low)?;
+        }
+        self.enabled = true;
+        Ok(())
+    }
+
+    fn step_in(&mut self, dir: Direction) -> Result<(), StepError> {
+        if !self.enabled {
+            self.enable()?;
+        }
+        if self.applied_dir != Some(dir) {
+            let level = match dir {
+                Direction::Normal => High,
+                Direction::Reverse => Low,
+            };
+            set_state(&mut self.dir_pin, "dir", level)?;
+            self.applied_dir = Some(dir);
+            self.delay.delay_ns(self.timing.dir_setup());
+        }
+
+        set_state(&mut self.step_pin, "step", High)?;
+        self.delay.delay_ns(self.timing.step_high());
+        set_state(&mut self.step_pin, "step", Low)?;
+        // DIR has to be held from the rising edge, so only the rest of the hold time is left
+        let hold = self
+            .timing
+            .dir_hold()
+            .saturating_sub(self.timing.step_high());
+        self.delay.delay_ns(self.timing.step_low().max(hold));
+
+        self.position = match dir {
+            Direction::Normal => self.position.wrapping_add(1),
+            Direction::Reverse => self.position.wrapping_sub(1),
+        };
+        Ok(())
+    }
+
+    fn step_and_wait(&mut self, dir: Direction, interval: Duration) -> Result<(), StepError> {
+        self.step_in(dir)?;
+        self.delay.delay_us(micros(interval));
+        Ok(())
+    }
+}
+
+impl<SP: OutputPin, DP: OutputPin, D: DelayNs, EP: OutputPin, MP: OutputPin> StepperMotor
+    for StepDir<SP, DP, D, EP, MP>
+where
+    SP::Error: Send + Sync + 'static,
+    DP::Error: Send + Sync + 'static,
+    EP::Error: Send + Sync + 'static,
+    MP::Error: Send + Sync + 'static,
+{
+    fn step(&mut self) -> Result<(), StepError> {
+        self.step_in(self.dir)
+    }
+
+    fn step_for(&mut self, steps: i32, ms: u32) -> Result<(), StepError> {
+        let delta = match self.dir {
+            Direction::Normal => steps,
+            Direction::Reverse => steps.saturating_neg(),
+        };
+        self.move_by(delta, ms)
+    }
+
+    fn move_by(&mut self, delta: i32, ms: u32) -> Result<(), StepError> {
+        let interval = Duration::from_millis(ms.into());
+        blocking::move_by(self, delta, interval, Self::step_and_wait)
+    }
+
+    fn move_by_profile(&mut self, delta: i32, profile: &MotionProfile) -> Result<(), StepError> {
+        blocking::move_by_profile(self, delta, profile, Self::step_and_wait)
+    }
+
+    fn current_position(&self) -> i32 {
+        self.position
+    }
+
+    fn set_position(&mut self, position: i32) {
+        self.position = position;
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint::new(self.position).with_microsteps(self.microsteps)
+    }
+
+    /// A position saved at another resolution is scaled to the current one
+    fn restore(&mut self, checkpoint: &Checkpoint) {
+        let from = checkpoint.microsteps().unwrap_or(self.microsteps);
+        self.position = rescale(
+            checkpoint.position(),
+            u32::from(from),
+            u32::from(self.microsteps),
+        );
+    }
+
+    fn set_soft_limits(&mut self, limits: Option<SoftLimits>) {
+        self.limits = limits;
+    }
+
+    fn soft_limits(&self) -> Option<SoftLimits> {
+        self.limits
+    }
+
+    fn set_idle_policy(&mut self, policy: IdlePolicy) {
+        self.idle = policy;
+    }
+
+    fn idle_policy(&self) -> IdlePolicy {
+        self.idle
+    }
+
+    fn set_stop_token(&mut self, token: Option<StopToken>) {
+        self.stop = token;
+    }
+
+    fn stop_token(&self) -> Option<&StopToken> {
+        self.stop.as_ref()
+    }
+
+    fn set_direction(&mut self, dir: Direction) {
+        self.dir = dir;
+    }
+
+    /// Disables the driver outputs, without an ENABLE pin the motor keeps holding
+    fn stop(&mut self) -> Result<(), StepError> {
+        if let Some(pin) = self.enable_pin.as_mut() {
+            set_state(pin, "enable", High)?;
+            self.enabled = false;
+        }
+        Ok(())
+    }
+}
+
+fn set_state<P>(pin: &mut P, name: &'static str, state: PinState) -> Result<(), StepError>
+where
+    P: OutputPin,
+    P::Error: Send + Sync + 'static,
+{
+    pin.set_state(state).map_err(|err| StepError::Pin {
+        pin: name,
+        error: HalError::from_pin(err),
+    })
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    delay::Delay,
+    gpio::{OutputPin, PinDriver},
+    prelude::Peripherals,
+};
+use log::info;
+
+use motor_controller_step_dir::{
+    axis::Axis, idle::IdlePolicy, profile::MotionProfile, Chip, StepDir,
+};
+
+fn main() -> Result<()> {
+    esp_idf_svc::sys::link_patches();
+    esp_idf_svc::log::EspLogger::initialize_default();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    // MS1-3 are wired to 3.3V on the carrier board for 1/16 microsteps
+    let mut motor = StepDir::new(
+        Chip::A4988,
+        PinDriver::output(peripherals.pins.gpio2.downgrade_output())?,
+        PinDriver::output(peripherals.pins.gpio3.downgrade_output())?,
+        Delay::new_default(),
+    )
+    .with_enable_pin(PinDriver::output(
+        peripherals.pins.gpio4.downgrade_output(),
+    )?)
+    .with_idle_policy(IdlePolicy::ReleaseAfter(std::time::Duration::ZERO));
+    motor.set_microsteps(16).unwrap();
+    info!("Motor controller initialized");
+
+    // a 1.8° NEMA 17 on a T8 lead screw
+    let config = motor.motor_config(200).with_lead(8.0);
+    let mut axis = Axis::new(motor, config, MotionProfile::trapezoidal(4000.0, 16000.0));
+    axis.set_rpm(120.0);
+
+    info!("Moving 50 mm at {} rpm", axis.rpm());
+    axis.move_mm(50.0).unwrap();
+
+    info!("sleeping for 1 second");
+    std::thread::sleep(std::time::Duration::from_secs(1));
+
+    info!("Returning to 0 from {:?} mm", axis.position_mm());
+    axis.move_mm(-50.0).unwrap();
+
+    Ok(())
+}
@@ -0,0 +1,55 @@
+//! The driver pins switching the coils of a motor
+
+use embedded_hal::digital::{OutputPin, PinState};
+use stepper::{HalError, StepError};
+
+/// Names used in `StepError::Pin`, pins past the last are reported as "in"
+const NAMES: [&str; 8] = ["in1", "in2", "in3", "in4", "in5", "in6", "in7", "in8"];
+
+/// `N` pins set together to one step of a `Sequence`
+///
+/// Implemented for arrays of pins of the same type, e.g. downgraded to
+/// `AnyOutputPin`, and for the 4 separate pins of a ULN2003 board.
+pub trait Coils<const N: usize> {
+    /// Set pin `i` to `states[i]`, in order
+    fn set(&mut self, states: &[PinState; N]) -> Result<(), StepError>;
+}
+
+impl<P: OutputPin, const N: usize> Coils<N> for [P; N]
+where
+    P::Error: Send + Sync + 'static,
+{
+    fn set(&mut self, states: &[PinState; N]) -> Result<(), StepError> {
+        for (i, (pin, state)) in self.iter_mut().zip(states).enumerate() {
+            set_state(pin, NAMES.get(i).copied().unwrap_or("in"), *state)?;
+        }
+        Ok(())
+    }
+}
+
+impl<P1: OutputPin, P2: OutputPin, P3: OutputPin, P4: OutputPin> Coils<4> for (P1, P2, P3, P4)
+where
+    P1::Error: Send + Sync + 'static,
+    P2::Error: Send + Sync + 'static,
+    P3::Error: Send + Sync + 'static,
+    P4::Error: Send + Sync + 'static,
+{
+    fn set(&mut self, states: &[PinState; 4]) -> Result<(), StepError> {
+        set_state(&mut self.0, NAMES[0], states[0])?;
+        set_state(&mut self.1, NAMES[1], states[1])?;
+        set_state(&mut self.2, NAMES[2], states[2])?;
+        set_state(&mut self.3, NAMES[3], states[3])?;
+        Ok(())
+    }
+}
+
+fn set_state<P>(pin: &mut P, name: &'static str, state: PinState) -> Result<(), StepError>
+where
+    P: OutputPin,
+    P::Error: Send + Sync + 'static,
+{
+    pin.set_state(state).map_err(|err| StepError::Pin {
+        pin: name,
+        error: HalError::from_pin(err),
+    })
+}
@@ -1,157 +1,388 @@
+use core::time::Duration;
 use embedded_hal::delay::DelayNs;
 
 use embedded_hal::digital::PinState::{High, Low};
 use embedded_hal::digital::{OutputPin, PinState};
+use stepper::{
+    axis::MotorConfig, idle::IdlePolicy, micros, persist::Checkpoint, profile::MotionProfile,
+    rescale, stop::StopToken,
+};
 
-/// different positions of the motor.
-/// Depending on the state different pins have to be high
-/// |wire | 0 | 1 | 2 | 3 | 4 | 5 | 6 | 7 | 8 |
-/// | --- | - | - | - | - | - | - | - | - | - |
-/// |  1  |   |   |   |   |   |   | x | x | x |
-/// |  2  |   |   |   |   | x | x | x |   |   |
-/// |  3  |   |   | x | x | x |   |   |   |   |
-/// |  4  |   | x | x |   |   |   |   |   | x |
-#[derive(Copy, Clone, Debug)]
-enum State {
-    State0,
-    State1,
-    State2,
-    State3,
-    State4,
+pub use stepper::{
+    asynch, axis, backlash, blocking, coordinator, homing, idle, persist, profile, queue, runner,
+    stop, Direction, HalError, SoftLimits, StepError, StepperMotor,
+};
+
+mod coils;
+#[cfg(target_os = "espidf")]
+mod nvs;
+mod sequence;
+
+pub use coils::Coils;
+#[cfg(target_os = "espidf")]
+pub use nvs::NvsStore;
+pub use sequence::{Sequence, SequenceError};
+
+/// Full steps per output shaft revolution of a 28BYJ-48 (32 steps * 64:1 gearbox)
+pub const FULL_STEPS_PER_REVOLUTION: u32 = 2048;
+
+/// Full steps per revolution of the 28BYJ-48 motor shaft, before the gearbox
+const MOTOR_FULL_STEPS: u32 = 32;
+
+/// Nominal gearbox ratio of the 28BYJ-48, most are actually ~63.684:1
+const GEAR_RATIO: f32 = 64.0;
+
+/// Built-in coil sequence used to drive a 4 wire unipolar motor, see `Sequence` for others.
+/// Columns are the steps of the sequence, x marks the energised wires
+///
+/// `Wave`, one coil at a time, least power and torque
+/// |wire | 1 | 2 | 3 | 4 |
+/// | --- | - | - | - | - |
+/// |  1  |   |   |   | x |
+/// |  2  |   |   | x |   |
+/// |  3  |   | x |   |   |
+/// |  4  | x |   |   |   |
+///
+/// `FullStep`, two adjacent coils at a time, full torque
+/// |wire | 1 | 2 | 3 | 4 |
+/// | --- | - | - | - | - |
+/// |  1  |   |   | x | x |
+/// |  2  |   | x | x |   |
+/// |  3  | x | x |   |   |
+/// |  4  | x |   |   | x |
+///
+/// `HalfStep`, alternates one and two coils, twice the resolution
+/// |wire | 1 | 2 | 3 | 4 | 5 | 6 | 7 | 8 |
+/// | --- | - | - | - | - | - | - | - | - |
+/// |  1  |   |   |   |   |   | x | x | x |
+/// |  2  |   |   |   | x | x | x |   |   |
+/// |  3  |   | x | x | x |   |   |   |   |
+/// |  4  | x | x |   |   |   |   |   | x |
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StepMode {
+    Wave,
+    FullStep,
+    HalfStep,
 }
 
-const fn get_pin_states(s: State) -> [PinState; 4] {
-    match s {
-        State::State0 => [Low, Low, Low, Low],
-        State::State1 => [Low, Low, Low, High],
-        State::State2 => [Low, Low, High, Low],
-        State::State3 => [Low, High, Low, Low],
-        State::State4 => [High, Low, Low, Low],
+const WAVE: [[PinState; 4]; 4] = [
+    [Low, Low, Low, High],
+    [Low, Low, High, Low],
+    [Low, High, Low, Low],
+    [High, Low, Low, Low],
+];
+
+const FULL_STEP: [[PinState; 4]; 4] = [
+    [Low, Low, High, High],
+    [Low, High, High, Low],
+    [High, High, Low, Low],
+    [High, Low, Low, High],
+];
+
+const HALF_STEP: [[PinState; 4]; 8] = [
+    [Low, Low, Low, High],
+    [Low, Low, High, High],
+    [Low, Low, High, Low],
+    [Low, High, High, Low],
+    [Low, High, Low, Low],
+    [High, High, Low, Low],
+    [High, Low, Low, Low],
+    [High, Low, Low, High],
+];
+
+const WAVE_SEQUENCE: Sequence<4> = builtin(&WAVE, 2, 1);
+const FULL_STEP_SEQUENCE: Sequence<4> = builtin(&FULL_STEP, 2, 1);
+const HALF_STEP_SEQUENCE: Sequence<4> = builtin(&HALF_STEP, 1, 2);
+
+/// Checks a built-in table while compiling, so a typo in it fails the build
+const fn builtin(
+    steps: &'static [[PinState; 4]],
+    max_changes: usize,
+    microsteps: u32,
+) -> Sequence<4> {
+    match Sequence::new(steps, max_changes) {
+        Ok(sequence) => sequence.with_microsteps(microsteps),
+        Err(_) => panic!("invalid built-in coil sequence"),
     }
 }
 
-const fn get_next_state(s: State) -> State {
-    match s {
-        State::State1 => State::State2,
-        State::State2 => State::State3,
-        State::State3 => State::State4,
-        State::State4 | State::State0 => State::State1,
+impl StepMode {
+    /// Returns the coil sequence of the mode
+    pub const fn sequence(self) -> Sequence<4> {
+        match self {
+            Self::Wave => WAVE_SEQUENCE,
+            Self::FullStep => FULL_STEP_SEQUENCE,
+            Self::HalfStep => HALF_STEP_SEQUENCE,
+        }
+    }
+
+    /// Steps needed for one revolution of the output shaft
+    pub const fn steps_per_revolution(self) -> u32 {
+        FULL_STEPS_PER_REVOLUTION * self.microsteps()
+    }
+
+    /// Steps per full step
+    pub const fn microsteps(self) -> u32 {
+        self.sequence().microsteps()
+    }
+}
+
+const fn get_next_phase(len: usize, phase: Option<usize>) -> usize {
+    match phase {
+        Some(phase) => (phase + 1) % len,
+        None => 0,
     }
 }
 
-const fn get_prev_state(s: State) -> State {
-    match s {
-        State::State0 | State::State1 => State::State4,
-        State::State2 => State::State1,
-        State::State3 => State::State2,
-        State::State4 => State::State3,
+const fn get_prev_phase(len: usize, phase: Option<usize>) -> usize {
+    match phase {
+        Some(phase) => (phase + len - 1) % len,
+        None => len - 1,
     }
 }
 
-/// Struct representing a Stepper motor with the 4 driver pins
-pub struct ULN2003<P1, P2, P3, P4, D>
+/// A 28BYJ-48 or similar unipolar stepper on a ULN2003 board, with the 4 driver pins
+pub type ULN2003<P1, P2, P3, P4, D> = CoilStepper<(P1, P2, P3, P4), D, 4>;
+
+/// Struct representing a Stepper motor driven by switching its coils through a `Sequence`
+pub struct CoilStepper<C, D, const N: usize>
 where
-    P1: OutputPin,
-    P2: OutputPin,
-    P3: OutputPin,
-    P4: OutputPin,
+    C: Coils<N>,
     D: DelayNs,
 {
-    in1: P1,
-    in2: P2,
-    in3: P3,
-    in4: P4,
-    state: State,
+    coils: C,
+    sequence: Sequence<N>,
+    /// index into `sequence`, `None` before the first step
+    phase: Option<usize>,
+    /// false while all coils are off, the phase is kept to continue from it
+    energised: bool,
+    idle: IdlePolicy,
+    /// signed step count, `Direction::Normal` counts up
+    position: i32,
+    limits: Option<SoftLimits>,
+    stop: Option<StopToken>,
     dir: Direction,
     delay: Option<D>,
 }
 
 impl<P1: OutputPin, P2: OutputPin, P3: OutputPin, P4: OutputPin, D: DelayNs>
     ULN2003<P1, P2, P3, P4, D>
+where
+    (P1, P2, P3, P4): Coils<4>,
 {
     /// Create a new `StepperMotor` from the 4 pins connected to te uln2003 driver.
     /// The delay parameter is needed if you want to use the `step_for` function.
+    /// Uses `StepMode::Wave` unless changed with `with_step_mode` or `with_sequence`.
     pub const fn new(in1: P1, in2: P2, in3: P3, in4: P4, delay: Option<D>) -> Self {
+        Self::from_coils((in1, in2, in3, in4), StepMode::Wave.sequence(), delay)
+    }
+}
+
+impl<C: Coils<4>, D: DelayNs> CoilStepper<C, D, 4> {
+    /// Use a different coil sequence than `StepMode::Wave`
+    #[must_use]
+    pub const fn with_step_mode(self, mode: StepMode) -> Self {
+        self.with_sequence(mode.sequence())
+    }
+
+    /// Change the coil sequence, see `set_sequence`
+    pub fn set_step_mode(&mut self, mode: StepMode) {
+        self.set_sequence(mode.sequence());
+    }
+
+    /// Returns the built-in coil sequence in use, `None` for a custom `Sequence`
+    pub fn step_mode(&self) -> Option<StepMode> {
+        [StepMode::Wave, StepMode::FullStep, StepMode::HalfStep]
+            .into_iter()
+            .find(|mode| mode.sequence() == self.sequence)
+    }
+
+    /// Steps needed for one revolution of the output shaft of a 28BYJ-48 in the current sequence
+    pub const fn steps_per_revolution(&self) -> u32 {
+        FULL_STEPS_PER_REVOLUTION * self.sequence.microsteps()
+    }
+
+    /// Unit configuration of a 28BYJ-48 in the current sequence, for use with `axis::Axis`.
+    /// Use `MotorConfig::with_gear_ratio` to correct the ratio of a measured gearbox.
+    pub const fn motor_config(&self) -> MotorConfig {
+        MotorConfig::new(MOTOR_FULL_STEPS)
+            .with_microsteps(self.sequence.microsteps())
+            .with_gear_ratio(GEAR_RATIO)
+    }
+}
+
+impl<C: Coils<N>, D: DelayNs, const N: usize> CoilStepper<C, D, N> {
+    /// Create a new `StepperMotor` from the driver pins and the sequence to switch them through.
+    /// The delay parameter is needed if you want to use the `step_for` function.
+    pub const fn from_coils(coils: C, sequence: Sequence<N>, delay: Option<D>) -> Self {
         Self {
-            in1,
-            in2,
-            in3,
-            in4,
-            state: State::State0,
+            coils,
+            sequence,
+            phase: None,
+            energised: false,
+            idle: IdlePolicy::Hold,
+            position: 0,
+            limits: None,
+            stop: None,
             dir: Direction::Normal,
             delay,
         }
     }
 
-    fn apply_state(&mut self) -> Result<(), StepError> {
-        let states = get_pin_states(self.state);
-        set_state(&mut self.in1, states[0])?;
-        set_state(&mut self.in2, states[1])?;
-        set_state(&mut self.in3, states[2])?;
-        set_state(&mut self.in4, states[3])?;
+    /// Use a custom coil sequence, e.g. for a different wiring order
+    #[must_use]
+    pub const fn with_sequence(mut self, sequence: Sequence<N>) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Use a different `IdlePolicy` than `IdlePolicy::Hold`
+    #[must_use]
+    pub const fn with_idle_policy(mut self, policy: IdlePolicy) -> Self {
+        self.idle = policy;
+        self
+    }
+
+    /// Change the coil sequence. The current coil state is mapped to the
+    /// closest step of the new sequence so the rotor does not jump.
+    /// The position and soft limits are scaled to the microsteps of the new
+    /// sequence, which is only exact on a full step.
+    pub fn set_sequence(&mut self, sequence: Sequence<N>) {
+        self.phase = self
+            .phase
+            .map(|phase| sequence.closest(&self.sequence.steps()[phase]));
+        let (from, to) = (self.sequence.microsteps(), sequence.microsteps());
+        self.position = rescale(self.position, from, to);
+        self.limits = self.limits.map(|limits| limits.rescale(from, to));
+        self.sequence = sequence;
+    }
+
+    /// Returns the coil sequence currently in use
+    pub const fn sequence(&self) -> Sequence<N> {
+        self.sequence
+    }
+
+    fn step_in(&mut self, dir: Direction) -> Result<(), StepError> {
+        let len = self.sequence.steps().len();
+        let phase = match dir {
+            Direction::Normal => get_next_phase(len, self.phase),
+            Direction::Reverse => get_prev_phase(len, self.phase),
+        };
+        self.phase = Some(phase);
+        self.energised = true;
+        self.apply_state()?;
+        self.position = match dir {
+            Direction::Normal => self.position.wrapping_add(1),
+            Direction::Reverse => self.position.wrapping_sub(1),
+        };
         Ok(())
     }
-}
 
-/// gets returned if an Error happens while stepping
-#[derive(Debug)]
-pub struct StepError;
+    fn apply_state(&mut self) -> Result<(), StepError> {
+        let states = match self.phase {
+            Some(phase) if self.energised => self.sequence.steps()[phase],
+            _ => [Low; N],
+        };
+        self.coils.set(&states)
+    }
 
-impl<P1: OutputPin, P2: OutputPin, P3: OutputPin, P4: OutputPin, D: DelayNs> StepperMotor
-    for ULN2003<P1, P2, P3, P4, D>
-{
-    fn step(&mut self) -> Result<(), StepError> {
-        match self.dir {
-            Direction::Normal => self.state = get_next_state(self.state),
-            Direction::Reverse => self.state = get_prev_state(self.state),
+    fn step_and_wait(&mut self, dir: Direction, interval: Duration) -> Result<(), StepError> {
+        self.step_in(dir)?;
+        if let Some(delay) = self.delay.as_mut() {
+            delay.delay_us(micros(interval));
         }
-        self.apply_state()?;
         Ok(())
     }
+}
+
+impl<C: Coils<N>, D: DelayNs, const N: usize> StepperMotor for CoilStepper<C, D, N> {
+    fn step(&mut self) -> Result<(), StepError> {
+        self.step_in(self.dir)
+    }
 
     fn step_for(&mut self, steps: i32, ms: u32) -> Result<(), StepError> {
+        let delta = match self.dir {
+            Direction::Normal => steps,
+            Direction::Reverse => steps.saturating_neg(),
+        };
+        self.move_by(delta, ms)
+    }
+
+    fn move_by(&mut self, delta: i32, ms: u32) -> Result<(), StepError> {
         if self.delay.is_none() {
-            return Err(StepError);
+            return Err(StepError::MissingDelay);
         }
-        for _ in 0..steps {
-            self.step()?;
-            self.delay.as_mut().unwrap().delay_ms(ms);
+        let interval = Duration::from_millis(ms.into());
+        blocking::move_by(self, delta, interval, Self::step_and_wait)
+    }
+
+    fn move_by_profile(&mut self, delta: i32, profile: &MotionProfile) -> Result<(), StepError> {
+        if self.delay.is_none() {
+            return Err(StepError::MissingDelay);
         }
-        Ok(())
+        blocking::move_by_profile(self, delta, profile, Self::step_and_wait)
     }
 
-    fn set_direction(&mut self, dir: Direction) {
-        self.dir = dir;
+    fn current_position(&self) -> i32 {
+        self.position
     }
 
-    fn stop(&mut self) -> Result<(), StepError> {
-        self.state = State::State0;
-        self.apply_state()?;
-        Ok(())
+    fn set_position(&mut self, position: i32) {
+        self.position = position;
     }
-}
 
-fn set_state<P: OutputPin>(pin: &mut P, state: PinState) -> Result<(), StepError> {
-    match pin.set_state(state) {
-        Ok(()) => Ok(()),
-        Err(_) => Err(StepError),
+    fn checkpoint(&self) -> Checkpoint {
+        let mut checkpoint = Checkpoint::new(self.position);
+        if let Ok(microsteps) = u16::try_from(self.sequence.microsteps()) {
+            checkpoint = checkpoint.with_microsteps(microsteps);
+        }
+        self.phase.map_or(checkpoint, |phase| {
+            checkpoint.with_phase(self.sequence.bits(phase))
+        })
     }
-}
 
-/// trait to prevent having to pass around the struct with all the generic arguments
-pub trait StepperMotor {
-    /// Do a single step
-    fn step(&mut self) -> Result<(), StepError>;
-    /// Do multiple steps with a given delay in ms
-    fn step_for(&mut self, steps: i32, delay: u32) -> Result<(), StepError>;
-    /// Set the stepping direction
-    fn set_direction(&mut self, dir: Direction);
-    /// Stopping sets all pins low
-    fn stop(&mut self) -> Result<(), StepError>;
-}
+    /// A position saved with a sequence of other microsteps is scaled to the current one
+    fn restore(&mut self, checkpoint: &Checkpoint) {
+        // the coils are saved rather than the index, so a changed sequence still continues from them
+        let to = self.sequence.microsteps();
+        let from = checkpoint.microsteps().map_or(to, u32::from);
+        self.position = rescale(checkpoint.position(), from, to);
+        self.phase = checkpoint
+            .phase()
+            .map(|bits| self.sequence.closest_to_bits(bits));
+        self.energised = false;
+    }
+
+    fn set_soft_limits(&mut self, limits: Option<SoftLimits>) {
+        self.limits = limits;
+    }
+
+    fn soft_limits(&self) -> Option<SoftLimits> {
+        self.limits
+    }
+
+    fn set_idle_policy(&mut self, policy: IdlePolicy) {
+        self.idle = policy;
+    }
+
+    fn idle_policy(&self) -> IdlePolicy {
+        self.idle
+    }
+
+    fn set_stop_token(&mut self, token: Option<StopToken>) {
+        self.stop = token;
+    }
 
-/// Direction the motor turns in. Just reverses the order of the internal states.
-pub enum Direction {
-    Normal,
-    Reverse,
+    fn stop_token(&self) -> Option<&StopToken> {
+        self.stop.as_ref()
+    }
+
+    fn set_direction(&mut self, dir: Direction) {
+        self.dir = dir;
+    }
+
+    fn stop(&mut self) -> Result<(), StepError> {
+        self.energised = false;
+        self.apply_state()
+    }
 }
@@ -1,12 +1,18 @@
 use anyhow::{Ok, Result};
-use esp_idf_svc::hal::{
-    delay::Delay,
-    gpio::{OutputPin, PinDriver},
-    prelude::Peripherals,
+use esp_idf_svc::{
+    hal::{
+        delay::Delay,
+        gpio::{OutputPin, PinDriver},
+        prelude::Peripherals,
+    },
+    nvs::EspDefaultNvsPartition,
 };
 use log::info;
 
-use motor_controller_uln2003::{Direction, StepperMotor, ULN2003};
+use motor_controller_uln2003::{
+    axis::Axis, idle::IdlePolicy, persist::Checkpointer, profile::MotionProfile, NvsStore,
+    StepperMotor, ULN2003,
+};
 
 fn main() -> Result<()> {
     esp_idf_svc::sys::link_patches();
@@ -20,20 +26,36 @@ fn main() -> Result<()> {
         PinDriver::output(peripherals.pins.gpio21.downgrade_output())?,
         PinDriver::output(peripherals.pins.gpio20.downgrade_output())?,
         Some(Delay::new(10000)), /* 10 ms */
-    );
+    )
+    // the gearbox holds the position well enough, holding just heats up the motor
+    .with_idle_policy(IdlePolicy::ReleaseAfter(std::time::Duration::ZERO));
     info!("Motor controller initialized");
 
-    let cycles_per_rev = 2048;
-    let revs = 1000;
-    info!("Stepping for {revs} steps at 1 ms delay");
-    motor.step_for(cycles_per_rev * revs, 2).unwrap();
+    // continue from the position and coils saved before the last reboot
+    let mut checkpoints = Checkpointer::new(
+        NvsStore::new(EspDefaultNvsPartition::take()?, "uln2003")?,
+        std::time::Duration::from_secs(10),
+    );
+    if checkpoints.restore(&mut motor)? {
+        info!("Restored position {}", motor.current_position());
+    }
+
+    // ramp up to 14 rpm, starting straight at that speed stalls the 28BYJ-48
+    let config = motor.motor_config();
+    let mut axis = Axis::new(motor, config, MotionProfile::trapezoidal(500.0, 1000.0));
+    axis.set_rpm(14.0);
+
+    let revs = 1000.0;
+    info!("Rotating for {revs} revolutions at {} rpm", axis.rpm());
+    axis.rotate_revolutions(revs).unwrap();
+    checkpoints.flush(axis.motor())?;
 
     info!("sleeping for 1 second");
     std::thread::sleep(std::time::Duration::from_secs(1));
 
-    info!("Reversing for {revs} steps at 1 ms delay");
-    motor.set_direction(Direction::Reverse);
-    motor.step_for(cycles_per_rev * revs, 2).unwrap();
+    info!("Returning to 0 from {}°", axis.position_degrees());
+    axis.rotate_degrees(-axis.position_degrees()).unwrap();
+    checkpoints.flush(axis.motor())?;
 
     Ok(())
 }
@@ -0,0 +1,46 @@
+//! Keeping the checkpoint of a motor in the NVS partition of the flash
+
+use esp_idf_svc::{
+    nvs::{EspNvs, EspNvsPartition, NvsPartitionId},
+    sys::EspError,
+};
+use stepper::persist::{Checkpoint, PositionStore};
+
+/// Key of the checkpoint within the namespace
+const KEY: &str = "checkpoint";
+
+/// `PositionStore` in ESP-IDF's non-volatile storage
+///
+/// The checkpoint is written as a single blob, so a reset in the middle of
+/// a write leaves the previous one intact. Every write wears the flash,
+/// throttle them with a `persist::Checkpointer`.
+pub struct NvsStore<T: NvsPartitionId> {
+    nvs: EspNvs<T>,
+}
+
+impl<T: NvsPartitionId> NvsStore<T> {
+    /// Create a new `NvsStore` in `namespace`, use a namespace per motor
+    pub fn new(partition: EspNvsPartition<T>, namespace: &str) -> Result<Self, EspError> {
+        Ok(Self {
+            nvs: EspNvs::new(partition, namespace, true)?,
+        })
+    }
+}
+
+impl<T: NvsPartitionId> PositionStore for NvsStore<T> {
+    type Error = EspError;
+
+    fn load(&mut self) -> Result<Option<Checkpoint>, EspError> {
+        let mut buf = [0; 11];
+        Ok(self
+            .nvs
+            .get_raw(KEY, &mut buf)?
+            .and_then(Checkpoint::from_bytes))
+    }
+
+    fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), EspError> {
+        let mut buf = [0; 11];
+        self.nvs.set_raw(KEY, checkpoint.to_bytes(&mut buf))?;
+        Ok(())
+    }
+}
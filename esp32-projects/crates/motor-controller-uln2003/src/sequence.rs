@@ -0,0 +1,149 @@
+//! Coil sequences for motors with any number of driver pins
+
+use core::fmt;
+
+use embedded_hal::digital::PinState;
+
+// === Errors ===
+
+/// Why a table is not a usable `Sequence`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceError {
+    /// The table has no steps
+    Empty,
+    /// Going from the previous step to `step` switches more coils than allowed.
+    /// The sequence repeats, so step 0 follows the last one.
+    TooManyChanges {
+        step: usize,
+        changes: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => f.write_str("Sequence has no steps"),
+            Self::TooManyChanges { step, changes, max } => write!(
+                f,
+                "Step {step} of the sequence switches {changes} coils, at most {max} are allowed"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SequenceError {}
+
+// === Sequence ===
+
+/// The pin states of every step of a coil sequence, for `N` driver pins
+///
+/// Stepping forward walks the table top to bottom and starts over, stepping
+/// back walks it the other way. This covers custom wiring orders, 5 wire
+/// unipolar motors as well as bipolar motors on two H-bridges, e.g. full
+/// steps with the pins A+, A-, B+, B- are `HLHL`, `LHHL`, `LHLH`, `HLLH`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sequence<const N: usize> {
+    steps: &'static [[PinState; N]],
+    microsteps: u32,
+}
+
+impl<const N: usize> Sequence<N> {
+    /// Check a table and turn it into a `Sequence` of full steps.
+    /// Every step, including the wrap from the last to the first, may switch
+    /// at most `max_changes` pins. A table that jumps further than that would
+    /// make the rotor skip or run backwards, usually because of a typo.
+    ///
+    /// Being a `const fn`, matching on the result in a `const` rejects a bad table at compile time.
+    pub const fn new(
+        steps: &'static [[PinState; N]],
+        max_changes: usize,
+    ) -> Result<Self, SequenceError> {
+        if steps.is_empty() {
+            return Err(SequenceError::Empty);
+        }
+        let mut step = 0;
+        while step < steps.len() {
+            let prev = if step == 0 { steps.len() - 1 } else { step - 1 };
+            let changes = changes(&steps[prev], &steps[step]);
+            if changes > max_changes {
+                return Err(SequenceError::TooManyChanges {
+                    step,
+                    changes,
+                    max: max_changes,
+                });
+            }
+            step += 1;
+        }
+        Ok(Self {
+            steps,
+            microsteps: 1,
+        })
+    }
+
+    /// Mark the steps as fractions of a full step, e.g. 2 for a half step sequence
+    #[must_use]
+    pub const fn with_microsteps(mut self, microsteps: u32) -> Self {
+        self.microsteps = microsteps;
+        self
+    }
+
+    /// Returns the pin states of every step
+    pub const fn steps(&self) -> &'static [[PinState; N]] {
+        self.steps
+    }
+
+    /// Returns the steps per full step
+    pub const fn microsteps(&self) -> u32 {
+        self.microsteps
+    }
+
+    /// Returns the step with the fewest pins different from `states`
+    pub(crate) fn closest(&self, states: &[PinState; N]) -> usize {
+        (0..self.steps.len())
+            .min_by_key(|&step| changes(&self.steps[step], states))
+            .unwrap_or(0)
+    }
+
+    /// Returns the pins `step` sets high as bits, the first pin in the lowest.
+    /// Pins past the 32nd are left out.
+    pub(crate) fn bits(&self, step: usize) -> u32 {
+        self.steps[step]
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| **state == PinState::High)
+            .fold(0, |bits, (pin, _)| {
+                bits | u32::try_from(pin)
+                    .ok()
+                    .and_then(|pin| 1u32.checked_shl(pin))
+                    .unwrap_or(0)
+            })
+    }
+
+    /// Returns the step closest to the pins set high in `bits`, as returned by `bits`
+    pub(crate) fn closest_to_bits(&self, bits: u32) -> usize {
+        let mut states = [PinState::Low; N];
+        for (pin, state) in states.iter_mut().enumerate().take(32) {
+            if bits & (1 << pin) != 0 {
+                *state = PinState::High;
+            }
+        }
+        self.closest(&states)
+    }
+}
+
+/// Number of pins that differ between two steps
+const fn changes<const N: usize>(a: &[PinState; N], b: &[PinState; N]) -> usize {
+    let mut changes = 0;
+    let mut pin = 0;
+    while pin < N {
+        if !matches!(
+            (a[pin], b[pin]),
+            (PinState::Low, PinState::Low) | (PinState::High, PinState::High)
+        ) {
+            changes += 1;
+        }
+        pin += 1;
+    }
+    changes
+}
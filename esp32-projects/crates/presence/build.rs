@@ -0,0 +1,6 @@
+//! crate presence
+
+use embuild::espidf;
+fn main() {
+    espidf::sysenv::output();
+}
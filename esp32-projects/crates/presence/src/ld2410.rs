@@ -0,0 +1,365 @@
+//! Parser for the LD2410 mmWave radar report frames
+//!
+//! The sensor streams frames of the form
+//! `F4 F3 F2 F1 | len (u16 LE) | payload | F8 F7 F6 F5` at 256000 baud.
+//! This module only deals with bytes, so it can be tested on the host.
+
+use core::fmt;
+
+const HEADER: [u8; 4] = [0xf4, 0xf3, 0xf2, 0xf1];
+const FOOTER: [u8; 4] = [0xf8, 0xf7, 0xf6, 0xf5];
+
+const DATA_ENGINEERING: u8 = 0x01;
+const DATA_BASIC: u8 = 0x02;
+const PAYLOAD_HEAD: u8 = 0xaa;
+const PAYLOAD_TAIL: u8 = 0x55;
+const PAYLOAD_CHECK: u8 = 0x00;
+
+/// type + head + target data + tail + check
+const MIN_PAYLOAD: usize = 13;
+/// The engineering mode frame is the largest at 35 bytes
+const MAX_PAYLOAD: usize = 64;
+const MAX_FRAME: usize = HEADER.len() + 2 + MAX_PAYLOAD + FOOTER.len();
+
+// === TargetState ===
+
+/// What the radar currently sees
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetState {
+    None,
+    Moving,
+    Stationary,
+    MovingAndStationary,
+}
+
+impl TargetState {
+    const fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::None),
+            1 => Some(Self::Moving),
+            2 => Some(Self::Stationary),
+            3 => Some(Self::MovingAndStationary),
+            _ => None,
+        }
+    }
+
+    /// Returns true if any target is detected
+    pub const fn is_present(self) -> bool {
+        !matches!(self, Self::None)
+    }
+}
+
+// === Report ===
+
+/// A target report sent by the radar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    state: TargetState,
+    moving_distance: u16,
+    moving_energy: u8,
+    stationary_distance: u16,
+    stationary_energy: u8,
+    detection_distance: u16,
+}
+
+impl Report {
+    /// Returns the detected target state
+    pub const fn state(&self) -> TargetState {
+        self.state
+    }
+
+    /// Returns true if any target is detected
+    pub const fn is_present(&self) -> bool {
+        self.state.is_present()
+    }
+
+    /// Returns the distance to the moving target, in cm
+    pub const fn moving_distance(&self) -> u16 {
+        self.moving_distance
+    }
+
+    /// Returns the energy of the moving target, from 0 to 100
+    pub const fn moving_energy(&self) -> u8 {
+        self.moving_energy
+    }
+
+    /// Returns the distance to the stationary target, in cm
+    pub const fn stationary_distance(&self) -> u16 {
+        self.stationary_distance
+    }
+
+    /// Returns the energy of the stationary target, from 0 to 100
+    pub const fn stationary_energy(&self) -> u8 {
+        self.stationary_energy
+    }
+
+    /// Returns the detection distance, in cm
+    pub const fn detection_distance(&self) -> u16 {
+        self.detection_distance
+    }
+}
+
+// === FrameError ===
+
+/// A type detailing why a frame was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The payload length is outside what a report frame can hold
+    InvalidLength(usize),
+    /// The frame did not end with the expected footer
+    InvalidFooter,
+    /// The payload is not a basic or engineering target report
+    UnknownDataType(u8),
+    /// The payload head, tail, check byte or target state is wrong
+    Malformed,
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength(len) => write!(f, "Invalid payload length {len}"),
+            Self::InvalidFooter => f.write_str("Frame footer missing"),
+            Self::UnknownDataType(kind) => write!(f, "Unknown data type {kind:#04x}"),
+            Self::Malformed => f.write_str("Malformed target report"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+// === Parsing ===
+
+/// Parses a complete frame, including header and footer
+pub fn parse_frame(frame: &[u8]) -> Result<Report, FrameError> {
+    if frame.len() < HEADER.len() + 2 + FOOTER.len() || frame[..HEADER.len()] != HEADER {
+        return Err(FrameError::Malformed);
+    }
+    let len = usize::from(u16::from_le_bytes([frame[4], frame[5]]));
+    let payload_end = HEADER.len() + 2 + len;
+    if frame.len() != payload_end + FOOTER.len() {
+        return Err(FrameError::InvalidLength(len));
+    }
+    if frame[payload_end..] != FOOTER {
+        return Err(FrameError::InvalidFooter);
+    }
+    parse_payload(&frame[HEADER.len() + 2..payload_end])
+}
+
+/// Parses the payload of a report frame
+pub fn parse_payload(payload: &[u8]) -> Result<Report, FrameError> {
+    if !(MIN_PAYLOAD..=MAX_PAYLOAD).contains(&payload.len()) {
+        return Err(FrameError::InvalidLength(payload.len()));
+    }
+    if payload[0] != DATA_BASIC && payload[0] != DATA_ENGINEERING {
+        return Err(FrameError::UnknownDataType(payload[0]));
+    }
+    // engineering frames add gate energies between the target data and the tail
+    let [.., tail, check] = payload else {
+        return Err(FrameError::Malformed);
+    };
+    if payload[1] != PAYLOAD_HEAD || *tail != PAYLOAD_TAIL || *check != PAYLOAD_CHECK {
+        return Err(FrameError::Malformed);
+    }
+
+    let data = &payload[2..11];
+    Ok(Report {
+        state: TargetState::from_byte(data[0]).ok_or(FrameError::Malformed)?,
+        moving_distance: u16::from_le_bytes([data[1], data[2]]),
+        moving_energy: data[3],
+        stationary_distance: u16::from_le_bytes([data[4], data[5]]),
+        stationary_energy: data[6],
+        detection_distance: u16::from_le_bytes([data[7], data[8]]),
+    })
+}
+
+// === FrameParser ===
+
+/// Incremental parser that picks frames out of the UART byte stream
+///
+/// Bytes before a header are dropped, so it resynchronises on its own after
+/// a corrupted frame or when started mid-frame.
+#[derive(Debug, Clone)]
+pub struct FrameParser {
+    buf: [u8; MAX_FRAME],
+    len: usize,
+}
+
+impl Default for FrameParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameParser {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME],
+            len: 0,
+        }
+    }
+
+    /// Feed a single byte, returns the result once a whole frame was received
+    pub fn push(&mut self, byte: u8) -> Option<Result<Report, FrameError>> {
+        if self.len < HEADER.len() {
+            if byte == HEADER[self.len] {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            } else if byte == HEADER[0] {
+                self.buf[0] = byte;
+                self.len = 1;
+            } else {
+                self.len = 0;
+            }
+            return None;
+        }
+
+        self.buf[self.len] = byte;
+        self.len += 1;
+
+        let header_len = HEADER.len() + 2;
+        if self.len < header_len {
+            return None;
+        }
+        let payload_len = usize::from(u16::from_le_bytes([self.buf[4], self.buf[5]]));
+        if payload_len > MAX_PAYLOAD {
+            self.len = 0;
+            return Some(Err(FrameError::InvalidLength(payload_len)));
+        }
+
+        let frame_len = header_len + payload_len + FOOTER.len();
+        if self.len < frame_len {
+            return None;
+        }
+        self.len = 0;
+        Some(parse_frame(&self.buf[..frame_len]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Target data: stationary target at 81 cm with energy 59, detection at 120 cm
+    const TARGET: [u8; 9] = [0x02, 0x51, 0x00, 0x00, 0x51, 0x00, 0x3b, 0x78, 0x00];
+
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let len = u16::try_from(payload.len()).unwrap();
+        let mut frame = HEADER.to_vec();
+        frame.extend_from_slice(&len.to_le_bytes());
+        frame.extend_from_slice(payload);
+        frame.extend_from_slice(&FOOTER);
+        frame
+    }
+
+    fn basic() -> Vec<u8> {
+        let mut payload = vec![DATA_BASIC, PAYLOAD_HEAD];
+        payload.extend_from_slice(&TARGET);
+        payload.extend_from_slice(&[PAYLOAD_TAIL, PAYLOAD_CHECK]);
+        frame(&payload)
+    }
+
+    fn engineering() -> Vec<u8> {
+        let mut payload = vec![DATA_ENGINEERING, PAYLOAD_HEAD];
+        payload.extend_from_slice(&TARGET);
+        // max gates, 9 moving and 9 stationary gate energies, light sensor and output pin
+        payload.extend_from_slice(&[8, 8]);
+        payload.extend_from_slice(&[10; 9]);
+        payload.extend_from_slice(&[20; 9]);
+        payload.extend_from_slice(&[0x80, 0x01]);
+        payload.extend_from_slice(&[PAYLOAD_TAIL, PAYLOAD_CHECK]);
+        frame(&payload)
+    }
+
+    const EXPECTED: Report = Report {
+        state: TargetState::Stationary,
+        moving_distance: 81,
+        moving_energy: 0,
+        stationary_distance: 81,
+        stationary_energy: 59,
+        detection_distance: 120,
+    };
+
+    fn push_all(parser: &mut FrameParser, bytes: &[u8]) -> Vec<Result<Report, FrameError>> {
+        bytes.iter().filter_map(|byte| parser.push(*byte)).collect()
+    }
+
+    #[test]
+    fn parses_basic_frame() {
+        let report = parse_frame(&basic()).unwrap();
+        assert_eq!(report, EXPECTED);
+        assert!(report.is_present());
+    }
+
+    #[test]
+    fn parses_engineering_frame() {
+        let frame = engineering();
+        assert_eq!(frame.len(), 6 + 35 + 4);
+        assert_eq!(parse_frame(&frame), Ok(EXPECTED));
+    }
+
+    #[test]
+    fn rejects_bad_header_and_footer() {
+        let mut frame = basic();
+        frame[0] = 0x00;
+        assert_eq!(parse_frame(&frame), Err(FrameError::Malformed));
+
+        let mut frame = basic();
+        let last = frame.len() - 1;
+        frame[last] = 0x00;
+        assert_eq!(parse_frame(&frame), Err(FrameError::InvalidFooter));
+    }
+
+    #[test]
+    fn rejects_length_mismatch() {
+        let mut frame = basic();
+        frame[4] = 0x0e;
+        assert_eq!(parse_frame(&frame), Err(FrameError::InvalidLength(14)));
+        assert_eq!(
+            parse_payload(&[DATA_BASIC, PAYLOAD_HEAD]),
+            Err(FrameError::InvalidLength(2))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_payload() {
+        let mut frame = basic();
+        frame[6] = 0x03;
+        assert_eq!(parse_frame(&frame), Err(FrameError::UnknownDataType(0x03)));
+
+        let mut frame = basic();
+        frame[8] = 0x07;
+        assert_eq!(parse_frame(&frame), Err(FrameError::Malformed));
+    }
+
+    #[test]
+    fn resyncs_after_garbage() {
+        let mut parser = FrameParser::new();
+        // garbage, including a partial header and a lone header start byte
+        let mut bytes = vec![0x00, 0xf4, 0xf3, 0x12, 0xf4, 0xff];
+        bytes.extend(basic());
+        bytes.extend([0x55, 0xf4]);
+        bytes.extend(engineering());
+
+        let results = push_all(&mut parser, &bytes);
+        assert_eq!(results, [Ok(EXPECTED), Ok(EXPECTED)]);
+    }
+
+    #[test]
+    fn resyncs_after_oversized_length() {
+        let mut parser = FrameParser::new();
+        let mut bytes = HEADER.to_vec();
+        bytes.extend([0xff, 0x00]);
+        let results = push_all(&mut parser, &bytes);
+        assert_eq!(results, [Err(FrameError::InvalidLength(0xff))]);
+        assert_eq!(push_all(&mut parser, &basic()), [Ok(EXPECTED)]);
+    }
+
+    #[test]
+    fn joins_frames_split_across_pushes() {
+        let mut parser = FrameParser::new();
+        let frame = basic();
+        let (first, second) = frame.split_at(9);
+        assert!(push_all(&mut parser, first).is_empty());
+        assert_eq!(push_all(&mut parser, second), [Ok(EXPECTED)]);
+    }
+}
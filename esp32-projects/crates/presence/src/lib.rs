@@ -0,0 +1,158 @@
+use core::time::Duration;
+
+pub mod ld2410;
+mod mmwave;
+mod pir;
+
+pub use mmwave::Ld2410;
+pub use pir::Pir;
+
+// === PresenceEvent ===
+
+/// Emitted when the debounced occupancy of a room changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresenceEvent {
+    Occupied,
+    Vacant,
+}
+
+// === Occupancy ===
+
+/// Turns a noisy detected/not detected signal into occupancy
+///
+/// A change of the raw input only counts once it has been stable for the
+/// debounce time. Once occupied, the room stays occupied until nothing has
+/// been detected for the hold time, so someone sitting still in front of a
+/// PIR does not switch the lights off.
+///
+/// Time is passed in by the caller (e.g. time since boot) to keep this free
+/// of any hardware or clock dependency.
+#[derive(Debug, Clone)]
+pub struct Occupancy {
+    debounce: Duration,
+    hold: Duration,
+    raw: bool,
+    raw_since: Duration,
+    last_detected: Option<Duration>,
+    occupied: bool,
+}
+
+impl Occupancy {
+    pub const fn new(debounce: Duration, hold: Duration) -> Self {
+        Self {
+            debounce,
+            hold,
+            raw: false,
+            raw_since: Duration::ZERO,
+            last_detected: None,
+            occupied: false,
+        }
+    }
+
+    /// Feed the latest raw detection state, returns an event if occupancy changed
+    pub fn update(&mut self, detected: bool, now: Duration) -> Option<PresenceEvent> {
+        if detected != self.raw {
+            self.raw = detected;
+            self.raw_since = now;
+        }
+
+        let stable = now.saturating_sub(self.raw_since) >= self.debounce;
+        if stable && self.raw {
+            self.last_detected = Some(now);
+        }
+
+        let occupied = self
+            .last_detected
+            .is_some_and(|seen| now.saturating_sub(seen) <= self.hold);
+        if occupied == self.occupied {
+            return None;
+        }
+
+        self.occupied = occupied;
+        if occupied {
+            Some(PresenceEvent::Occupied)
+        } else {
+            Some(PresenceEvent::Vacant)
+        }
+    }
+
+    /// Returns the current debounced occupancy
+    pub const fn is_occupied(&self) -> bool {
+        self.occupied
+    }
+
+    /// Change how long the room stays occupied after the last detection
+    pub fn set_hold(&mut self, hold: Duration) {
+        self.hold = hold;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn ignores_detections_shorter_than_the_debounce() {
+        let mut occupancy = Occupancy::new(ms(100), ms(1000));
+        assert_eq!(occupancy.update(true, ms(0)), None);
+        assert_eq!(occupancy.update(true, ms(99)), None);
+        assert_eq!(occupancy.update(false, ms(120)), None);
+        assert!(!occupancy.is_occupied());
+
+        assert_eq!(occupancy.update(true, ms(200)), None);
+        assert_eq!(
+            occupancy.update(true, ms(300)),
+            Some(PresenceEvent::Occupied)
+        );
+        assert!(occupancy.is_occupied());
+    }
+
+    #[test]
+    fn stays_occupied_for_the_hold_time() {
+        let mut occupancy = Occupancy::new(ms(100), ms(1000));
+        occupancy.update(true, ms(0));
+        assert_eq!(
+            occupancy.update(true, ms(100)),
+            Some(PresenceEvent::Occupied)
+        );
+        // the hold counts from the last stable detection
+        assert_eq!(occupancy.update(true, ms(400)), None);
+        assert_eq!(occupancy.update(false, ms(450)), None);
+        assert_eq!(occupancy.update(false, ms(1400)), None);
+        assert_eq!(
+            occupancy.update(false, ms(1401)),
+            Some(PresenceEvent::Vacant)
+        );
+        assert_eq!(occupancy.update(false, ms(5000)), None);
+    }
+
+    #[test]
+    fn a_short_detection_does_not_extend_the_hold() {
+        let mut occupancy = Occupancy::new(ms(100), ms(1000));
+        occupancy.update(true, ms(0));
+        occupancy.update(true, ms(100));
+        occupancy.update(false, ms(150));
+        // a blip while held is not stable, the room empties on time
+        occupancy.update(true, ms(1000));
+        assert_eq!(occupancy.update(false, ms(1050)), None);
+        assert_eq!(
+            occupancy.update(false, ms(1101)),
+            Some(PresenceEvent::Vacant)
+        );
+    }
+
+    #[test]
+    fn a_shorter_hold_applies_at_once() {
+        let mut occupancy = Occupancy::new(Duration::ZERO, ms(1000));
+        assert_eq!(occupancy.update(true, ms(0)), Some(PresenceEvent::Occupied));
+        occupancy.set_hold(ms(100));
+        assert_eq!(
+            occupancy.update(false, ms(200)),
+            Some(PresenceEvent::Vacant)
+        );
+    }
+}
@@ -0,0 +1,33 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    gpio::{InputPin, PinDriver},
+    prelude::Peripherals,
+};
+use log::info;
+
+use presence::{Occupancy, Pir, PresenceEvent};
+
+fn main() -> Result<()> {
+    esp_idf_svc::sys::link_patches();
+    esp_idf_svc::log::EspLogger::initialize_default();
+
+    let peripherals = Peripherals::take().unwrap();
+    let pin = PinDriver::input(peripherals.pins.gpio4.downgrade_input())?;
+
+    let occupancy = Occupancy::new(Duration::from_millis(200), Duration::from_secs(120));
+    let mut pir = Pir::new(pin, occupancy);
+    info!("PIR setup on pin 4");
+
+    let start = Instant::now();
+    loop {
+        match pir.poll(start.elapsed()) {
+            Ok(Some(PresenceEvent::Occupied)) => info!("room occupied"),
+            Ok(Some(PresenceEvent::Vacant)) => info!("room vacant"),
+            Ok(None) => {}
+            Err(err) => info!("error during read: {:?}", err),
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,161 @@
+use core::time::Duration;
+use embedded_io::{Read, ReadReady};
+
+use crate::{
+    ld2410::{FrameParser, Report},
+    Occupancy, PresenceEvent,
+};
+
+/// An LD2410 mmWave radar on a UART
+///
+/// Unlike a PIR the radar also sees people sitting still, so a short hold
+/// time is usually enough. The radar streams a report about every 100 ms,
+/// once none arrived for the report timeout the last one is dropped and
+/// nobody counts as detected, e.g. after a loose wire.
+pub struct Ld2410<U: Read + ReadReady> {
+    uart: U,
+    parser: FrameParser,
+    occupancy: Occupancy,
+    last_report: Option<Report>,
+    /// when `last_report` was received
+    received: Duration,
+    report_timeout: Duration,
+    invalid_frames: u32,
+}
+
+impl<U: Read + ReadReady> Ld2410<U> {
+    /// Create a new `Ld2410`, the UART has to be configured for 256000 baud 8N1
+    pub const fn new(uart: U, occupancy: Occupancy) -> Self {
+        Self {
+            uart,
+            parser: FrameParser::new(),
+            occupancy,
+            last_report: None,
+            received: Duration::ZERO,
+            report_timeout: Duration::from_secs(1),
+            invalid_frames: 0,
+        }
+    }
+
+    /// Use a different report timeout than 1 s
+    #[must_use]
+    pub const fn with_report_timeout(mut self, timeout: Duration) -> Self {
+        self.report_timeout = timeout;
+        self
+    }
+
+    /// Drain the bytes received so far without blocking, returns an event if occupancy changed
+    pub fn poll(&mut self, now: Duration) -> Result<Option<PresenceEvent>, U::Error> {
+        let mut buf = [0; 32];
+        while self.uart.read_ready()? {
+            let read = self.uart.read(&mut buf)?;
+            for &byte in &buf[..read] {
+                match self.parser.push(byte) {
+                    Some(Ok(report)) => {
+                        self.last_report = Some(report);
+                        self.received = now;
+                    }
+                    // corrupted frames are expected on a noisy line, the parser resyncs on the next header
+                    Some(Err(_)) => self.invalid_frames = self.invalid_frames.wrapping_add(1),
+                    None => {}
+                }
+            }
+        }
+
+        if now.saturating_sub(self.received) > self.report_timeout {
+            self.last_report = None;
+        }
+        let detected = self.last_report.is_some_and(|report| report.is_present());
+        Ok(self.occupancy.update(detected, now))
+    }
+
+    /// Returns the last valid report, with target distances and energies,
+    /// `None` if none arrived within the report timeout
+    pub const fn last_report(&self) -> Option<Report> {
+        self.last_report
+    }
+
+    /// Returns how many corrupted frames were dropped
+    pub const fn invalid_frames(&self) -> u32 {
+        self.invalid_frames
+    }
+
+    /// Returns the current debounced occupancy
+    pub const fn is_occupied(&self) -> bool {
+        self.occupancy.is_occupied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+    use embedded_io::ErrorType;
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    /// Basic mode frame with a stationary target at 81 cm
+    const PRESENT: [u8; 23] = [
+        0xf4, 0xf3, 0xf2, 0xf1, 0x0d, 0x00, 0x02, 0xaa, 0x02, 0x51, 0x00, 0x00, 0x51, 0x00, 0x3b,
+        0x78, 0x00, 0x55, 0x00, 0xf8, 0xf7, 0xf6, 0xf5,
+    ];
+
+    /// Bytes received and not read yet
+    #[derive(Default)]
+    struct FakeUart(VecDeque<u8>);
+
+    impl ErrorType for FakeUart {
+        type Error = Infallible;
+    }
+
+    impl Read for FakeUart {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            let read = buf.len().min(self.0.len());
+            for (slot, byte) in buf.iter_mut().zip(self.0.drain(..read)) {
+                *slot = byte;
+            }
+            Ok(read)
+        }
+    }
+
+    impl ReadReady for FakeUart {
+        fn read_ready(&mut self) -> Result<bool, Infallible> {
+            Ok(!self.0.is_empty())
+        }
+    }
+
+    const fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn drops_the_report_once_the_radar_goes_quiet() {
+        let occupancy = Occupancy::new(Duration::ZERO, ms(500));
+        let mut radar = Ld2410::new(FakeUart::default(), occupancy);
+        radar.uart.0.extend(PRESENT);
+        assert_eq!(radar.poll(ms(0)), Ok(Some(PresenceEvent::Occupied)));
+        assert!(radar.last_report().is_some());
+
+        // within the timeout the last report still counts
+        assert_eq!(radar.poll(ms(1000)), Ok(None));
+        assert!(radar.last_report().is_some());
+
+        assert_eq!(radar.poll(ms(1001)), Ok(None));
+        assert_eq!(radar.last_report(), None);
+        assert_eq!(radar.poll(ms(1501)), Ok(Some(PresenceEvent::Vacant)));
+    }
+
+    #[test]
+    fn new_reports_restart_the_timeout() {
+        let occupancy = Occupancy::new(Duration::ZERO, Duration::ZERO);
+        let mut radar = Ld2410::new(FakeUart::default(), occupancy).with_report_timeout(ms(200));
+        for now in (0..1000).step_by(100) {
+            radar.uart.0.extend(PRESENT);
+            radar.poll(ms(now)).unwrap();
+            assert!(radar.is_occupied(), "at {now} ms");
+        }
+        assert_eq!(radar.poll(ms(1100)), Ok(None));
+        assert_eq!(radar.poll(ms(1101)), Ok(Some(PresenceEvent::Vacant)));
+        assert_eq!(radar.invalid_frames(), 0);
+    }
+}
@@ -0,0 +1,27 @@
+use core::time::Duration;
+use embedded_hal::digital::InputPin;
+
+use crate::{Occupancy, PresenceEvent};
+
+/// A PIR motion sensor on a digital input, high while motion is detected
+pub struct Pir<P: InputPin> {
+    pin: P,
+    occupancy: Occupancy,
+}
+
+impl<P: InputPin> Pir<P> {
+    pub const fn new(pin: P, occupancy: Occupancy) -> Self {
+        Self { pin, occupancy }
+    }
+
+    /// Sample the pin, returns an event if occupancy changed
+    pub fn poll(&mut self, now: Duration) -> Result<Option<PresenceEvent>, P::Error> {
+        let detected = self.pin.is_high()?;
+        Ok(self.occupancy.update(detected, now))
+    }
+
+    /// Returns the current debounced occupancy
+    pub const fn is_occupied(&self) -> bool {
+        self.occupancy.is_occupied()
+    }
+}
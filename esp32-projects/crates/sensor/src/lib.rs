@@ -0,0 +1,16 @@
+//! crate sensor
+
+/// A trait for reading data from any sensor in the workspace
+///
+/// Each sensor crate keeps its own reading and error types, this trait only
+/// gives automations a single way to poll them without caring which sensor is
+/// attached (e.g. a DHT11 for temperature or a BH1750 for daylight).
+pub trait Sensor {
+    /// The value returned by a successful read
+    type Reading;
+    /// The error returned when a read fails
+    type Error;
+
+    /// Reads data from the sensor and returns a `Reading`
+    fn read(&mut self) -> Result<Self::Reading, Self::Error>;
+}
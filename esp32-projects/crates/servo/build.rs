@@ -0,0 +1,6 @@
+//! crate servo
+
+use embuild::espidf;
+fn main() {
+    espidf::sysenv::output();
+}
@@ -0,0 +1,420 @@
+//! Hobby servos like the SG90 or MG996R on any PWM output
+//!
+//! On the ESP32 a `LedcDriver` running at 50 Hz works as the PWM output,
+//! use a high timer resolution, e.g. 14 bits, for a fine angle resolution.
+
+use core::{fmt, time::Duration};
+use embedded_hal::{
+    delay::DelayNs,
+    pwm::{self, SetDutyCycle},
+};
+
+mod sweep;
+
+pub use sweep::{Easing, Sweep};
+
+// === ServoConfig ===
+
+/// The pulse widths and angles of a servo model
+///
+/// Servos expect a pulse every period, the width of the pulse picks the
+/// angle. The range of pulse widths varies between models and even between
+/// servos of the same model, so check the end stops before narrowing `min_pulse`
+/// and `max_pulse` down to the full travel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServoConfig {
+    min_pulse: Duration,
+    max_pulse: Duration,
+    range: f32,
+    min_angle: f32,
+    max_angle: f32,
+    period: Duration,
+}
+
+impl ServoConfig {
+    /// Create a new `ServoConfig` moving over `range` degrees from `min_pulse` to
+    /// `max_pulse`, with a 20 ms period and the full range allowed
+    pub const fn new(min_pulse: Duration, max_pulse: Duration, range: f32) -> Self {
+        Self {
+            min_pulse,
+            max_pulse,
+            range,
+            min_angle: 0.0,
+            max_angle: range,
+            period: Duration::from_millis(20),
+        }
+    }
+
+    /// The SG90 micro servo
+    pub const fn sg90() -> Self {
+        Self::new(
+            Duration::from_micros(500),
+            Duration::from_micros(2400),
+            180.0,
+        )
+    }
+
+    /// The MG996R metal gear servo
+    pub const fn mg996r() -> Self {
+        Self::new(
+            Duration::from_micros(500),
+            Duration::from_micros(2500),
+            180.0,
+        )
+    }
+
+    /// Only allow angles between `min` and `max`, e.g. to keep a vent flap off its frame.
+    /// Fails with `InvalidLimits` unless both are within 0° and the range.
+    pub const fn with_limits(mut self, min: f32, max: f32) -> Result<Self, ServoError> {
+        let (min, max) = if min > max { (max, min) } else { (min, max) };
+        // written as a negation so NaN is rejected as well
+        if !(min >= 0.0 && max <= self.range) {
+            return Err(ServoError::InvalidLimits {
+                min,
+                max,
+                range: self.range,
+            });
+        }
+        self.min_angle = min;
+        self.max_angle = max;
+        Ok(self)
+    }
+
+    /// Use a different PWM period, which has to match the frequency of the PWM output
+    #[must_use]
+    pub const fn with_period(mut self, period: Duration) -> Self {
+        self.period = period;
+        self
+    }
+
+    /// Returns the pulse width at 0°
+    pub const fn min_pulse(&self) -> Duration {
+        self.min_pulse
+    }
+
+    /// Returns the pulse width at the end of the range
+    pub const fn max_pulse(&self) -> Duration {
+        self.max_pulse
+    }
+
+    /// Returns the degrees covered from `min_pulse` to `max_pulse`
+    pub const fn range(&self) -> f32 {
+        self.range
+    }
+
+    /// Returns the smallest allowed angle
+    pub const fn min_angle(&self) -> f32 {
+        self.min_angle
+    }
+
+    /// Returns the largest allowed angle
+    pub const fn max_angle(&self) -> f32 {
+        self.max_angle
+    }
+
+    /// Returns the PWM period
+    pub const fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Returns true if `angle` is within the limits
+    pub fn contains(&self, angle: f32) -> bool {
+        (self.min_angle..=self.max_angle).contains(&angle)
+    }
+
+    /// Returns the pulse width for `angle`, or `AngleOutOfRange` if it is outside the limits
+    pub fn pulse_width(&self, angle: f32) -> Result<Duration, ServoError> {
+        if !self.contains(angle) {
+            return Err(ServoError::AngleOutOfRange {
+                angle,
+                min: self.min_angle,
+                max: self.max_angle,
+            });
+        }
+        // a range that is not positive only allows 0°, which is `min_pulse`
+        if self.range <= 0.0 {
+            return Ok(self.min_pulse);
+        }
+        let span = self.max_pulse.saturating_sub(self.min_pulse).as_micros();
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_precision_loss,
+            clippy::cast_sign_loss
+        )]
+        let offset = ((span as f32 * angle / self.range).round() as u128).min(span);
+        Ok(self.min_pulse + Duration::from_micros(u64::try_from(offset).unwrap_or(u64::MAX)))
+    }
+
+    /// Returns the duty cycle for `angle` on a PWM output with `max_duty` as full duty
+    pub fn duty(&self, angle: f32, max_duty: u16) -> Result<u16, ServoError> {
+        let fraction = self.pulse_width(angle)?.as_secs_f32() / self.period.as_secs_f32();
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let duty = (f32::from(max_duty) * fraction.min(1.0)).round() as u16;
+        Ok(duty)
+    }
+}
+
+// === ServoError ===
+
+/// A type detailing the errors that can happen while driving a servo
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServoError {
+    /// The angle is outside the limits of the `ServoConfig`
+    AngleOutOfRange { angle: f32, min: f32, max: f32 },
+    /// The limits passed to `ServoConfig::with_limits` are outside of its range
+    InvalidLimits { min: f32, max: f32, range: f32 },
+    /// Received a low-level error from the HAL while setting the duty cycle
+    Pwm(pwm::ErrorKind),
+}
+
+impl fmt::Display for ServoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AngleOutOfRange { angle, min, max } => {
+                write!(f, "angle {angle}° outside of {min}°..={max}°")
+            }
+            Self::InvalidLimits { min, max, range } => {
+                write!(f, "limits {min}°..={max}° outside of 0°..={range}°")
+            }
+            Self::Pwm(kind) => write!(f, "HAL pwm error: {kind:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ServoError {}
+
+// === Servo ===
+
+/// A servo on a PWM output
+///
+/// The servo starts detached, without pulses, and attaches on the first
+/// angle set. Detaching stops a servo from jittering and buzzing while it
+/// holds still, as long as the load does not push it out of position.
+pub struct Servo<P: SetDutyCycle> {
+    pwm: P,
+    config: ServoConfig,
+    angle: Option<f32>,
+    attached: bool,
+    sweep: Option<Sweep>,
+    detach_after: Option<Duration>,
+}
+
+impl<P: SetDutyCycle> Servo<P> {
+    /// Create a new `Servo`, detached until an angle is set
+    pub fn new(pwm: P, config: ServoConfig) -> Result<Self, ServoError> {
+        let mut servo = Self {
+            pwm,
+            config,
+            angle: None,
+            attached: true,
+            sweep: None,
+            detach_after: None,
+        };
+        servo.detach()?;
+        Ok(servo)
+    }
+
+    /// Detach automatically once a sweep has been at rest for `delay`, see `poll`
+    #[must_use]
+    pub const fn with_detach_after(mut self, delay: Duration) -> Self {
+        self.detach_after = Some(delay);
+        self
+    }
+
+    /// Returns the configuration
+    pub const fn config(&self) -> &ServoConfig {
+        &self.config
+    }
+
+    /// Returns the last angle set, `None` before the first one.
+    /// A detached servo may have been pushed away from it.
+    pub const fn angle(&self) -> Option<f32> {
+        self.angle
+    }
+
+    /// Returns true while the servo receives pulses
+    pub const fn is_attached(&self) -> bool {
+        self.attached
+    }
+
+    /// Returns true while a sweep started with `start_sweep` is running
+    pub const fn is_sweeping(&self) -> bool {
+        self.sweep.is_some()
+    }
+
+    /// Move to `angle` as fast as the servo can, cancelling a running sweep
+    pub fn set_angle(&mut self, angle: f32) -> Result<(), ServoError> {
+        self.sweep = None;
+        self.write(angle)
+    }
+
+    /// Stop sending pulses, the servo goes limp until the next angle is set
+    pub fn detach(&mut self) -> Result<(), ServoError> {
+        self.sweep = None;
+        self.pwm
+            .set_duty_cycle_fully_off()
+            .map_err(|err| ServoError::Pwm(pwm::Error::kind(&err)))?;
+        self.attached = false;
+        Ok(())
+    }
+
+    /// Sweep to `angle` over `duration`, blocking until done.
+    /// Starts from the last angle set, or jumps to `angle` if there is none.
+    pub fn sweep<D: DelayNs>(
+        &mut self,
+        angle: f32,
+        duration: Duration,
+        easing: Easing,
+        delay: &mut D,
+    ) -> Result<(), ServoError> {
+        self.config.pulse_width(angle)?;
+        self.sweep = None;
+        let sweep = Sweep::new(
+            self.angle.unwrap_or(angle),
+            angle,
+            Duration::ZERO,
+            duration,
+            easing,
+        );
+
+        // the servo only picks up a new angle once per period
+        let period = self.config.period;
+        let mut now = Duration::ZERO;
+        loop {
+            self.write(sweep.angle_at(now))?;
+            if sweep.is_done(now) {
+                return Ok(());
+            }
+            delay.delay_us(micros(period));
+            now += period;
+        }
+    }
+
+    /// Start sweeping to `angle` over `duration` without blocking, `poll` moves the servo.
+    /// Starts from the last angle set, or jumps to `angle` if there is none.
+    pub fn start_sweep(
+        &mut self,
+        angle: f32,
+        duration: Duration,
+        easing: Easing,
+        now: Duration,
+    ) -> Result<(), ServoError> {
+        self.config.pulse_width(angle)?;
+        let from = self.angle.unwrap_or(angle);
+        self.sweep = Some(Sweep::new(from, angle, now, duration, easing));
+        Ok(())
+    }
+
+    /// Move a running sweep on to the angle due at `now`. Returns true while the sweep is running.
+    /// Keep polling at rest as well, so the servo detaches after the `with_detach_after` delay.
+    pub fn poll(&mut self, now: Duration) -> Result<bool, ServoError> {
+        let Some(sweep) = self.sweep else {
+            return Ok(false);
+        };
+        if !sweep.is_done(now) {
+            self.write(sweep.angle_at(now))?;
+            return Ok(true);
+        }
+
+        if self.angle != Some(sweep.to()) || !self.attached {
+            self.write(sweep.to())?;
+        }
+        match self.detach_after {
+            Some(delay) if now.saturating_sub(sweep.end()) >= delay => self.detach()?,
+            Some(_) => {}
+            None => self.sweep = None,
+        }
+        Ok(false)
+    }
+
+    /// Stop using the servo and get the PWM output back
+    pub fn into_inner(self) -> P {
+        self.pwm
+    }
+
+    fn write(&mut self, angle: f32) -> Result<(), ServoError> {
+        let duty = self.config.duty(angle, self.pwm.max_duty_cycle())?;
+        self.pwm
+            .set_duty_cycle(duty)
+            .map_err(|err| ServoError::Pwm(pwm::Error::kind(&err)))?;
+        self.angle = Some(angle);
+        self.attached = true;
+        Ok(())
+    }
+}
+
+/// Whole microseconds in `duration`, saturating at `u32::MAX`
+fn micros(duration: Duration) -> u32 {
+    u32::try_from(duration.as_micros()).unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_angles_to_pulse_widths() {
+        let config = ServoConfig::sg90();
+        assert_eq!(config.pulse_width(0.0), Ok(Duration::from_micros(500)));
+        assert_eq!(config.pulse_width(90.0), Ok(Duration::from_micros(1450)));
+        assert_eq!(config.pulse_width(180.0), Ok(Duration::from_micros(2400)));
+        // rounded to whole microseconds
+        assert_eq!(config.pulse_width(1.0), Ok(Duration::from_micros(511)));
+    }
+
+    #[test]
+    fn rejects_angles_outside_the_limits() {
+        let config = ServoConfig::sg90().with_limits(10.0, 170.0).unwrap();
+        assert!(config.pulse_width(10.0).is_ok());
+        assert!(config.pulse_width(170.0).is_ok());
+        for angle in [9.9, 170.1, -1.0, f32::NAN] {
+            assert!(matches!(
+                config.pulse_width(angle),
+                Err(ServoError::AngleOutOfRange { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn orders_the_limits() {
+        let config = ServoConfig::sg90().with_limits(170.0, 10.0).unwrap();
+        assert_eq!((config.min_angle(), config.max_angle()), (10.0, 170.0));
+    }
+
+    #[test]
+    fn rejects_limits_outside_the_range() {
+        for (min, max) in [(-90.0, 90.0), (0.0, 181.0), (f32::NAN, 90.0)] {
+            assert!(matches!(
+                ServoConfig::sg90().with_limits(min, max),
+                Err(ServoError::InvalidLimits { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn survives_a_range_without_width() {
+        let min = Duration::from_micros(1500);
+        let config = ServoConfig::new(min, Duration::from_micros(1500), 0.0);
+        assert_eq!(config.pulse_width(0.0), Ok(min));
+        assert!(config.pulse_width(1.0).is_err());
+
+        let config = ServoConfig::new(min, Duration::from_micros(2000), f32::NAN);
+        assert!(config.pulse_width(0.0).is_err());
+    }
+
+    #[test]
+    fn maps_angles_to_duty_cycles() {
+        let config = ServoConfig::sg90();
+        // 500 µs of a 20 ms period
+        assert_eq!(config.duty(0.0, 16383), Ok(410));
+        assert_eq!(config.duty(180.0, 16383), Ok(1966));
+        assert_eq!(config.duty(90.0, 1000), Ok(73));
+        assert!(config.duty(181.0, 16383).is_err());
+    }
+
+    #[test]
+    fn caps_the_duty_cycle_at_full_duty() {
+        let config = ServoConfig::sg90().with_period(Duration::from_micros(2000));
+        assert_eq!(config.duty(180.0, 1000), Ok(1000));
+    }
+}
@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    delay::Delay,
+    ledc::{
+        config::{Resolution, TimerConfig},
+        LedcDriver, LedcTimerDriver,
+    },
+    prelude::Peripherals,
+    units::Hertz,
+};
+use log::info;
+
+use servo::{Easing, Servo, ServoConfig};
+
+fn main() -> Result<()> {
+    esp_idf_svc::sys::link_patches();
+    esp_idf_svc::log::EspLogger::initialize_default();
+
+    let peripherals = Peripherals::take().unwrap();
+    let timer = LedcTimerDriver::new(
+        peripherals.ledc.timer0,
+        &TimerConfig::new()
+            .frequency(Hertz(50))
+            .resolution(Resolution::Bits14),
+    )?;
+    let pwm = LedcDriver::new(peripherals.ledc.channel0, &timer, peripherals.pins.gpio4)?;
+
+    // keep the vent flap off the frame at both ends
+    let config = ServoConfig::sg90().with_limits(10.0, 170.0)?;
+    let mut vent = Servo::new(pwm, config)?;
+    let mut delay = Delay::new_default();
+    info!("SG90 setup on pin 4");
+
+    vent.set_angle(10.0)?;
+    loop {
+        info!("opening vent");
+        vent.sweep(170.0, Duration::from_secs(2), Easing::EaseInOut, &mut delay)?;
+        vent.detach()?;
+        std::thread::sleep(Duration::from_secs(10));
+
+        info!("closing vent");
+        vent.sweep(10.0, Duration::from_secs(2), Easing::EaseInOut, &mut delay)?;
+        vent.detach()?;
+        std::thread::sleep(Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,167 @@
+use core::time::Duration;
+
+/// How the angle moves from the start to the end of a sweep
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Easing {
+    /// Constant speed, starts and stops abruptly
+    Linear,
+    /// Start slowly and speed up
+    EaseIn,
+    /// Start fast and slow down into the end
+    EaseOut,
+    /// Speed up and slow down, so the servo neither jerks nor slams
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    /// Map the progress of a sweep from 0.0 to 1.0 to the fraction of the angle covered
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => t * (2.0 - t),
+            Self::EaseInOut => t * t * 2.0f32.mul_add(-t, 3.0),
+        }
+    }
+}
+
+/// A timed move from one angle to another
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sweep {
+    from: f32,
+    to: f32,
+    start: Duration,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl Sweep {
+    /// Create a new `Sweep` starting at `start` and taking `duration`
+    pub const fn new(
+        from: f32,
+        to: f32,
+        start: Duration,
+        duration: Duration,
+        easing: Easing,
+    ) -> Self {
+        Self {
+            from,
+            to,
+            start,
+            duration,
+            easing,
+        }
+    }
+
+    /// Returns the angle the sweep starts at
+    pub const fn from(&self) -> f32 {
+        self.from
+    }
+
+    /// Returns the angle the sweep ends at
+    pub const fn to(&self) -> f32 {
+        self.to
+    }
+
+    /// Returns the time the sweep ends
+    pub fn end(&self) -> Duration {
+        self.start + self.duration
+    }
+
+    /// Returns the angle at `now`, holding the start and end angle outside the sweep
+    pub fn angle_at(&self, now: Duration) -> f32 {
+        if now >= self.end() {
+            return self.to;
+        }
+        let elapsed = now.saturating_sub(self.start).as_secs_f32();
+        let progress = self.easing.apply(elapsed / self.duration.as_secs_f32());
+        (self.to - self.from).mul_add(progress, self.from)
+    }
+
+    /// Returns true once the sweep has reached its end angle
+    pub fn is_done(&self, now: Duration) -> bool {
+        now >= self.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EASINGS: [Easing; 4] = [
+        Easing::Linear,
+        Easing::EaseIn,
+        Easing::EaseOut,
+        Easing::EaseInOut,
+    ];
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn easings_start_at_0_and_end_at_1() {
+        for easing in EASINGS {
+            assert!(close(easing.apply(0.0), 0.0), "{easing:?}");
+            assert!(close(easing.apply(1.0), 1.0), "{easing:?}");
+            // progress outside of the sweep is clamped
+            assert!(close(easing.apply(-1.0), 0.0), "{easing:?}");
+            assert!(close(easing.apply(2.0), 1.0), "{easing:?}");
+        }
+    }
+
+    #[test]
+    fn easings_never_move_backwards() {
+        for easing in EASINGS {
+            let fractions: Vec<f32> = (0..=100u8)
+                .map(|t| easing.apply(f32::from(t) / 100.0))
+                .collect();
+            assert!(fractions.windows(2).all(|w| w[1] >= w[0]), "{easing:?}");
+        }
+    }
+
+    #[test]
+    fn easings_have_their_shape() {
+        assert!(close(Easing::Linear.apply(0.25), 0.25));
+        assert!(Easing::EaseIn.apply(0.25) < 0.25);
+        assert!(Easing::EaseOut.apply(0.25) > 0.25);
+        assert!(close(Easing::EaseInOut.apply(0.5), 0.5));
+        assert!(Easing::EaseInOut.apply(0.1) < 0.1);
+        assert!(Easing::EaseInOut.apply(0.9) > 0.9);
+    }
+
+    #[test]
+    fn sweeps_between_the_angles() {
+        let start = Duration::from_secs(1);
+        let sweep = Sweep::new(20.0, 120.0, start, Duration::from_secs(2), Easing::Linear);
+        assert_eq!(sweep.end(), Duration::from_secs(3));
+        assert!(close(sweep.angle_at(Duration::ZERO), 20.0));
+        assert!(close(sweep.angle_at(start), 20.0));
+        assert!(close(sweep.angle_at(Duration::from_millis(1500)), 45.0));
+        assert!(close(sweep.angle_at(Duration::from_secs(2)), 70.0));
+        assert!(close(sweep.angle_at(Duration::from_secs(5)), 120.0));
+        assert!(!sweep.is_done(Duration::from_millis(2999)));
+        assert!(sweep.is_done(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn sweeps_downwards() {
+        let sweep = Sweep::new(
+            90.0,
+            0.0,
+            Duration::ZERO,
+            Duration::from_secs(1),
+            Easing::EaseInOut,
+        );
+        assert!(close(sweep.angle_at(Duration::from_millis(500)), 45.0));
+    }
+
+    #[test]
+    fn an_instant_sweep_is_done_right_away() {
+        let sweep = Sweep::new(0.0, 90.0, Duration::ZERO, Duration::ZERO, Easing::Linear);
+        assert!(sweep.is_done(Duration::ZERO));
+        assert!(close(sweep.angle_at(Duration::ZERO), 90.0));
+    }
+}
@@ -0,0 +1,6 @@
+//! crate soil-moisture
+
+use embuild::espidf;
+fn main() {
+    espidf::sysenv::output();
+}
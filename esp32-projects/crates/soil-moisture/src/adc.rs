@@ -0,0 +1,36 @@
+use core::borrow::Borrow;
+use esp_idf_svc::{
+    hal::adc::{
+        attenuation::DB_11,
+        oneshot::{
+            config::{AdcChannelConfig, Calibration},
+            AdcChannelDriver, AdcDriver,
+        },
+        ADCPin,
+    },
+    sys::EspError,
+};
+
+use crate::MillivoltSource;
+
+/// Channel configuration for a capacitive probe
+///
+/// 11 dB attenuation covers the probe's output of up to ~3 V and curve
+/// fitting calibration makes `read` return millivolts.
+pub fn channel_config() -> AdcChannelConfig {
+    AdcChannelConfig {
+        attenuation: DB_11,
+        calibration: Calibration::Curve,
+        ..Default::default()
+    }
+}
+
+impl<'d, T: ADCPin, M: Borrow<AdcDriver<'d, T::Adc>>> MillivoltSource
+    for AdcChannelDriver<'d, T, M>
+{
+    type Error = EspError;
+
+    fn read_mv(&mut self) -> Result<u16, EspError> {
+        self.read()
+    }
+}
@@ -0,0 +1,98 @@
+//! Hardware independent conversion from probe voltage to moisture
+
+/// The probe voltage measured in completely dry and completely wet soil
+///
+/// Capacitive probes read lower the wetter the soil is, but the points can
+/// be given in either order.
+#[derive(Debug, Clone, Copy)]
+pub struct Calibration {
+    dry: u16,
+    wet: u16,
+}
+
+impl Calibration {
+    /// Create a new `Calibration` from the dry and wet readings, in millivolts
+    pub const fn new(dry: u16, wet: u16) -> Self {
+        Self { dry, wet }
+    }
+
+    /// Maps a voltage linearly between the calibration points to 0.0 (dry) - 100.0 (wet)
+    ///
+    /// Readings outside the calibration range are clamped.
+    pub fn moisture(&self, millivolts: u16) -> f32 {
+        if self.dry == self.wet {
+            return 0.0;
+        }
+        let span = f32::from(self.wet) - f32::from(self.dry);
+        ((f32::from(millivolts) - f32::from(self.dry)) / span * 100.0).clamp(0.0, 100.0)
+    }
+}
+
+/// Averages the samples, rounding to the nearest millivolt
+///
+/// With three or more samples the lowest and highest are dropped first, which
+/// removes single spikes from the ADC.
+pub fn average(samples: &[u16]) -> Option<u16> {
+    let (&min, &max) = (samples.iter().min()?, samples.iter().max()?);
+    let (sum, count) = if samples.len() >= 3 {
+        (
+            samples.iter().map(|&s| u64::from(s)).sum::<u64>() - u64::from(min) - u64::from(max),
+            samples.len() as u64 - 2,
+        )
+    } else {
+        (
+            samples.iter().map(|&s| u64::from(s)).sum::<u64>(),
+            samples.len() as u64,
+        )
+    };
+    // the mean of u16 values always fits a u16
+    u16::try_from((sum + count / 2) / count).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A capacitive probe, reading lower in wet soil
+    const PROBE: Calibration = Calibration::new(2800, 1200);
+
+    #[test]
+    fn maps_dry_wet_and_midpoint() {
+        assert!((PROBE.moisture(2800) - 0.0).abs() < f32::EPSILON);
+        assert!((PROBE.moisture(1200) - 100.0).abs() < f32::EPSILON);
+        assert!((PROBE.moisture(2000) - 50.0).abs() < 1e-4);
+        assert!((PROBE.moisture(2400) - 25.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn clamps_outside_the_range() {
+        assert!((PROBE.moisture(3300) - 0.0).abs() < f32::EPSILON);
+        assert!((PROBE.moisture(0) - 100.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn accepts_inverted_calibration() {
+        // a resistive probe reads higher in wet soil
+        let probe = Calibration::new(1200, 2800);
+        assert!((probe.moisture(1200) - 0.0).abs() < f32::EPSILON);
+        assert!((probe.moisture(2800) - 100.0).abs() < f32::EPSILON);
+        assert!((probe.moisture(2000) - 50.0).abs() < 1e-4);
+        assert!((probe.moisture(500) - 0.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn equal_points_read_dry() {
+        assert!((Calibration::new(1500, 1500).moisture(1000) - 0.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn averages_samples() {
+        assert_eq!(average(&[]), None);
+        assert_eq!(average(&[1000]), Some(1000));
+        // two samples are both kept, rounding to nearest
+        assert_eq!(average(&[1000, 1003]), Some(1002));
+        // the spike and the lowest sample are dropped
+        assert_eq!(average(&[1000, 1010, 3300, 1020, 900]), Some(1010));
+        assert_eq!(average(&[u16::MAX; 4]), Some(u16::MAX));
+    }
+}
@@ -0,0 +1,97 @@
+use core::fmt;
+use sensor::Sensor;
+
+#[cfg(target_os = "espidf")]
+mod adc;
+pub mod calibration;
+
+#[cfg(target_os = "espidf")]
+pub use adc::channel_config;
+pub use calibration::{average, Calibration};
+
+/// Upper bound on samples averaged per read, they are kept on the stack
+const MAX_SAMPLES: usize = 32;
+
+// === MillivoltSource ===
+
+/// A calibrated analog input that returns millivolts
+///
+/// Implemented for the ESP-IDF oneshot ADC channel, the indirection keeps
+/// `SoilMoisture` free of the concrete ADC driver.
+pub trait MillivoltSource {
+    type Error;
+
+    /// Take a single calibrated sample, in millivolts
+    fn read_mv(&mut self) -> Result<u16, Self::Error>;
+}
+
+// === Reading ===
+
+/// A sensor reading
+#[derive(Debug, Clone, Copy)]
+pub struct Reading {
+    moisture: f32,
+    millivolts: u16,
+}
+
+impl Reading {
+    /// Returns the soil moisture, as a percentage value from 0.0 (dry) to 100.0 (wet)
+    pub const fn moisture(&self) -> f32 {
+        self.moisture
+    }
+
+    /// Returns the averaged probe voltage, in millivolts
+    pub const fn millivolts(&self) -> u16 {
+        self.millivolts
+    }
+}
+
+impl fmt::Display for Reading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Moisture: {}% ({} mV)", self.moisture, self.millivolts)
+    }
+}
+
+// === SoilMoisture ===
+
+/// A capacitive soil moisture probe
+pub struct SoilMoisture<S: MillivoltSource> {
+    source: S,
+    calibration: Calibration,
+    samples: usize,
+}
+
+impl<S: MillivoltSource> SoilMoisture<S> {
+    /// Create a new `SoilMoisture` averaging `samples` ADC readings (1 to 32) per read
+    pub fn new(source: S, calibration: Calibration, samples: usize) -> Self {
+        Self {
+            source,
+            calibration,
+            samples: samples.clamp(1, MAX_SAMPLES),
+        }
+    }
+
+    /// Replace the dry/wet calibration points, e.g. after re-measuring the probe
+    pub fn set_calibration(&mut self, calibration: Calibration) {
+        self.calibration = calibration;
+    }
+}
+
+impl<S: MillivoltSource> Sensor for SoilMoisture<S> {
+    type Reading = Reading;
+    type Error = S::Error;
+
+    fn read(&mut self) -> Result<Reading, S::Error> {
+        let mut samples = [0; MAX_SAMPLES];
+        for sample in &mut samples[..self.samples] {
+            *sample = self.source.read_mv()?;
+        }
+
+        // samples is never empty
+        let millivolts = average(&samples[..self.samples]).unwrap_or_default();
+        Ok(Reading {
+            moisture: self.calibration.moisture(millivolts),
+            millivolts,
+        })
+    }
+}
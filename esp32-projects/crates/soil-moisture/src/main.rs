@@ -0,0 +1,35 @@
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    adc::oneshot::{AdcChannelDriver, AdcDriver},
+    prelude::Peripherals,
+};
+use log::info;
+
+use sensor::Sensor;
+use soil_moisture::{channel_config, Calibration, SoilMoisture};
+
+fn main() -> Result<()> {
+    esp_idf_svc::sys::link_patches();
+    esp_idf_svc::log::EspLogger::initialize_default();
+
+    let peripherals = Peripherals::take().unwrap();
+    let adc = AdcDriver::new(peripherals.adc1)?;
+    let channel = AdcChannelDriver::new(&adc, peripherals.pins.gpio2, &channel_config())?;
+
+    // measured with the probe in air and in a glass of water
+    let calibration = Calibration::new(2800, 1300);
+    let mut soil = SoilMoisture::new(channel, calibration, 16);
+    info!("Soil moisture probe setup on pin 2");
+
+    loop {
+        match soil.read() {
+            Ok(res) => {
+                info!("Soil moisture read: {res}");
+            }
+            Err(err) => {
+                info!("error during read: {}", err);
+            }
+        };
+        std::thread::sleep(std::time::Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,284 @@
+//! Async motion on top of the blocking `StepperMotor` drivers
+
+use core::{convert::Infallible, future, time::Duration};
+use embedded_hal_async::delay::DelayNs;
+
+use crate::{
+    idle, micros,
+    profile::{MotionProfile, Ramp},
+    runner::{jog_target, plan_step, stopping_target},
+    stop::StopMode,
+    Direction, StepError, StepperMotor,
+};
+
+/// Async counterpart of `StepperMotor` for moves that can be awaited
+///
+/// The futures only hold the motor between steps, so they can be raced
+/// against a stop signal. Dropping a future stops the motor after the step
+/// in progress and the position stays correct. The stop token of the motor
+/// makes them return `StepError::Cancelled` instead.
+#[allow(async_fn_in_trait)]
+pub trait AsyncStepperMotor {
+    /// Move by a signed number of steps, negative moves reverse
+    async fn move_by(&mut self, delta: i32) -> Result<(), StepError>;
+    /// Move to an absolute position, fails without moving if it is outside the soft limits
+    async fn move_to(&mut self, target: i32) -> Result<(), StepError>;
+    /// Turn at a signed speed in steps/s until the future is dropped or the motor is stopped.
+    /// A speed of 0 brings the motor to a stop and waits, as does reaching a soft limit.
+    /// Fails with `StepError::InvalidSpeed` for a speed that is not finite.
+    async fn run_at_speed(&mut self, speed: f32) -> Result<Infallible, StepError>;
+}
+
+/// Drives any `StepperMotor` with an async delay
+///
+/// Stepping goes through `StepperMotor::step`, so the driver's own coil
+/// handling is used. Every move starts from standstill and follows the profile,
+/// except that `run_at_speed` picks up the speed of a `run_at_speed` future
+/// dropped just before, so dropping it and calling it again with a new speed
+/// ramps smoothly from one speed to the other. Await `idle` between moves to
+/// release the coils as set by the `IdlePolicy`, this also forgets the speed.
+pub struct AsyncStepper<M: StepperMotor, D: DelayNs> {
+    motor: M,
+    delay: D,
+    profile: MotionProfile,
+    ramp: Ramp,
+    dir: Direction,
+}
+
+impl<M: StepperMotor, D: DelayNs> AsyncStepper<M, D> {
+    pub const fn new(motor: M, delay: D, profile: MotionProfile) -> Self {
+        Self {
+            motor,
+            delay,
+            profile,
+            ramp: Ramp::new(profile),
+            dir: Direction::Normal,
+        }
+    }
+
+    /// Change the speed limits used by the next move
+    pub fn set_profile(&mut self, profile: MotionProfile) {
+        self.profile = profile;
+    }
+
+    /// Returns the driven motor, e.g. to read its position
+    pub const fn motor(&self) -> &M {
+        &self.motor
+    }
+
+    /// Returns the driven motor mutably
+    pub fn motor_mut(&mut self) -> &mut M {
+        &mut self.motor
+    }
+
+    /// Apply the idle policy of the motor, waiting out the delay of `IdlePolicy::ReleaseAfter`.
+    /// Meant to be raced against the next command, dropping it leaves the coils as they are.
+    pub async fn idle(&mut self) -> Result<(), StepError> {
+        self.ramp.reset();
+        let Some(delay) = self.motor.idle_policy().delay() else {
+            return Ok(());
+        };
+        self.delay.delay_us(micros(delay)).await;
+        idle::apply(&mut self.motor)
+    }
+
+    /// Stop using the async wrapper and get the motor and delay back
+    pub fn into_inner(self) -> (M, D) {
+        (self.motor, self.delay)
+    }
+
+    async fn step(&mut self, dir: Direction, interval: Duration) -> Result<(), StepError> {
+        self.motor.set_direction(dir);
+        self.motor.step()?;
+        self.dir = dir;
+        self.delay.delay_us(micros(interval)).await;
+        Ok(())
+    }
+
+    /// Apply idle policies that need no `idle` call
+    fn settle(&mut self) -> Result<(), StepError> {
+        if self.motor.idle_policy().delay() == Some(Duration::ZERO) {
+            idle::apply(&mut self.motor)?;
+        }
+        Ok(())
+    }
+}
+
+impl<M: StepperMotor, D: DelayNs> AsyncStepperMotor for AsyncStepper<M, D> {
+    async fn move_by(&mut self, delta: i32) -> Result<(), StepError> {
+        let target = self.motor.current_position().wrapping_add(delta);
+        self.move_to(target).await
+    }
+
+    async fn move_to(&mut self, target: i32) -> Result<(), StepError> {
+        if let Some(limits) = self.motor.soft_limits() {
+            limits.check(target)?;
+        }
+        self.ramp = Ramp::new(self.profile);
+        let mut target = target;
+        let mut executed = 0;
+        let mut stopping = false;
+        loop {
+            let position = self.motor.current_position();
+            match self.motor.stop_requested() {
+                Some(StopMode::Halt) => {
+                    self.ramp.reset();
+                    return Err(StepError::Cancelled { executed });
+                }
+                Some(StopMode::Decelerate) if !stopping => {
+                    target = stopping_target(&self.ramp, self.dir, position, target);
+                    stopping = true;
+                }
+                _ => {}
+            }
+
+            let delta = target.wrapping_sub(position);
+            let Some((dir, interval)) = plan_step(&mut self.ramp, self.dir, delta) else {
+                self.settle()?;
+                return if stopping {
+                    Err(StepError::Cancelled { executed })
+                } else {
+                    Ok(())
+                };
+            };
+            self.step(dir, interval).await?;
+            executed += 1;
+        }
+    }
+
+    async fn run_at_speed(&mut self, speed: f32) -> Result<Infallible, StepError> {
+        if !speed.is_finite() {
+            return Err(StepError::InvalidSpeed);
+        }
+        // a zero speed only brings a motor still turning from a dropped run to a stop
+        let zero = speed == 0.0;
+        if zero {
+            self.ramp.brake();
+        } else {
+            self.ramp
+                .set_profile(self.profile.with_max_speed(speed.abs()));
+        }
+        let mut executed: u32 = 0;
+        let mut stopping = false;
+        loop {
+            match self.motor.stop_requested() {
+                Some(StopMode::Halt) => {
+                    self.ramp.reset();
+                    return Err(StepError::Cancelled { executed });
+                }
+                Some(StopMode::Decelerate) if !stopping => {
+                    self.ramp.brake();
+                    stopping = true;
+                }
+                _ => {}
+            }
+
+            let next = if stopping || zero {
+                self.ramp
+                    .next_interval()
+                    .map(|interval| (self.dir, interval))
+            } else {
+                // stops at the soft limits like the `Runner`, without them it never
+                // gets close enough to the end to start decelerating
+                let position = self.motor.current_position();
+                let target = jog_target(self.motor.soft_limits(), position, speed);
+                plan_step(&mut self.ramp, self.dir, target.wrapping_sub(position))
+            };
+            let Some((dir, interval)) = next else {
+                self.settle()?;
+                if stopping {
+                    return Err(StepError::Cancelled { executed });
+                }
+                return future::pending().await;
+            };
+            self.step(dir, interval).await?;
+            executed = executed.saturating_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+    use crate::{mock::MockMotor, SoftLimits};
+
+    const PROFILE: MotionProfile = MotionProfile::trapezoidal(500.0, 1000.0);
+
+    /// Over at once, counting the waits to catch moves that never settle
+    #[derive(Default)]
+    struct NoDelay(u32);
+
+    impl DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {
+            self.0 += 1;
+            assert!(self.0 < 1_000_000, "still moving");
+        }
+    }
+
+    /// Poll `future` once, with delays that are over at once it only stays
+    /// pending once it waits for good
+    fn poll_once<F: Future>(future: F) -> Poll<F::Output> {
+        pin!(future).poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    #[test]
+    fn rejects_speeds_that_are_not_finite() {
+        let mut stepper = AsyncStepper::new(MockMotor::new(), NoDelay::default(), PROFILE);
+        for speed in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert_eq!(
+                poll_once(stepper.run_at_speed(speed)),
+                Poll::Ready(Err(StepError::InvalidSpeed))
+            );
+        }
+        assert_eq!(stepper.motor().steps, 0);
+    }
+
+    #[test]
+    fn runs_up_to_the_soft_limits_and_waits() {
+        let mut motor = MockMotor::new();
+        motor.limits = Some(SoftLimits::new(-40, 250));
+        let mut stepper = AsyncStepper::new(motor, NoDelay::default(), PROFILE);
+
+        assert!(poll_once(stepper.run_at_speed(300.0)).is_pending());
+        assert_eq!(stepper.motor().position, 250);
+
+        assert!(poll_once(stepper.run_at_speed(-300.0)).is_pending());
+        assert_eq!(stepper.motor().position, -40);
+    }
+
+    #[test]
+    fn moves_complete_on_the_target() {
+        for profile in [
+            PROFILE,
+            MotionProfile::trapezoidal(100.0, 50.0),
+            MotionProfile::s_curve(500.0, 1000.0, 5000.0),
+            MotionProfile::s_curve(200.0, 400.0, 800.0),
+        ] {
+            for target in [1, 2, 5, 37, 400, -1, -37, -123] {
+                let mut stepper = AsyncStepper::new(MockMotor::new(), NoDelay::default(), profile);
+                assert_eq!(poll_once(stepper.move_to(target)), Poll::Ready(Ok(())));
+                let motor = stepper.motor();
+                assert_eq!(motor.position, target, "{profile:?} to {target}");
+                assert_eq!(
+                    motor.steps,
+                    target.unsigned_abs(),
+                    "{profile:?} to {target}"
+                );
+
+                // and back from there
+                assert_eq!(poll_once(stepper.move_by(-target)), Poll::Ready(Ok(())));
+                assert_eq!(
+                    stepper.motor().position,
+                    0,
+                    "{profile:?} back from {target}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Moves in revolutions, degrees and millimetres instead of steps
+
+use crate::{profile::MotionProfile, StepError, StepperMotor};
+
+// === MotorConfig ===
+
+/// Describes how steps relate to the output shaft and what it drives
+///
+/// A 28BYJ-48 for example has 32 full steps and a 64:1 gearbox, a NEMA 17
+/// on a lead screw usually 200 full steps and a lead of 8 mm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotorConfig {
+    full_steps_per_revolution: u32,
+    microsteps: u32,
+    gear_ratio: f32,
+    lead: Option<f32>,
+}
+
+impl MotorConfig {
+    /// Create a `MotorConfig` from the full steps per turn of the motor shaft itself
+    pub const fn new(full_steps_per_revolution: u32) -> Self {
+        Self {
+            full_steps_per_revolution,
+            microsteps: 1,
+            gear_ratio: 1.0,
+            lead: None,
+        }
+    }
+
+    /// Steps the driver takes per full step, e.g. 2 when half stepping
+    #[must_use]
+    pub const fn with_microsteps(mut self, microsteps: u32) -> Self {
+        self.microsteps = microsteps;
+        self
+    }
+
+    /// Motor shaft turns per output shaft turn, e.g. 64 for a 64:1 gearbox
+    #[must_use]
+    pub const fn with_gear_ratio(mut self, gear_ratio: f32) -> Self {
+        self.gear_ratio = gear_ratio;
+        self
+    }
+
+    /// Linear travel per output shaft turn in mm, the lead of a lead screw
+    /// or the circumference of a belt pulley
+    #[must_use]
+    pub const fn with_lead(mut self, lead: f32) -> Self {
+        self.lead = Some(lead);
+        self
+    }
+
+    /// Returns the full steps per turn of the motor shaft
+    pub const fn full_steps_per_revolution(&self) -> u32 {
+        self.full_steps_per_revolution
+    }
+
+    /// Returns the steps per full step
+    pub const fn microsteps(&self) -> u32 {
+        self.microsteps
+    }
+
+    /// Returns the gear ratio between motor and output shaft
+    pub const fn gear_ratio(&self) -> f32 {
+        self.gear_ratio
+    }
+
+    /// Returns the travel per output shaft turn in mm, if set
+    pub const fn lead(&self) -> Option<f32> {
+        self.lead
+    }
+
+    /// Steps per output shaft turn, fractional for gear ratios that are not whole numbers
+    pub fn steps_per_revolution(&self) -> f64 {
+        f64::from(self.full_steps_per_revolution)
+            * f64::from(self.microsteps)
+            * f64::from(self.gear_ratio)
+    }
+
+    /// Steps per mm of travel, `None` without a lead
+    pub fn steps_per_mm(&self) -> Option<f64> {
+        self.lead
+            .map(|lead| self.steps_per_revolution() / f64::from(lead))
+    }
+
+    /// Converts an output shaft speed in rpm to steps/s
+    pub fn steps_per_second(&self, rpm: f32) -> f32 {
+        #[allow(clippy::cast_possible_truncation)]
+        let steps = (f64::from(rpm) * self.steps_per_revolution() / 60.0) as f32;
+        steps
+    }
+}
+
+// === Axis ===
+
+/// Moves a `StepperMotor` in physical units
+///
+/// The target is kept as an exact fractional step count and every move goes
+/// to the closest whole step, so moving by a third of a revolution three
+/// times ends up exactly one revolution further instead of drifting by the
+/// rounding of each move.
+///
+/// The steps per unit come from the `MotorConfig`, not the motor. After
+/// changing the resolution of the motor, e.g. with `set_microsteps` of a
+/// step/dir driver or `set_sequence` of a ULN2003, call `set_microsteps` here.
+pub struct Axis<M: StepperMotor> {
+    motor: M,
+    config: MotorConfig,
+    profile: MotionProfile,
+    target: f64,
+}
+
+impl<M: StepperMotor> Axis<M> {
+    /// Create a new `Axis`, starting at the current position of the motor
+    pub fn new(motor: M, config: MotorConfig, profile: MotionProfile) -> Self {
+        let target = f64::from(motor.current_position());
+        Self {
+            motor,
+            config,
+            profile,
+            target,
+        }
+    }
+
+    /// Returns the unit configuration
+    pub const fn config(&self) -> &MotorConfig {
+        &self.config
+    }
+
+    /// Take `microsteps` steps per full step from now on, after the resolution of
+    /// the motor was changed. The target and the profile are scaled along, so
+    /// the position in units and the speed of the output shaft stay the same.
+    pub fn set_microsteps(&mut self, microsteps: u32) {
+        let factor = f64::from(microsteps) / f64::from(self.config.microsteps);
+        self.target *= factor;
+        #[allow(clippy::cast_possible_truncation)]
+        let profile = self.profile.scaled(factor as f32);
+        self.profile = profile;
+        self.config = self.config.with_microsteps(microsteps);
+    }
+
+    /// Set the cruise speed of the output shaft, keeping the acceleration
+    pub fn set_rpm(&mut self, rpm: f32) {
+        self.profile = self
+            .profile
+            .with_max_speed(self.config.steps_per_second(rpm));
+    }
+
+    /// Returns the cruise speed of the output shaft in rpm
+    pub fn rpm(&self) -> f32 {
+        #[allow(clippy::cast_possible_truncation)]
+        let rpm = (f64::from(self.profile.max_speed()) * 60.0 / self.config.steps_per_revolution())
+            as f32;
+        rpm
+    }
+
+    /// Replace the profile used for moves
+    pub fn set_profile(&mut self, profile: MotionProfile) {
+        self.profile = profile;
+    }
+
+    /// Returns the profile used for moves
+    pub const fn profile(&self) -> &MotionProfile {
+        &self.profile
+    }
+
+    /// Turn the output shaft by a number of revolutions, negative turns reverse
+    pub fn rotate_revolutions(&mut self, revolutions: f32) -> Result<(), StepError> {
+        self.move_steps(f64::from(revolutions) * self.config.steps_per_revolution())
+    }
+
+    /// Turn the output shaft by an angle in degrees, negative angles reverse
+    pub fn rotate_degrees(&mut self, degrees: f32) -> Result<(), StepError> {
+        self.move_steps(f64::from(degrees) / 360.0 * self.config.steps_per_revolution())
+    }
+
+    /// Move by a distance in mm. Fails without moving if no lead is configured.
+    pub fn move_mm(&mut self, mm: f32) -> Result<(), StepError> {
+        let Some(steps_per_mm) = self.config.steps_per_mm() else {
+            return Err(StepError::MissingLead);
+        };
+        self.move_steps(f64::from(mm) * steps_per_mm)
+    }
+
+    /// Returns the position of the output shaft in revolutions
+    pub fn position_revolutions(&self) -> f32 {
+        #[allow(clippy::cast_possible_truncation)]
+        let revolutions = (self.position() / self.config.steps_per_revolution()) as f32;
+        revolutions
+    }
+
+    /// Returns the position of the output shaft in degrees
+    pub fn position_degrees(&self) -> f32 {
+        self.position_revolutions() * 360.0
+    }
+
+    /// Returns the position in mm, `None` without a lead
+    pub fn position_mm(&self) -> Option<f32> {
+        #[allow(clippy::cast_possible_truncation)]
+        self.config
+            .steps_per_mm()
+            .map(|steps_per_mm| (self.position() / steps_per_mm) as f32)
+    }
+
+    /// Returns the driven motor, e.g. to read its position in steps
+    pub const fn motor(&self) -> &M {
+        &self.motor
+    }
+
+    /// Returns the driven motor mutably
+    pub fn motor_mut(&mut self) -> &mut M {
+        &mut self.motor
+    }
+
+    /// Stop using the axis and get the motor back
+    pub fn into_inner(self) -> M {
+        self.motor
+    }
+
+    /// Exact position in steps, the fractional target while the motor is on it
+    fn position(&self) -> f64 {
+        let position = self.motor.current_position();
+        if round_steps(self.target) == position {
+            self.target
+        } else {
+            f64::from(position)
+        }
+    }
+
+    fn move_steps(&mut self, steps: f64) -> Result<(), StepError> {
+        // continues from the actual position if the motor was moved or re-zeroed directly
+        self.target = self.position() + steps;
+        self.motor
+            .move_to_profile(round_steps(self.target), &self.profile)
+    }
+}
+
+fn round_steps(steps: f64) -> i32 {
+    #[allow(clippy::cast_possible_truncation)]
+    let steps = steps.round() as i32;
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockMotor;
+
+    const PROFILE: MotionProfile = MotionProfile::trapezoidal(500.0, 1000.0);
+
+    /// A 28BYJ-48 half stepping, its gearbox is not exactly 64:1
+    const BYJ48: MotorConfig = MotorConfig::new(32)
+        .with_microsteps(2)
+        .with_gear_ratio(63.683_95);
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn converts_full_steps_microsteps_and_gear_ratio() {
+        let config = MotorConfig::new(200);
+        assert!(close(config.steps_per_revolution(), 200.0));
+        assert!(close(
+            config.with_microsteps(16).steps_per_revolution(),
+            3200.0
+        ));
+        let geared = config.with_microsteps(16).with_gear_ratio(5.0);
+        assert!(close(geared.steps_per_revolution(), 16_000.0));
+        assert!(close(BYJ48.steps_per_revolution(), 4_075.772_8));
+    }
+
+    #[test]
+    fn converts_travel_with_a_lead() {
+        let config = MotorConfig::new(200).with_microsteps(16);
+        assert_eq!(config.steps_per_mm(), None);
+        let config = config.with_lead(8.0);
+        assert!(close(config.steps_per_mm().unwrap(), 400.0));
+
+        let mut axis = Axis::new(MockMotor::new(), config, PROFILE);
+        axis.move_mm(2.5).unwrap();
+        assert_eq!(axis.motor().position, 1000);
+        assert!((axis.position_mm().unwrap() - 2.5).abs() < 1e-6);
+        axis.move_mm(-10.0).unwrap();
+        assert_eq!(axis.motor().position, -3000);
+    }
+
+    #[test]
+    fn moves_in_mm_need_a_lead() {
+        let mut axis = Axis::new(MockMotor::new(), MotorConfig::new(200), PROFILE);
+        assert_eq!(axis.move_mm(1.0), Err(StepError::MissingLead));
+        assert_eq!(axis.motor().moves, 0);
+        assert_eq!(axis.position_mm(), None);
+    }
+
+    #[test]
+    fn rotates_by_revolutions_and_degrees() {
+        let config = MotorConfig::new(200).with_microsteps(2);
+        let mut axis = Axis::new(MockMotor::new(), config, PROFILE);
+        axis.rotate_revolutions(1.0).unwrap();
+        assert_eq!(axis.motor().position, 400);
+        axis.rotate_degrees(-90.0).unwrap();
+        assert_eq!(axis.motor().position, 300);
+        assert!((axis.position_degrees() - 270.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn fractional_moves_do_not_drift() {
+        let mut axis = Axis::new(MockMotor::new(), BYJ48, PROFILE);
+        for _ in 0..3000 {
+            axis.rotate_degrees(1.0).unwrap();
+        }
+        // a single 3000° move ends on the same step
+        let expected = round_steps(BYJ48.steps_per_revolution() * 3000.0 / 360.0);
+        assert_eq!(axis.motor().position, expected);
+        assert!((axis.position_degrees() - 3000.0).abs() < 1e-2);
+
+        for _ in 0..3000 {
+            axis.rotate_degrees(-1.0).unwrap();
+        }
+        assert_eq!(axis.motor().position, 0);
+    }
+
+    #[test]
+    fn thirds_of_a_revolution_add_up_to_one() {
+        // 4096 steps per revolution do not divide by 3
+        let config = MotorConfig::new(32)
+            .with_microsteps(2)
+            .with_gear_ratio(64.0);
+        let mut axis = Axis::new(MockMotor::new(), config, PROFILE);
+        for _ in 0..3 {
+            axis.rotate_revolutions(1.0 / 3.0).unwrap();
+        }
+        assert_eq!(axis.motor().position, 4096);
+    }
+
+    #[test]
+    fn continues_from_a_position_set_on_the_motor() {
+        let mut axis = Axis::new(MockMotor::new(), BYJ48, PROFILE);
+        axis.rotate_degrees(0.1).unwrap();
+        axis.motor_mut().set_position(0);
+        axis.rotate_revolutions(1.0).unwrap();
+        assert_eq!(axis.motor().position, 4076);
+    }
+
+    #[test]
+    fn converts_rpm_to_the_cruise_speed() {
+        let mut axis = Axis::new(MockMotor::new(), MotorConfig::new(200), PROFILE);
+        axis.set_rpm(60.0);
+        assert!((axis.profile().max_speed() - 200.0).abs() < 1e-3);
+        assert!((axis.rpm() - 60.0).abs() < 1e-3);
+        // the acceleration is kept
+        assert!((axis.profile().acceleration() - 1000.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn follows_a_change_of_microsteps() {
+        let config = MotorConfig::new(200).with_lead(8.0);
+        let mut axis = Axis::new(MockMotor::new(), config, PROFILE);
+        axis.set_rpm(60.0);
+        axis.move_mm(4.0).unwrap();
+        assert_eq!(axis.motor().position, 100);
+
+        // what a driver does to its position when switching to 1/16 steps
+        let position = crate::rescale(axis.motor().current_position(), 1, 16);
+        axis.motor_mut().set_position(position);
+        axis.set_microsteps(16);
+        assert!((axis.position_mm().unwrap() - 4.0).abs() < 1e-6);
+        assert!((axis.rpm() - 60.0).abs() < 1e-3);
+        assert!((axis.profile().acceleration() - 16_000.0).abs() < 1e-3);
+
+        axis.move_mm(1.0).unwrap();
+        assert_eq!(axis.motor().position, 2000);
+    }
+}
@@ -0,0 +1,359 @@
+//! Compensating the play in a gearbox when the motor reverses
+
+use embedded_hal::delay::DelayNs;
+
+use crate::{
+    homing::{self, Endstop, HomingConfig, HomingError},
+    idle::IdlePolicy,
+    micros_per_step,
+    persist::Checkpoint,
+    profile::MotionProfile,
+    stop::StopToken,
+    Direction, SoftLimits, StepError, StepperMotor,
+};
+
+// === Backlash ===
+
+/// Adds extra steps to take up the play of a gearbox whenever the motor reverses
+///
+/// After a reversal the motor first has to turn through the play before the
+/// output shaft follows. The wrapper steps through it on top of the move, so
+/// the output ends up at the same place from either side. The position and
+/// soft limits are those of the output, the extra steps never show up in them.
+pub struct Backlash<M: StepperMotor> {
+    motor: M,
+    steps: u32,
+    /// steps the motor can turn in `Direction::Reverse` before the output follows,
+    /// the rest of `steps` is the play in `Direction::Normal`
+    play: u32,
+    /// motor position minus output position
+    offset: i32,
+    limits: Option<SoftLimits>,
+    dir: Direction,
+}
+
+impl<M: StepperMotor> Backlash<M> {
+    /// Create a new `Backlash` taking up `steps` of play on every reversal.
+    /// Assumes the gears were last driven in `Direction::Normal`, use `set_engaged`
+    /// after homing in the other direction. Takes over the soft limits of the motor.
+    pub fn new(mut motor: M, steps: u32) -> Self {
+        let limits = motor.soft_limits();
+        motor.set_soft_limits(None);
+        Self {
+            motor,
+            steps,
+            play: steps,
+            offset: 0,
+            limits,
+            dir: Direction::Normal,
+        }
+    }
+
+    /// Returns the play taken up on a reversal, in steps
+    pub const fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    /// Change the play taken up on a reversal, e.g. after `calibrate`.
+    /// The gears count as engaged in the direction of the last move.
+    pub fn set_steps(&mut self, steps: u32) {
+        let engaged = self.engaged();
+        self.steps = steps;
+        self.set_engaged(engaged);
+    }
+
+    /// Returns the direction the gears were last driven in.
+    /// Gears in the middle of the play count as engaged in `Direction::Normal`.
+    pub const fn engaged(&self) -> Direction {
+        if self.play == 0 {
+            Direction::Reverse
+        } else {
+            Direction::Normal
+        }
+    }
+
+    /// Tell the wrapper the gears were last driven in `dir`, e.g. after homing towards `dir`
+    pub fn set_engaged(&mut self, dir: Direction) {
+        self.play = match dir {
+            Direction::Normal => self.steps,
+            Direction::Reverse => 0,
+        };
+    }
+
+    /// Returns the driven motor
+    pub const fn motor(&self) -> &M {
+        &self.motor
+    }
+
+    /// Stop using the wrapper and get the motor back, at the position of the output
+    pub fn into_inner(mut self) -> M {
+        let position = self.current_position();
+        self.motor.set_position(position);
+        self.motor.set_soft_limits(self.limits);
+        self.motor
+    }
+
+    /// Returns the steps the motor turns in `dir` before the output follows
+    const fn slack(&self, dir: Direction) -> u32 {
+        match dir {
+            Direction::Normal => self.steps - self.play,
+            Direction::Reverse => self.play,
+        }
+    }
+
+    /// Account for `moved` motor steps in `dir`, returns how many of them moved the output
+    fn account(&mut self, dir: Direction, moved: u32) -> u32 {
+        let taken = moved.min(self.slack(dir));
+        let shift = i32::try_from(taken).unwrap_or(i32::MAX);
+        match dir {
+            Direction::Normal => {
+                self.play += taken;
+                self.offset = self.offset.wrapping_add(shift);
+            }
+            Direction::Reverse => {
+                self.play -= taken;
+                self.offset = self.offset.wrapping_sub(shift);
+            }
+        }
+        moved - taken
+    }
+
+    /// Run a blocking move of `delta` output steps with the play added on top
+    fn blocking(
+        &mut self,
+        delta: i32,
+        run: impl FnOnce(&mut M, i32) -> Result<(), StepError>,
+    ) -> Result<(), StepError> {
+        if let Some(limits) = self.limits {
+            limits.check(self.current_position().wrapping_add(delta))?;
+        }
+        if delta == 0 {
+            return run(&mut self.motor, 0);
+        }
+        let dir = if delta < 0 {
+            Direction::Reverse
+        } else {
+            Direction::Normal
+        };
+        let slack = self.slack(dir);
+        let extra = i32::try_from(slack).unwrap_or(i32::MAX);
+        let total = match dir {
+            Direction::Normal => delta.saturating_add(extra),
+            Direction::Reverse => delta.saturating_sub(extra),
+        };
+
+        let start = self.motor.current_position();
+        let result = run(&mut self.motor, total);
+        let moved = match result {
+            Ok(()) => total.unsigned_abs(),
+            Err(StepError::Cancelled { executed }) => executed,
+            // e.g. a pin error part way through, the motor position tells how far it got
+            Err(_) => self
+                .motor
+                .current_position()
+                .wrapping_sub(start)
+                .unsigned_abs(),
+        };
+        let executed = self.account(dir, moved);
+        match result {
+            Err(StepError::Cancelled { .. }) => Err(StepError::Cancelled { executed }),
+            result => result,
+        }
+    }
+}
+
+impl<M: StepperMotor> StepperMotor for Backlash<M> {
+    fn step(&mut self) -> Result<(), StepError> {
+        // a step taking up play leaves the output where it is, callers
+        // tracking the position keep stepping until the output moves
+        self.motor.step()?;
+        self.account(self.dir, 1);
+        Ok(())
+    }
+
+    fn step_for(&mut self, steps: i32, delay: u32) -> Result<(), StepError> {
+        let delta = match self.dir {
+            Direction::Normal => steps,
+            Direction::Reverse => steps.saturating_neg(),
+        };
+        self.move_by(delta, delay)
+    }
+
+    fn move_by(&mut self, delta: i32, delay: u32) -> Result<(), StepError> {
+        self.blocking(delta, |motor, total| motor.move_by(total, delay))
+    }
+
+    fn move_by_profile(&mut self, delta: i32, profile: &MotionProfile) -> Result<(), StepError> {
+        self.blocking(delta, |motor, total| motor.move_by_profile(total, profile))
+    }
+
+    fn current_position(&self) -> i32 {
+        self.motor.current_position().wrapping_sub(self.offset)
+    }
+
+    fn set_position(&mut self, position: i32) {
+        self.offset = self.motor.current_position().wrapping_sub(position);
+    }
+
+    /// Saves the output position and the engaged direction along with the
+    /// coils of the motor
+    fn checkpoint(&self) -> Checkpoint {
+        let motor = self.motor.checkpoint();
+        let mut checkpoint = Checkpoint::new(self.current_position()).with_engaged(self.engaged());
+        if let Some(phase) = motor.phase() {
+            checkpoint = checkpoint.with_phase(phase);
+        }
+        if let Some(microsteps) = motor.microsteps() {
+            checkpoint = checkpoint.with_microsteps(microsteps);
+        }
+        checkpoint
+    }
+
+    /// Restores the engaged direction as well if it was saved,
+    /// otherwise call `set_engaged` if it is not `Direction::Normal`
+    fn restore(&mut self, checkpoint: &Checkpoint) {
+        self.motor.restore(checkpoint);
+        self.offset = 0;
+        if let Some(dir) = checkpoint.engaged() {
+            self.set_engaged(dir);
+        }
+    }
+
+    fn set_soft_limits(&mut self, limits: Option<SoftLimits>) {
+        self.limits = limits;
+    }
+
+    fn soft_limits(&self) -> Option<SoftLimits> {
+        self.limits
+    }
+
+    fn set_idle_policy(&mut self, policy: IdlePolicy) {
+        self.motor.set_idle_policy(policy);
+    }
+
+    fn idle_policy(&self) -> IdlePolicy {
+        self.motor.idle_policy()
+    }
+
+    fn set_hold_duty(&mut self, duty: u8) -> Result<(), StepError> {
+        self.motor.set_hold_duty(duty)
+    }
+
+    fn set_stop_token(&mut self, token: Option<StopToken>) {
+        self.motor.set_stop_token(token);
+    }
+
+    fn stop_token(&self) -> Option<&StopToken> {
+        self.motor.stop_token()
+    }
+
+    fn set_direction(&mut self, dir: Direction) {
+        self.dir = dir;
+        self.motor.set_direction(dir);
+    }
+
+    fn stop(&mut self) -> Result<(), StepError> {
+        self.motor.stop()
+    }
+}
+
+// === Calibration ===
+
+/// Measure the play with an endstop, in steps
+///
+/// Drives into the endstop at the fast speed of `config`, then at the slow
+/// speed counts the steps until the endstop releases and triggers again.
+/// Both include the play as well as the hysteresis of the endstop, so use a
+/// sensor with a sharp edge, like an optical slot sensor, and an accurate
+/// result needs a slow speed. Gives up if the endstop does not change within
+/// `max_backlash` steps. The position of the motor is left as it ends up.
+pub fn calibrate<M, E, D>(
+    motor: &mut M,
+    endstop: &mut E,
+    delay: &mut D,
+    config: &HomingConfig,
+    max_backlash: u32,
+) -> Result<u32, HomingError<E::Error>>
+where
+    M: StepperMotor,
+    E: Endstop,
+    D: DelayNs,
+{
+    let towards = config.direction();
+    let (fast, slow) = (config.fast_speed(), config.slow_speed());
+    let mut executed = 0;
+    let mut seek = |dir: Direction,
+                    speed: f32,
+                    max_steps: u32,
+                    triggered: bool|
+     -> Result<Option<u32>, HomingError<E::Error>> {
+        motor.set_direction(dir);
+        let interval = micros_per_step(speed);
+        for steps in 0..=max_steps {
+            if endstop.is_triggered().map_err(HomingError::Endstop)? == triggered {
+                return Ok(Some(steps));
+            }
+            if steps < max_steps {
+                homing::step(motor, &mut executed)?;
+                delay.delay_us(interval);
+            }
+        }
+        Ok(None)
+    };
+
+    // driving into the endstop engages the gears towards it
+    seek(towards, fast, config.max_travel(), true)?.ok_or(HomingError::NotTriggered)?;
+    let away =
+        seek(towards.reversed(), slow, max_backlash, false)?.ok_or(HomingError::NotReleased)?;
+    let back = seek(towards, slow, max_backlash, true)?.ok_or(HomingError::NotTriggered)?;
+    Ok((away + back).div_ceil(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockMotor;
+
+    #[test]
+    fn takes_up_the_play_on_reversal() {
+        let mut backlash = Backlash::new(MockMotor::new(), 5);
+        backlash.move_by(20, 0).unwrap();
+        backlash.move_by(-8, 0).unwrap();
+        assert_eq!(backlash.current_position(), 12);
+        assert_eq!(backlash.motor().position, 7);
+        assert_eq!(backlash.engaged(), Direction::Reverse);
+    }
+
+    #[test]
+    fn checkpoints_keep_the_engaged_direction() {
+        let mut backlash = Backlash::new(MockMotor::new(), 5);
+        backlash.move_by(-10, 0).unwrap();
+        let checkpoint = backlash.checkpoint();
+        assert_eq!(checkpoint.position(), -10);
+        assert_eq!(checkpoint.engaged(), Some(Direction::Reverse));
+
+        // after a reboot, a move on in the same direction has no play to take up
+        let mut backlash = Backlash::new(MockMotor::new(), 5);
+        backlash.restore(&checkpoint);
+        backlash.move_by(-10, 0).unwrap();
+        assert_eq!(backlash.current_position(), -20);
+        assert_eq!(backlash.motor().position, -20);
+    }
+
+    #[test]
+    fn accounts_for_steps_made_before_an_error() {
+        let mut backlash = Backlash::new(MockMotor::new(), 5);
+        // fails after 3 steps, still within the play
+        let result = backlash.blocking(-10, |motor, _| {
+            motor.position -= 3;
+            Err(StepError::MissingDelay)
+        });
+        assert_eq!(result, Err(StepError::MissingDelay));
+        assert_eq!(backlash.current_position(), 0);
+
+        // the rest of the play is taken up before the output moves
+        backlash.move_by(-10, 0).unwrap();
+        assert_eq!(backlash.current_position(), -10);
+        assert_eq!(backlash.motor().position, -15);
+    }
+}
@@ -0,0 +1,129 @@
+//! The blocking moves of `StepperMotor`, shared by the drivers
+//!
+//! A driver passes how it takes one step in a direction and waits out the
+//! interval after it. The loops here check the soft limits, follow the stop
+//! token and apply the idle policy, as documented on `StepperMotor`.
+
+use core::time::Duration;
+
+use crate::{idle, profile::MotionProfile, stop::StopMode, Direction, StepError, StepperMotor};
+
+/// Move by `delta` steps with `interval` after every step, for `StepperMotor::move_by`
+pub fn move_by<M: StepperMotor + ?Sized>(
+    motor: &mut M,
+    delta: i32,
+    interval: Duration,
+    mut step: impl FnMut(&mut M, Direction, Duration) -> Result<(), StepError>,
+) -> Result<(), StepError> {
+    let dir = start(motor, delta)?;
+    for executed in 0..delta.unsigned_abs() {
+        // without a ramp there is nothing to decelerate, any stop is immediate
+        if motor.stop_requested().is_some() {
+            idle::apply(motor)?;
+            return Err(StepError::Cancelled { executed });
+        }
+        step(motor, dir, interval)?;
+    }
+    idle::apply(motor)
+}
+
+/// Move by `delta` steps along the ramp of `profile`, for `StepperMotor::move_by_profile`
+pub fn move_by_profile<M: StepperMotor + ?Sized>(
+    motor: &mut M,
+    delta: i32,
+    profile: &MotionProfile,
+    mut step: impl FnMut(&mut M, Direction, Duration) -> Result<(), StepError>,
+) -> Result<(), StepError> {
+    let dir = start(motor, delta)?;
+    let mut ramp = profile.ramp(delta.unsigned_abs());
+    let mut executed = 0;
+    let mut stopping = false;
+    loop {
+        match motor.stop_requested() {
+            Some(StopMode::Halt) => {
+                stopping = true;
+                break;
+            }
+            Some(StopMode::Decelerate) if !stopping => {
+                ramp.brake();
+                stopping = true;
+            }
+            _ => {}
+        }
+        let Some(interval) = ramp.next_interval() else {
+            break;
+        };
+        step(motor, dir, interval)?;
+        executed += 1;
+    }
+    idle::apply(motor)?;
+    if stopping {
+        return Err(StepError::Cancelled { executed });
+    }
+    Ok(())
+}
+
+/// Rejects targets outside the soft limits and returns the direction to step in
+fn start<M: StepperMotor + ?Sized>(motor: &M, delta: i32) -> Result<Direction, StepError> {
+    if let Some(limits) = motor.soft_limits() {
+        limits.check(motor.current_position().wrapping_add(delta))?;
+    }
+    Ok(if delta < 0 {
+        Direction::Reverse
+    } else {
+        Direction::Normal
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{idle::IdlePolicy, mock::MockMotor, stop::StopToken, SoftLimits};
+
+    const PROFILE: MotionProfile = MotionProfile::trapezoidal(500.0, 1000.0);
+
+    fn step(motor: &mut MockMotor, dir: Direction, _interval: Duration) -> Result<(), StepError> {
+        motor.set_direction(dir);
+        motor.step()
+    }
+
+    #[test]
+    fn moves_and_applies_the_idle_policy() {
+        let mut motor = MockMotor::new();
+        motor.idle = IdlePolicy::ReleaseAfter(Duration::ZERO);
+        move_by(&mut motor, -30, Duration::from_millis(2), step).unwrap();
+        move_by_profile(&mut motor, 200, &PROFILE, step).unwrap();
+        assert_eq!((motor.position, motor.steps, motor.stops), (170, 230, 2));
+    }
+
+    #[test]
+    fn rejects_targets_outside_the_soft_limits() {
+        let mut motor = MockMotor::new();
+        motor.limits = Some(SoftLimits::new(-10, 10));
+        let result = move_by_profile(&mut motor, 11, &PROFILE, step);
+        assert!(matches!(
+            result,
+            Err(StepError::LimitViolation { target: 11, .. })
+        ));
+        assert_eq!(motor.steps, 0);
+    }
+
+    #[test]
+    fn stops_as_the_token_says() {
+        let token = StopToken::new();
+        let mut motor = MockMotor::new();
+        motor.token = Some(token.clone());
+        motor.idle = IdlePolicy::ReleaseAfter(Duration::ZERO);
+
+        token.halt();
+        let result = move_by(&mut motor, 100, Duration::ZERO, step);
+        assert_eq!(result, Err(StepError::Cancelled { executed: 0 }));
+        assert_eq!(motor.stops, 1);
+
+        token.reset();
+        token.stop();
+        let result = move_by_profile(&mut motor, 1000, &PROFILE, step);
+        assert!(matches!(result, Err(StepError::Cancelled { executed }) if executed < 1000));
+        assert_eq!(motor.stops, 2);
+    }
+}
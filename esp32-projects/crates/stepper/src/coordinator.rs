@@ -0,0 +1,262 @@
+//! Linear moves of several motors that start and finish together
+
+use embedded_hal::delay::DelayNs;
+
+use crate::{
+    idle, micros, profile::MotionProfile, stop::StopMode, Direction, StepError, StepperMotor,
+};
+
+/// Moves `N` motors along a straight line, e.g. the pan and slide axis of a camera slider
+///
+/// The axis with the most steps leads, the others step in between
+/// Bresenham-style, so every axis covers its distance evenly and all of
+/// them arrive together. The lead axis is slowed down until no axis exceeds
+/// the speed, acceleration or jerk of its own profile.
+pub struct Coordinator<M: StepperMotor, const N: usize> {
+    axes: [M; N],
+    profiles: [MotionProfile; N],
+}
+
+impl<M: StepperMotor, const N: usize> Coordinator<M, N> {
+    /// Create a new `Coordinator`, with the speed limits of each axis in steps/s
+    pub const fn new(axes: [M; N], profiles: [MotionProfile; N]) -> Self {
+        Self { axes, profiles }
+    }
+
+    /// Change the speed limits of one axis
+    ///
+    /// # Panics
+    ///
+    /// Panics if `axis` is not below `N`
+    pub fn set_profile(&mut self, axis: usize, profile: MotionProfile) {
+        self.profiles[axis] = profile;
+    }
+
+    /// Returns the speed limits of each axis
+    pub const fn profiles(&self) -> &[MotionProfile; N] {
+        &self.profiles
+    }
+
+    /// Returns the position of each axis in steps
+    pub fn positions(&self) -> [i32; N] {
+        core::array::from_fn(|axis| self.axes[axis].current_position())
+    }
+
+    /// Returns the motors
+    pub const fn axes(&self) -> &[M; N] {
+        &self.axes
+    }
+
+    /// Returns the motors mutably, e.g. to home them one by one
+    pub fn axes_mut(&mut self) -> &mut [M; N] {
+        &mut self.axes
+    }
+
+    /// Stop using the coordinator and get the motors back
+    pub fn into_inner(self) -> [M; N] {
+        self.axes
+    }
+
+    /// Move every axis to its absolute target, blocking until all arrived.
+    /// Fails without moving if any target is outside the soft limits of its axis.
+    ///
+    /// A stop requested through the stop token of any axis stops all of them,
+    /// `Cancelled` counts the steps of the lead axis.
+    pub fn move_to<D: DelayNs>(
+        &mut self,
+        targets: [i32; N],
+        delay: &mut D,
+    ) -> Result<(), StepError> {
+        for (motor, target) in self.axes.iter().zip(targets) {
+            if let Some(limits) = motor.soft_limits() {
+                limits.check(target)?;
+            }
+        }
+        let deltas: [i32; N] = core::array::from_fn(|axis| {
+            targets[axis].wrapping_sub(self.axes[axis].current_position())
+        });
+        self.run(deltas, delay)
+    }
+
+    /// Move every axis by a signed number of steps, blocking until all arrived.
+    /// Fails without moving if any target is outside the soft limits of its axis.
+    pub fn move_by<D: DelayNs>(
+        &mut self,
+        deltas: [i32; N],
+        delay: &mut D,
+    ) -> Result<(), StepError> {
+        let targets: [i32; N] = core::array::from_fn(|axis| {
+            self.axes[axis]
+                .current_position()
+                .wrapping_add(deltas[axis])
+        });
+        self.move_to(targets, delay)
+    }
+
+    /// Returns the profile for the lead axis that keeps every axis within its own limits
+    fn lead_profile(&self, steps: &[u32; N], lead: u32) -> MotionProfile {
+        let mut max_speed = f32::INFINITY;
+        let mut acceleration = f32::INFINITY;
+        let mut jerk: Option<f32> = None;
+        for (profile, &steps) in self.profiles.iter().zip(steps) {
+            if steps == 0 {
+                continue;
+            }
+            // the axis moves `ratio` steps for every step of the lead axis
+            #[allow(clippy::cast_precision_loss)]
+            let ratio = steps as f32 / lead as f32;
+            max_speed = max_speed.min(profile.max_speed() / ratio);
+            acceleration = acceleration.min(profile.acceleration() / ratio);
+            if let Some(limit) = profile.jerk() {
+                jerk = Some(jerk.map_or(limit / ratio, |jerk| jerk.min(limit / ratio)));
+            }
+        }
+        let Some(jerk) = jerk else {
+            return MotionProfile::trapezoidal(max_speed, acceleration);
+        };
+        MotionProfile::s_curve(max_speed, acceleration, jerk)
+    }
+
+    fn stop_requested(&self) -> Option<StopMode> {
+        let mut requested = None;
+        for mode in self.axes.iter().filter_map(StepperMotor::stop_requested) {
+            if mode == StopMode::Halt {
+                return Some(mode);
+            }
+            requested = Some(mode);
+        }
+        requested
+    }
+
+    fn run<D: DelayNs>(&mut self, deltas: [i32; N], delay: &mut D) -> Result<(), StepError> {
+        let steps = deltas.map(i32::unsigned_abs);
+        let lead = steps.iter().copied().max().unwrap_or(0);
+        for (motor, delta) in self.axes.iter_mut().zip(deltas) {
+            motor.set_direction(if delta < 0 {
+                Direction::Reverse
+            } else {
+                Direction::Normal
+            });
+        }
+
+        let mut ramp = self.lead_profile(&steps, lead).ramp(lead);
+        // starting half way rounds each axis to its nearest step instead of lagging behind
+        let mut errors = [lead / 2; N];
+        let mut executed = 0;
+        let mut stopping = false;
+        loop {
+            match self.stop_requested() {
+                Some(StopMode::Halt) => {
+                    stopping = true;
+                    break;
+                }
+                Some(StopMode::Decelerate) if !stopping => {
+                    ramp.brake();
+                    stopping = true;
+                }
+                _ => {}
+            }
+            let Some(interval) = ramp.next_interval() else {
+                break;
+            };
+            for ((motor, error), &steps) in self.axes.iter_mut().zip(&mut errors).zip(&steps) {
+                *error += steps;
+                if *error >= lead {
+                    *error -= lead;
+                    motor.step()?;
+                }
+            }
+            executed += 1;
+            delay.delay_us(micros(interval));
+        }
+        for motor in &mut self.axes {
+            idle::apply(motor)?;
+        }
+        if stopping {
+            return Err(StepError::Cancelled { executed });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::*;
+    use crate::{
+        idle::{IdlePolicy, PwmEnable},
+        mock::{MockMotor, MockPwm},
+        stop::StopToken,
+    };
+
+    const PROFILE: MotionProfile = MotionProfile::trapezoidal(500.0, 1000.0);
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    const fn coordinator() -> Coordinator<MockMotor, 2> {
+        Coordinator::new([MockMotor::new(), MockMotor::new()], [PROFILE; 2])
+    }
+
+    #[test]
+    fn moves_every_axis_to_its_target() {
+        let mut coordinator = coordinator();
+        coordinator.move_to([300, -100], &mut NoDelay).unwrap();
+        assert_eq!(coordinator.positions(), [300, -100]);
+        assert_eq!(
+            coordinator.axes().each_ref().map(|motor| motor.steps),
+            [300, 100]
+        );
+    }
+
+    #[test]
+    fn a_halt_applies_the_idle_policy() {
+        let mut coordinator = coordinator();
+        let token = StopToken::new();
+        for motor in coordinator.axes_mut() {
+            motor.set_idle_policy(IdlePolicy::ReleaseAfter(Duration::ZERO));
+            motor.set_stop_token(Some(token.clone()));
+        }
+        token.halt();
+
+        let result = coordinator.move_by([100, 50], &mut NoDelay);
+        assert_eq!(result, Err(StepError::Cancelled { executed: 0 }));
+        assert_eq!(
+            coordinator.axes().each_ref().map(|motor| motor.stops),
+            [1, 1]
+        );
+    }
+
+    #[test]
+    fn a_stop_decelerates_and_applies_the_idle_policy() {
+        let mut coordinator = coordinator();
+        let token = StopToken::new();
+        coordinator.axes_mut()[0].set_stop_token(Some(token.clone()));
+        coordinator.axes_mut()[1].set_idle_policy(IdlePolicy::ReleaseAfter(Duration::ZERO));
+        token.stop();
+
+        // at standstill there is nothing to decelerate from
+        let result = coordinator.move_by([1000, 1000], &mut NoDelay);
+        assert_eq!(result, Err(StepError::Cancelled { executed: 0 }));
+        assert_eq!(
+            coordinator.axes().each_ref().map(|motor| motor.stops),
+            [0, 1]
+        );
+    }
+
+    #[test]
+    fn coordinates_different_motor_types() {
+        let mut borrowed = MockMotor::new();
+        let pwm = PwmEnable::new(MockMotor::new(), MockPwm(0)).unwrap();
+        let axes: [Box<dyn StepperMotor>; 2] = [Box::new(pwm), Box::new(&mut borrowed)];
+        let mut coordinator = Coordinator::new(axes, [PROFILE; 2]);
+        coordinator.move_to([200, -50], &mut NoDelay).unwrap();
+        assert_eq!(coordinator.positions(), [200, -50]);
+        drop(coordinator);
+        assert_eq!((borrowed.position, borrowed.steps), (-50, 50));
+    }
+}
@@ -0,0 +1,384 @@
+//! Finding the zero position with an endstop
+
+use core::fmt;
+use embedded_hal::{delay::DelayNs, digital::InputPin};
+
+use crate::{idle, micros_per_step, Direction, StepError, StepperMotor};
+
+// === Endstop ===
+
+/// Anything that can tell when the motor reached the end of its travel
+pub trait Endstop {
+    type Error;
+
+    /// Returns true while the endstop is triggered
+    fn is_triggered(&mut self) -> Result<bool, Self::Error>;
+}
+
+/// A mechanical, optical or hall effect switch on an input pin
+pub struct Switch<P: InputPin> {
+    pin: P,
+    active_low: bool,
+}
+
+impl<P: InputPin> Switch<P> {
+    /// Switch that pulls the pin high when triggered
+    pub const fn active_high(pin: P) -> Self {
+        Self {
+            pin,
+            active_low: false,
+        }
+    }
+
+    /// Switch that pulls the pin low when triggered, e.g. to ground with a pull-up
+    pub const fn active_low(pin: P) -> Self {
+        Self {
+            pin,
+            active_low: true,
+        }
+    }
+}
+
+impl<P: InputPin> Endstop for Switch<P> {
+    type Error = P::Error;
+
+    fn is_triggered(&mut self) -> Result<bool, P::Error> {
+        if self.active_low {
+            self.pin.is_low()
+        } else {
+            self.pin.is_high()
+        }
+    }
+}
+
+// === HomingConfig ===
+
+/// How to search for the endstop, distances are in steps and speeds in steps/s
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HomingConfig {
+    direction: Direction,
+    max_travel: u32,
+    back_off: u32,
+    fast_speed: f32,
+    slow_speed: f32,
+}
+
+impl HomingConfig {
+    /// Search in `direction`, giving up after `max_travel` steps.
+    /// Defaults to 400 steps/s, re-approaching at 100 steps/s after backing off 100 steps.
+    pub const fn new(direction: Direction, max_travel: u32) -> Self {
+        Self {
+            direction,
+            max_travel,
+            back_off: 100,
+            fast_speed: 400.0,
+            slow_speed: 100.0,
+        }
+    }
+
+    /// Set the speed of the first approach and the slow re-approach
+    #[must_use]
+    pub const fn with_speeds(mut self, fast_speed: f32, slow_speed: f32) -> Self {
+        self.fast_speed = fast_speed;
+        self.slow_speed = slow_speed;
+        self
+    }
+
+    /// Set how far to back off the endstop before re-approaching
+    #[must_use]
+    pub const fn with_back_off(mut self, back_off: u32) -> Self {
+        self.back_off = back_off;
+        self
+    }
+
+    /// Returns the direction the endstop is in
+    pub const fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    /// Returns the maximum steps to search for the endstop
+    pub const fn max_travel(&self) -> u32 {
+        self.max_travel
+    }
+
+    /// Returns the steps to back off before re-approaching
+    pub const fn back_off(&self) -> u32 {
+        self.back_off
+    }
+
+    /// Returns the speed of the first approach
+    pub const fn fast_speed(&self) -> f32 {
+        self.fast_speed
+    }
+
+    /// Returns the speed of the re-approach
+    pub const fn slow_speed(&self) -> f32 {
+        self.slow_speed
+    }
+}
+
+// === HomingError ===
+
+/// A type detailing the ways homing can fail
+#[derive(Debug)]
+pub enum HomingError<E> {
+    /// The endstop did not trigger within the maximum travel
+    NotTriggered,
+    /// The endstop was still triggered after backing off
+    NotReleased,
+    /// Reading the endstop failed
+    Endstop(E),
+    /// Stepping the motor failed
+    Step(StepError),
+}
+
+impl<E> From<StepError> for HomingError<E> {
+    fn from(error: StepError) -> Self {
+        Self::Step(error)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for HomingError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotTriggered => f.write_str("Endstop not reached within the maximum travel"),
+            Self::NotReleased => f.write_str("Endstop still triggered after backing off"),
+            Self::Endstop(err) => write!(f, "Endstop error: {:?}", err),
+            Self::Step(err) => write!(f, "Step error: {err}"),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for HomingError<E> {}
+
+// === Homing ===
+
+/// Find the endstop and make it position 0
+///
+/// Drives towards the endstop at the fast speed, backs off and re-approaches
+/// at the slow speed, so the zero does not depend on how hard the switch was
+/// hit. Soft limits are ignored while homing, since the position is unknown.
+/// Any stop through the stop token of the motor stops homing dead. The idle
+/// policy of the motor is applied at the end, whether homing succeeded or not.
+pub fn home<M, E, D>(
+    motor: &mut M,
+    endstop: &mut E,
+    delay: &mut D,
+    config: &HomingConfig,
+) -> Result<(), HomingError<E::Error>>
+where
+    M: StepperMotor,
+    E: Endstop,
+    D: DelayNs,
+{
+    let result = search(motor, endstop, delay, config);
+    let idle = idle::apply(motor).map_err(HomingError::Step);
+    result.and(idle)
+}
+
+/// Everything `home` does but applying the idle policy
+fn search<M, E, D>(
+    motor: &mut M,
+    endstop: &mut E,
+    delay: &mut D,
+    config: &HomingConfig,
+) -> Result<(), HomingError<E::Error>>
+where
+    M: StepperMotor,
+    E: Endstop,
+    D: DelayNs,
+{
+    let towards = config.direction;
+    let mut executed = 0;
+
+    // a switch that is already pressed skips straight to backing off
+    let fast = micros_per_step(config.fast_speed);
+    if !seek(
+        motor,
+        endstop,
+        delay,
+        towards,
+        fast,
+        config.max_travel,
+        &mut executed,
+    )? {
+        return Err(HomingError::NotTriggered);
+    }
+
+    motor.set_direction(towards.reversed());
+    for _ in 0..config.back_off {
+        step(motor, &mut executed)?;
+        delay.delay_us(fast);
+    }
+    if endstop.is_triggered().map_err(HomingError::Endstop)? {
+        return Err(HomingError::NotReleased);
+    }
+
+    let slow = micros_per_step(config.slow_speed);
+    let search = config.back_off.saturating_mul(2);
+    if !seek(motor, endstop, delay, towards, slow, search, &mut executed)? {
+        return Err(HomingError::NotTriggered);
+    }
+
+    motor.set_position(0);
+    Ok(())
+}
+
+/// Step until the endstop triggers, returns false if it did not within `max_steps`
+fn seek<M, E, D>(
+    motor: &mut M,
+    endstop: &mut E,
+    delay: &mut D,
+    dir: Direction,
+    interval: u32,
+    max_steps: u32,
+    executed: &mut u32,
+) -> Result<bool, HomingError<E::Error>>
+where
+    M: StepperMotor,
+    E: Endstop,
+    D: DelayNs,
+{
+    motor.set_direction(dir);
+    for _ in 0..max_steps {
+        if endstop.is_triggered().map_err(HomingError::Endstop)? {
+            return Ok(true);
+        }
+        step(motor, executed)?;
+        delay.delay_us(interval);
+    }
+    endstop.is_triggered().map_err(HomingError::Endstop)
+}
+
+/// Step unless a stop was requested
+pub(crate) fn step<M: StepperMotor, E>(
+    motor: &mut M,
+    executed: &mut u32,
+) -> Result<(), HomingError<E>> {
+    if motor.stop_requested().is_some() {
+        return Err(HomingError::Step(StepError::Cancelled {
+            executed: *executed,
+        }));
+    }
+    motor.step()?;
+    *executed += 1;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use core::time::Duration;
+
+    use super::*;
+    use crate::{idle::IdlePolicy, mock::MockMotor, stop::StopToken};
+
+    /// Triggered at and beyond `at` towards the reverse end, or while `stuck`
+    struct FakeEndstop {
+        shaft: Rc<Cell<i32>>,
+        at: i32,
+        stuck: bool,
+        broken: bool,
+    }
+
+    impl Endstop for FakeEndstop {
+        type Error = &'static str;
+
+        fn is_triggered(&mut self) -> Result<bool, Self::Error> {
+            if self.broken {
+                return Err("broken");
+            }
+            Ok(self.stuck || self.shaft.get() <= self.at)
+        }
+    }
+
+    /// Records every wait
+    #[derive(Default)]
+    struct Waits(Vec<u32>);
+
+    impl DelayNs for Waits {
+        fn delay_ns(&mut self, ns: u32) {
+            self.0.push(ns / 1000);
+        }
+    }
+
+    const CONFIG: HomingConfig = HomingConfig::new(Direction::Reverse, 1000);
+
+    fn rig(start: i32, at: i32) -> (MockMotor, FakeEndstop) {
+        let shaft = Rc::new(Cell::new(start));
+        let mut motor = MockMotor::new();
+        motor.position = start;
+        motor.idle = IdlePolicy::ReleaseAfter(Duration::ZERO);
+        motor.shaft = Some(shaft.clone());
+        let endstop = FakeEndstop {
+            shaft,
+            at,
+            stuck: false,
+            broken: false,
+        };
+        (motor, endstop)
+    }
+
+    #[test]
+    fn backs_off_and_zeroes_on_the_slow_re_approach() {
+        let (mut motor, mut endstop) = rig(0, -250);
+        let mut waits = Waits::default();
+        home(&mut motor, &mut endstop, &mut waits, &CONFIG).unwrap();
+
+        // 250 steps in, 100 back off and 100 slowly back in
+        assert_eq!(motor.steps, 450);
+        assert_eq!(endstop.shaft.get(), -250);
+        assert_eq!(motor.position, 0);
+        assert_eq!(motor.stops, 1);
+        let (fast, slow) = waits.0.split_at(350);
+        assert!(fast.iter().all(|&us| us == 2500));
+        assert!(slow.iter().all(|&us| us == 10_000));
+    }
+
+    #[test]
+    fn backs_off_a_switch_that_is_already_pressed() {
+        let (mut motor, mut endstop) = rig(-300, -250);
+        home(&mut motor, &mut endstop, &mut Waits::default(), &CONFIG).unwrap();
+        assert_eq!(motor.steps, 150);
+        assert_eq!((endstop.shaft.get(), motor.position), (-250, 0));
+    }
+
+    #[test]
+    fn gives_up_at_the_maximum_travel() {
+        let (mut motor, mut endstop) = rig(0, -2000);
+        let result = home(&mut motor, &mut endstop, &mut Waits::default(), &CONFIG);
+        assert!(matches!(result, Err(HomingError::NotTriggered)));
+        assert_eq!((motor.steps, motor.position), (1000, -1000));
+        assert_eq!(motor.stops, 1);
+    }
+
+    #[test]
+    fn fails_when_the_switch_does_not_release() {
+        let (mut motor, mut endstop) = rig(0, -250);
+        endstop.stuck = true;
+        let result = home(&mut motor, &mut endstop, &mut Waits::default(), &CONFIG);
+        assert!(matches!(result, Err(HomingError::NotReleased)));
+        assert_eq!((motor.steps, motor.position), (100, 100));
+        assert_eq!(motor.stops, 1);
+    }
+
+    #[test]
+    fn passes_on_endstop_errors_and_stops() {
+        let (mut motor, mut endstop) = rig(0, -250);
+        endstop.broken = true;
+        let result = home(&mut motor, &mut endstop, &mut Waits::default(), &CONFIG);
+        assert!(matches!(result, Err(HomingError::Endstop("broken"))));
+
+        let (mut motor, mut endstop) = rig(0, -250);
+        let token = StopToken::new();
+        token.halt();
+        motor.token = Some(token);
+        let result = home(&mut motor, &mut endstop, &mut Waits::default(), &CONFIG);
+        assert!(matches!(
+            result,
+            Err(HomingError::Step(StepError::Cancelled { executed: 0 }))
+        ));
+        assert_eq!((motor.position, motor.stops), (0, 1));
+    }
+}
@@ -0,0 +1,226 @@
+//! What a motor does with its coils once it stops moving
+
+use core::time::Duration;
+use embedded_hal::{digital::ErrorKind, pwm::SetDutyCycle};
+
+use crate::{
+    persist::Checkpoint, profile::MotionProfile, stop::StopToken, Direction, HalError, SoftLimits,
+    StepError, StepperMotor,
+};
+
+// === IdlePolicy ===
+
+/// What to do with the coils while the motor is not moving
+///
+/// Blocking moves apply the policy as soon as they are done, `ReleaseAfter`
+/// releases right away there, as nothing runs afterwards to wait out the delay.
+/// `StepperRunner::poll` and `AsyncStepper::idle` honour the delay.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IdlePolicy {
+    /// Keep the coils energised at full current, holding the position firmly
+    #[default]
+    Hold,
+    /// Switch the coils off once the motor was idle for the given time
+    ReleaseAfter(Duration),
+    /// Keep holding at a reduced duty cycle in percent, the driver has to be able
+    /// to limit the current, e.g. with a `PwmEnable`
+    ReducedHold(u8),
+}
+
+impl IdlePolicy {
+    /// Returns how long to wait before applying the policy, `None` for `Hold`
+    pub const fn delay(self) -> Option<Duration> {
+        match self {
+            Self::Hold => None,
+            Self::ReleaseAfter(after) => Some(after),
+            Self::ReducedHold(_) => Some(Duration::ZERO),
+        }
+    }
+}
+
+/// Apply the idle policy of `motor` without waiting, drivers call this at the end of blocking moves
+pub fn apply<M: StepperMotor + ?Sized>(motor: &mut M) -> Result<(), StepError> {
+    match motor.idle_policy() {
+        IdlePolicy::Hold => Ok(()),
+        IdlePolicy::ReleaseAfter(_) => motor.stop(),
+        IdlePolicy::ReducedHold(duty) => motor.set_hold_duty(duty),
+    }
+}
+
+// === PwmEnable ===
+
+/// Adds current control through a PWM on the enable line of the driver
+///
+/// Moves always run at full duty, while idle the duty of
+/// `IdlePolicy::ReducedHold` is used. With a ULN2003 the PWM can switch
+/// the common motor supply through a MOSFET.
+pub struct PwmEnable<M: StepperMotor, P: SetDutyCycle> {
+    motor: M,
+    enable: P,
+    duty: u8,
+}
+
+impl<M: StepperMotor, P: SetDutyCycle> PwmEnable<M, P>
+where
+    P::Error: Send + Sync + 'static,
+{
+    /// Create a new `PwmEnable`, starting at full duty
+    pub fn new(motor: M, enable: P) -> Result<Self, StepError> {
+        let mut pwm = Self {
+            motor,
+            enable,
+            duty: 0,
+        };
+        pwm.set_hold_duty(100)?;
+        Ok(pwm)
+    }
+
+    /// Returns the current duty cycle of the enable line, in percent
+    pub const fn duty(&self) -> u8 {
+        self.duty
+    }
+
+    /// Returns the driven motor
+    pub const fn motor(&self) -> &M {
+        &self.motor
+    }
+
+    /// Stop using the wrapper and get the motor and the PWM back
+    pub fn into_inner(self) -> (M, P) {
+        (self.motor, self.enable)
+    }
+
+    fn full_power(&mut self) -> Result<(), StepError> {
+        if self.duty == 100 {
+            return Ok(());
+        }
+        self.set_hold_duty(100)
+    }
+
+    /// Run a blocking move at full duty and apply the idle policy afterwards
+    fn blocking(
+        &mut self,
+        run: impl FnOnce(&mut M) -> Result<(), StepError>,
+    ) -> Result<(), StepError> {
+        self.full_power()?;
+        // a cancelled or failed move stopped as well, it idles all the same
+        let result = run(&mut self.motor);
+        apply(self)?;
+        result
+    }
+}
+
+impl<M: StepperMotor, P: SetDutyCycle> StepperMotor for PwmEnable<M, P>
+where
+    P::Error: Send + Sync + 'static,
+{
+    fn step(&mut self) -> Result<(), StepError> {
+        self.full_power()?;
+        self.motor.step()
+    }
+
+    fn step_for(&mut self, steps: i32, delay: u32) -> Result<(), StepError> {
+        self.blocking(|motor| motor.step_for(steps, delay))
+    }
+
+    fn move_by(&mut self, delta: i32, delay: u32) -> Result<(), StepError> {
+        self.blocking(|motor| motor.move_by(delta, delay))
+    }
+
+    fn move_by_profile(&mut self, delta: i32, profile: &MotionProfile) -> Result<(), StepError> {
+        self.blocking(|motor| motor.move_by_profile(delta, profile))
+    }
+
+    fn current_position(&self) -> i32 {
+        self.motor.current_position()
+    }
+
+    fn set_position(&mut self, position: i32) {
+        self.motor.set_position(position);
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        self.motor.checkpoint()
+    }
+
+    fn restore(&mut self, checkpoint: &Checkpoint) {
+        self.motor.restore(checkpoint);
+    }
+
+    fn set_soft_limits(&mut self, limits: Option<SoftLimits>) {
+        self.motor.set_soft_limits(limits);
+    }
+
+    fn soft_limits(&self) -> Option<SoftLimits> {
+        self.motor.soft_limits()
+    }
+
+    fn set_idle_policy(&mut self, policy: IdlePolicy) {
+        self.motor.set_idle_policy(policy);
+    }
+
+    fn idle_policy(&self) -> IdlePolicy {
+        self.motor.idle_policy()
+    }
+
+    fn set_hold_duty(&mut self, duty: u8) -> Result<(), StepError> {
+        self.enable
+            .set_duty_cycle_percent(duty)
+            .map_err(|err| StepError::Pin {
+                pin: "enable",
+                error: HalError::new(ErrorKind::Other, err),
+            })?;
+        self.duty = duty;
+        Ok(())
+    }
+
+    fn set_stop_token(&mut self, token: Option<StopToken>) {
+        self.motor.set_stop_token(token);
+    }
+
+    fn stop_token(&self) -> Option<&StopToken> {
+        self.motor.stop_token()
+    }
+
+    fn set_direction(&mut self, dir: Direction) {
+        self.motor.set_direction(dir);
+    }
+
+    fn stop(&mut self) -> Result<(), StepError> {
+        self.motor.stop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockMotor, MockPwm};
+
+    fn reduced() -> PwmEnable<MockMotor, MockPwm> {
+        let mut motor = MockMotor::new();
+        motor.idle = IdlePolicy::ReducedHold(30);
+        PwmEnable::new(motor, MockPwm(0)).unwrap()
+    }
+
+    #[test]
+    fn reduces_the_duty_after_moves() {
+        let mut pwm = reduced();
+        assert_eq!(pwm.duty(), 100);
+        pwm.move_by(50, 1000).unwrap();
+        assert_eq!((pwm.duty(), pwm.enable.0), (30, 30));
+        pwm.step().unwrap();
+        assert_eq!(pwm.duty(), 100);
+    }
+
+    #[test]
+    fn reduces_the_duty_after_cancelled_moves() {
+        let mut pwm = reduced();
+        let token = StopToken::new();
+        pwm.set_stop_token(Some(token.clone()));
+        token.halt();
+        let result = pwm.move_by_profile(50, &MotionProfile::trapezoidal(500.0, 1000.0));
+        assert_eq!(result, Err(StepError::Cancelled { executed: 0 }));
+        assert_eq!((pwm.duty(), pwm.enable.0), (30, 30));
+        assert_eq!(pwm.current_position(), 0);
+    }
+}
@@ -0,0 +1,507 @@
+//! crate stepper
+//!
+//! Hardware independent building blocks shared by the stepper motor drivers.
+//! Needs `std`, for the float math of the motion profiles and the shared stop tokens.
+
+pub mod asynch;
+pub mod axis;
+pub mod backlash;
+pub mod blocking;
+pub mod coordinator;
+pub mod homing;
+pub mod idle;
+pub mod persist;
+pub mod profile;
+pub mod queue;
+pub mod runner;
+pub mod stop;
+
+#[cfg(test)]
+mod mock;
+
+use core::{fmt, time::Duration};
+use embedded_hal::digital::{self, ErrorKind};
+use std::sync::Arc;
+
+use idle::IdlePolicy;
+use persist::Checkpoint;
+use profile::MotionProfile;
+use stop::{StopMode, StopToken};
+
+// === HalError ===
+
+/// The error a HAL returned for a driver pin, kept so it is not lost in a `StepError`.
+/// Compares equal by `kind` only, as HAL errors are not comparable in general.
+#[derive(Clone)]
+pub struct HalError {
+    kind: ErrorKind,
+    error: Arc<dyn fmt::Debug + Send + Sync>,
+}
+
+impl HalError {
+    /// Keep any HAL error, e.g. of a PWM channel, together with the closest `ErrorKind`
+    pub fn new<E: fmt::Debug + Send + Sync + 'static>(kind: ErrorKind, error: E) -> Self {
+        Self {
+            kind,
+            error: Arc::new(error),
+        }
+    }
+
+    /// Keep the error of a digital pin, with the kind it reports
+    pub fn from_pin<E: digital::Error + Send + Sync + 'static>(error: E) -> Self {
+        Self::new(error.kind(), error)
+    }
+
+    /// Returns the kind of the error
+    pub const fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the error as returned by the HAL
+    pub fn error(&self) -> &(dyn fmt::Debug + Send + Sync) {
+        &*self.error
+    }
+}
+
+impl fmt::Debug for HalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl fmt::Display for HalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:?})", self.kind, self.error)
+    }
+}
+
+impl PartialEq for HalError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind
+    }
+}
+
+impl Eq for HalError {}
+
+// === StepError ===
+
+/// A type detailing the errors that can happen while stepping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepError {
+    /// A blocking move was requested from a driver created without a delay
+    MissingDelay,
+    /// A move in mm was requested without a lead in the `MotorConfig`
+    MissingLead,
+    /// The driver chip cannot be set to this many microsteps per full step
+    UnsupportedMicrosteps { microsteps: u16 },
+    /// Received a low-level error from the HAL while setting a driver pin, e.g. `in3`
+    Pin { pin: &'static str, error: HalError },
+    /// The target is outside the soft limits, nothing was moved
+    LimitViolation { target: i32, limits: SoftLimits },
+    /// The move was stopped after `executed` steps, before reaching its target
+    Cancelled { executed: u32 },
+    /// A speed that is not a finite number of steps/s was requested, nothing was moved
+    InvalidSpeed,
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingDelay => f.write_str("Blocking moves need a delay provider"),
+            Self::MissingLead => f.write_str("Moves in mm need a lead in the motor config"),
+            Self::UnsupportedMicrosteps { microsteps } => {
+                write!(f, "Driver does not support 1/{microsteps} microsteps")
+            }
+            Self::Pin { pin, error } => write!(f, "HAL pin error on {pin}: {error}"),
+            Self::LimitViolation { target, limits } => write!(
+                f,
+                "Target {target} is outside the soft limits {}..={}",
+                limits.min, limits.max
+            ),
+            Self::Cancelled { executed } => write!(f, "Move cancelled after {executed} steps"),
+            Self::InvalidSpeed => f.write_str("Speed must be a finite number of steps/s"),
+        }
+    }
+}
+
+impl std::error::Error for StepError {}
+
+// === StepperMotor ===
+
+/// trait to prevent having to pass around the struct with all the generic arguments
+pub trait StepperMotor {
+    /// Do a single step, ignoring the soft limits
+    fn step(&mut self) -> Result<(), StepError>;
+    /// Do multiple steps in the set direction with a given delay in ms.
+    /// A negative count steps against the set direction.
+    fn step_for(&mut self, steps: i32, delay: u32) -> Result<(), StepError>;
+    /// Move by a signed number of steps with a given delay in ms.
+    /// Positive counts turn in `Direction::Normal`, negative in `Direction::Reverse`,
+    /// independent of the set direction.
+    fn move_by(&mut self, delta: i32, delay: u32) -> Result<(), StepError>;
+    /// Move to an absolute position with a given delay in ms.
+    /// Fails without moving if the target is outside the soft limits.
+    fn move_to(&mut self, target: i32, delay: u32) -> Result<(), StepError> {
+        self.move_by(target.wrapping_sub(self.current_position()), delay)
+    }
+    /// Move by a signed number of steps, accelerating and decelerating as set by the profile
+    fn move_by_profile(&mut self, delta: i32, profile: &MotionProfile) -> Result<(), StepError>;
+    /// Move to an absolute position, accelerating and decelerating as set by the profile.
+    /// Fails without moving if the target is outside the soft limits.
+    fn move_to_profile(&mut self, target: i32, profile: &MotionProfile) -> Result<(), StepError> {
+        self.move_by_profile(target.wrapping_sub(self.current_position()), profile)
+    }
+    /// Returns the position in steps, counting up in `Direction::Normal`
+    fn current_position(&self) -> i32;
+    /// Overwrite the position without moving, e.g. to zero it at a known reference
+    fn set_position(&mut self, position: i32);
+    /// Returns what is needed to continue from here after a reboot, see `persist`
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint::new(self.current_position())
+    }
+    /// Continue from a checkpoint saved before a reboot, without moving
+    fn restore(&mut self, checkpoint: &Checkpoint) {
+        self.set_position(checkpoint.position());
+    }
+    /// Restrict moves to a range of positions, `None` allows any position
+    fn set_soft_limits(&mut self, limits: Option<SoftLimits>);
+    /// Returns the range of positions moves are restricted to
+    fn soft_limits(&self) -> Option<SoftLimits>;
+    /// Set what the coils do once a move is done
+    fn set_idle_policy(&mut self, policy: IdlePolicy);
+    /// Returns what the coils do once a move is done
+    fn idle_policy(&self) -> IdlePolicy;
+    /// Limit the holding current to `duty` percent, 100 for full torque.
+    /// Drivers that cannot limit the current keep holding at full torque.
+    fn set_hold_duty(&mut self, duty: u8) -> Result<(), StepError> {
+        let _ = duty;
+        Ok(())
+    }
+    /// Let `token` cancel moves, `None` lets moves always run to their target
+    fn set_stop_token(&mut self, token: Option<StopToken>);
+    /// Returns the token that cancels moves
+    fn stop_token(&self) -> Option<&StopToken>;
+    /// Returns the stop requested through the stop token, if any
+    fn stop_requested(&self) -> Option<StopMode> {
+        self.stop_token().and_then(StopToken::requested)
+    }
+    /// Set the stepping direction
+    fn set_direction(&mut self, dir: Direction);
+    /// Stopping sets all pins low, the next step energises the coils again
+    fn stop(&mut self) -> Result<(), StepError>;
+}
+
+/// A borrowed motor drives like the motor itself, e.g. to coordinate motors owned elsewhere
+impl<T: StepperMotor + ?Sized> StepperMotor for &mut T {
+    fn step(&mut self) -> Result<(), StepError> {
+        (**self).step()
+    }
+
+    fn step_for(&mut self, steps: i32, delay: u32) -> Result<(), StepError> {
+        (**self).step_for(steps, delay)
+    }
+
+    fn move_by(&mut self, delta: i32, delay: u32) -> Result<(), StepError> {
+        (**self).move_by(delta, delay)
+    }
+
+    fn move_to(&mut self, target: i32, delay: u32) -> Result<(), StepError> {
+        (**self).move_to(target, delay)
+    }
+
+    fn move_by_profile(&mut self, delta: i32, profile: &MotionProfile) -> Result<(), StepError> {
+        (**self).move_by_profile(delta, profile)
+    }
+
+    fn move_to_profile(&mut self, target: i32, profile: &MotionProfile) -> Result<(), StepError> {
+        (**self).move_to_profile(target, profile)
+    }
+
+    fn current_position(&self) -> i32 {
+        (**self).current_position()
+    }
+
+    fn set_position(&mut self, position: i32) {
+        (**self).set_position(position);
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        (**self).checkpoint()
+    }
+
+    fn restore(&mut self, checkpoint: &Checkpoint) {
+        (**self).restore(checkpoint);
+    }
+
+    fn set_soft_limits(&mut self, limits: Option<SoftLimits>) {
+        (**self).set_soft_limits(limits);
+    }
+
+    fn soft_limits(&self) -> Option<SoftLimits> {
+        (**self).soft_limits()
+    }
+
+    fn set_idle_policy(&mut self, policy: IdlePolicy) {
+        (**self).set_idle_policy(policy);
+    }
+
+    fn idle_policy(&self) -> IdlePolicy {
+        (**self).idle_policy()
+    }
+
+    fn set_hold_duty(&mut self, duty: u8) -> Result<(), StepError> {
+        (**self).set_hold_duty(duty)
+    }
+
+    fn set_stop_token(&mut self, token: Option<StopToken>) {
+        (**self).set_stop_token(token);
+    }
+
+    fn stop_token(&self) -> Option<&StopToken> {
+        (**self).stop_token()
+    }
+
+    fn stop_requested(&self) -> Option<StopMode> {
+        (**self).stop_requested()
+    }
+
+    fn set_direction(&mut self, dir: Direction) {
+        (**self).set_direction(dir);
+    }
+
+    fn stop(&mut self) -> Result<(), StepError> {
+        (**self).stop()
+    }
+}
+
+/// A boxed motor drives like the motor itself, so `Box<dyn StepperMotor>` can mix
+/// different drivers where one motor type is expected, e.g. in a `Coordinator`
+impl<T: StepperMotor + ?Sized> StepperMotor for Box<T> {
+    fn step(&mut self) -> Result<(), StepError> {
+        (**self).step()
+    }
+
+    fn step_for(&mut self, steps: i32, delay: u32) -> Result<(), StepError> {
+        (**self).step_for(steps, delay)
+    }
+
+    fn move_by(&mut self, delta: i32, delay: u32) -> Result<(), StepError> {
+        (**self).move_by(delta, delay)
+    }
+
+    fn move_to(&mut self, target: i32, delay: u32) -> Result<(), StepError> {
+        (**self).move_to(target, delay)
+    }
+
+    fn move_by_profile(&mut self, delta: i32, profile: &MotionProfile) -> Result<(), StepError> {
+        (**self).move_by_profile(delta, profile)
+    }
+
+    fn move_to_profile(&mut self, target: i32, profile: &MotionProfile) -> Result<(), StepError> {
+        (**self).move_to_profile(target, profile)
+    }
+
+    fn current_position(&self) -> i32 {
+        (**self).current_position()
+    }
+
+    fn set_position(&mut self, position: i32) {
+        (**self).set_position(position);
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        (**self).checkpoint()
+    }
+
+    fn restore(&mut self, checkpoint: &Checkpoint) {
+        (**self).restore(checkpoint);
+    }
+
+    fn set_soft_limits(&mut self, limits: Option<SoftLimits>) {
+        (**self).set_soft_limits(limits);
+    }
+
+    fn soft_limits(&self) -> Option<SoftLimits> {
+        (**self).soft_limits()
+    }
+
+    fn set_idle_policy(&mut self, policy: IdlePolicy) {
+        (**self).set_idle_policy(policy);
+    }
+
+    fn idle_policy(&self) -> IdlePolicy {
+        (**self).idle_policy()
+    }
+
+    fn set_hold_duty(&mut self, duty: u8) -> Result<(), StepError> {
+        (**self).set_hold_duty(duty)
+    }
+
+    fn set_stop_token(&mut self, token: Option<StopToken>) {
+        (**self).set_stop_token(token);
+    }
+
+    fn stop_token(&self) -> Option<&StopToken> {
+        (**self).stop_token()
+    }
+
+    fn stop_requested(&self) -> Option<StopMode> {
+        (**self).stop_requested()
+    }
+
+    fn set_direction(&mut self, dir: Direction) {
+        (**self).set_direction(dir);
+    }
+
+    fn stop(&mut self) -> Result<(), StepError> {
+        (**self).stop()
+    }
+}
+
+// === Direction ===
+
+/// Direction the motor turns in. Just reverses the order of the internal states.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Normal,
+    Reverse,
+}
+
+impl Direction {
+    /// Returns the opposite direction
+    #[must_use]
+    pub const fn reversed(self) -> Self {
+        match self {
+            Self::Normal => Self::Reverse,
+            Self::Reverse => Self::Normal,
+        }
+    }
+}
+
+// === SoftLimits ===
+
+/// Range of positions a motor may be moved to, both ends included
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SoftLimits {
+    min: i32,
+    max: i32,
+}
+
+impl SoftLimits {
+    /// Create new `SoftLimits`, the ends are swapped if `min` is above `max`
+    pub const fn new(min: i32, max: i32) -> Self {
+        if min <= max {
+            Self { min, max }
+        } else {
+            Self { min: max, max: min }
+        }
+    }
+
+    /// Returns the lowest allowed position
+    pub const fn min(&self) -> i32 {
+        self.min
+    }
+
+    /// Returns the highest allowed position
+    pub const fn max(&self) -> i32 {
+        self.max
+    }
+
+    /// Returns true if `position` is within the limits
+    pub const fn contains(&self, position: i32) -> bool {
+        self.min <= position && position <= self.max
+    }
+
+    /// Rejects a move to a target outside the limits
+    pub const fn check(&self, target: i32) -> Result<(), StepError> {
+        if self.contains(target) {
+            Ok(())
+        } else {
+            Err(StepError::LimitViolation {
+                target,
+                limits: *self,
+            })
+        }
+    }
+
+    /// Convert both ends from `1/from` to `1/to` full steps, rounded like `rescale`
+    #[must_use]
+    pub fn rescale(self, from: u32, to: u32) -> Self {
+        Self::new(rescale(self.min, from, to), rescale(self.max, from, to))
+    }
+}
+
+/// Convert a position counted in `1/from` full steps to `1/to` full steps
+///
+/// Rounds toward zero if `to` is coarser and saturates at the ends of `i32`,
+/// a `from` of 0 leaves the position as is.
+pub fn rescale(position: i32, from: u32, to: u32) -> i32 {
+    if from == 0 || from == to {
+        return position;
+    }
+    let scaled = i64::from(position) * i64::from(to) / i64::from(from);
+    #[allow(clippy::cast_possible_truncation)]
+    let scaled = scaled.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32;
+    scaled
+}
+
+/// Whole µs of a step interval for `DelayNs::delay_us`, intervals longer than
+/// `u32::MAX` µs (~71 minutes) are capped
+pub fn micros(interval: Duration) -> u32 {
+    u32::try_from(interval.as_micros()).unwrap_or(u32::MAX)
+}
+
+/// Interval between steps at a constant speed in steps/s, in µs
+pub(crate) fn micros_per_step(speed: f32) -> u32 {
+    Duration::try_from_secs_f32(speed.recip()).map_or(u32::MAX, micros)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Fault;
+
+    impl digital::Error for Fault {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    #[test]
+    fn pin_errors_keep_the_hal_error() {
+        let err = StepError::Pin {
+            pin: "in3",
+            error: HalError::from_pin(Fault),
+        };
+        let StepError::Pin { error, .. } = &err else {
+            unreachable!()
+        };
+        assert_eq!(error.kind(), ErrorKind::Other);
+        assert_eq!(format!("{:?}", error.error()), "Fault");
+        assert!(err.to_string().contains("in3"));
+        assert!(err.to_string().contains("Fault"));
+    }
+
+    #[test]
+    fn rescales_positions_between_resolutions() {
+        assert_eq!(rescale(6, 1, 2), 12);
+        assert_eq!(rescale(13, 2, 1), 6);
+        assert_eq!(rescale(-13, 2, 1), -6);
+        assert_eq!(rescale(i32::MAX, 1, 256), i32::MAX);
+        assert_eq!(rescale(5, 0, 16), 5);
+        assert_eq!(
+            SoftLimits::new(-100, 301).rescale(2, 1),
+            SoftLimits::new(-50, 150)
+        );
+    }
+
+    #[test]
+    fn hal_errors_compare_by_kind() {
+        assert_eq!(
+            HalError::from_pin(Fault),
+            HalError::new(ErrorKind::Other, "timeout")
+        );
+    }
+}
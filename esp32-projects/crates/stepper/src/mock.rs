@@ -0,0 +1,150 @@
+//! A `StepperMotor` without hardware for the unit tests
+
+// `unreachable_pub` wants `pub(crate)` in a private module, clippy wants `pub`
+#![allow(clippy::redundant_pub_crate)]
+
+use core::convert::Infallible;
+use embedded_hal::pwm::{ErrorType, SetDutyCycle};
+use std::{cell::Cell, rc::Rc};
+
+use crate::{
+    idle::IdlePolicy, profile::MotionProfile, stop::StopToken, Direction, SoftLimits, StepError,
+    StepperMotor,
+};
+
+/// Keeps the position and counts what it was asked to do.
+/// Blocking moves take no time, the position jumps to the target
+/// unless a stop was requested, which cancels them before the first step.
+#[derive(Debug)]
+pub(crate) struct MockMotor {
+    pub(crate) position: i32,
+    pub(crate) direction: Direction,
+    /// Single steps taken with `step`
+    pub(crate) steps: u32,
+    /// Blocking moves made
+    pub(crate) moves: u32,
+    /// Times the coils were released with `stop`
+    pub(crate) stops: u32,
+    pub(crate) limits: Option<SoftLimits>,
+    pub(crate) idle: IdlePolicy,
+    pub(crate) token: Option<StopToken>,
+    /// Follows the position of single steps, for fakes that sense the motor
+    pub(crate) shaft: Option<Rc<Cell<i32>>>,
+}
+
+impl MockMotor {
+    pub(crate) const fn new() -> Self {
+        Self {
+            position: 0,
+            direction: Direction::Normal,
+            steps: 0,
+            moves: 0,
+            stops: 0,
+            limits: None,
+            idle: IdlePolicy::Hold,
+            token: None,
+            shaft: None,
+        }
+    }
+}
+
+impl StepperMotor for MockMotor {
+    fn step(&mut self) -> Result<(), StepError> {
+        self.steps += 1;
+        self.position += match self.direction {
+            Direction::Normal => 1,
+            Direction::Reverse => -1,
+        };
+        if let Some(shaft) = &self.shaft {
+            shaft.set(self.position);
+        }
+        Ok(())
+    }
+
+    fn step_for(&mut self, steps: i32, _delay: u32) -> Result<(), StepError> {
+        let steps = match self.direction {
+            Direction::Normal => steps,
+            Direction::Reverse => -steps,
+        };
+        self.position += steps;
+        self.moves += 1;
+        Ok(())
+    }
+
+    fn move_by(&mut self, delta: i32, _delay: u32) -> Result<(), StepError> {
+        let target = self.position.wrapping_add(delta);
+        if let Some(limits) = self.limits {
+            limits.check(target)?;
+        }
+        if self.stop_requested().is_some() {
+            return Err(StepError::Cancelled { executed: 0 });
+        }
+        self.position = target;
+        self.moves += 1;
+        Ok(())
+    }
+
+    fn move_by_profile(&mut self, delta: i32, _profile: &MotionProfile) -> Result<(), StepError> {
+        self.move_by(delta, 0)
+    }
+
+    fn current_position(&self) -> i32 {
+        self.position
+    }
+
+    fn set_position(&mut self, position: i32) {
+        self.position = position;
+    }
+
+    fn set_soft_limits(&mut self, limits: Option<SoftLimits>) {
+        self.limits = limits;
+    }
+
+    fn soft_limits(&self) -> Option<SoftLimits> {
+        self.limits
+    }
+
+    fn set_idle_policy(&mut self, policy: IdlePolicy) {
+        self.idle = policy;
+    }
+
+    fn idle_policy(&self) -> IdlePolicy {
+        self.idle
+    }
+
+    fn set_stop_token(&mut self, token: Option<StopToken>) {
+        self.token = token;
+    }
+
+    fn stop_token(&self) -> Option<&StopToken> {
+        self.token.as_ref()
+    }
+
+    fn set_direction(&mut self, dir: Direction) {
+        self.direction = dir;
+    }
+
+    fn stop(&mut self) -> Result<(), StepError> {
+        self.stops += 1;
+        Ok(())
+    }
+}
+
+/// Keeps the last duty cycle set, out of 100
+#[derive(Debug)]
+pub(crate) struct MockPwm(pub(crate) u16);
+
+impl ErrorType for MockPwm {
+    type Error = Infallible;
+}
+
+impl SetDutyCycle for MockPwm {
+    fn max_duty_cycle(&self) -> u16 {
+        100
+    }
+
+    fn set_duty_cycle(&mut self, duty: u16) -> Result<(), Infallible> {
+        self.0 = duty;
+        Ok(())
+    }
+}
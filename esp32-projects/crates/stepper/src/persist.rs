@@ -0,0 +1,260 @@
+//! Keeping the position of a motor across reboots
+
+use core::{convert::Infallible, time::Duration};
+
+use crate::{Direction, StepperMotor};
+
+// === Checkpoint ===
+
+/// What a motor needs to continue where it was after a reboot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    position: i32,
+    phase: Option<u32>,
+    microsteps: Option<u16>,
+    engaged: Option<Direction>,
+}
+
+impl Checkpoint {
+    /// Create a new `Checkpoint` of a position only
+    pub const fn new(position: i32) -> Self {
+        Self {
+            position,
+            phase: None,
+            microsteps: None,
+            engaged: None,
+        }
+    }
+
+    /// Add the driver specific state of the coils, so the first step after
+    /// a reboot continues from the coils the rotor was left on
+    #[must_use]
+    pub const fn with_phase(mut self, phase: u32) -> Self {
+        self.phase = Some(phase);
+        self
+    }
+
+    /// Add the microsteps per full step the position is counted in, so it can
+    /// be restored into a driver set to another resolution
+    #[must_use]
+    pub const fn with_microsteps(mut self, microsteps: u16) -> Self {
+        self.microsteps = Some(microsteps);
+        self
+    }
+
+    /// Add the direction the gears were last driven in, kept by `backlash::Backlash`
+    #[must_use]
+    pub const fn with_engaged(mut self, dir: Direction) -> Self {
+        self.engaged = Some(dir);
+        self
+    }
+
+    /// Returns the position in steps
+    pub const fn position(&self) -> i32 {
+        self.position
+    }
+
+    /// Returns the state of the coils, `None` if the driver does not keep one
+    pub const fn phase(&self) -> Option<u32> {
+        self.phase
+    }
+
+    /// Returns the microsteps per full step of the position, `None` if not recorded
+    pub const fn microsteps(&self) -> Option<u16> {
+        self.microsteps
+    }
+
+    /// Returns the direction the gears were last driven in, `None` if not recorded
+    pub const fn engaged(&self) -> Option<Direction> {
+        self.engaged
+    }
+
+    /// Encode into `buf` for stores that keep bytes, returns the used part.
+    /// The position takes 4 bytes, followed by 4 for the phase, 2 for the
+    /// microsteps and 1 for the engaged direction if present, so the length
+    /// tells which are there.
+    pub fn to_bytes<'a>(&self, buf: &'a mut [u8; 11]) -> &'a [u8] {
+        buf[..4].copy_from_slice(&self.position.to_le_bytes());
+        let mut len = 4;
+        if let Some(phase) = self.phase {
+            buf[len..len + 4].copy_from_slice(&phase.to_le_bytes());
+            len += 4;
+        }
+        if let Some(microsteps) = self.microsteps {
+            buf[len..len + 2].copy_from_slice(&microsteps.to_le_bytes());
+            len += 2;
+        }
+        if let Some(dir) = self.engaged {
+            buf[len] = match dir {
+                Direction::Normal => 0,
+                Direction::Reverse => 1,
+            };
+            len += 1;
+        }
+        &buf[..len]
+    }
+
+    /// Decode bytes written by `to_bytes`, `None` if they do not look like a checkpoint
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() > 11 {
+            return None;
+        }
+        let (position, mut rest) = bytes.split_first_chunk::<4>()?;
+        let mut checkpoint = Self::new(i32::from_le_bytes(*position));
+        // the optional parts take 4, 2 and 1 bytes, so what is left tells which follow
+        if let Some((phase, tail)) = rest.split_first_chunk::<4>() {
+            checkpoint = checkpoint.with_phase(u32::from_le_bytes(*phase));
+            rest = tail;
+        }
+        if let Some((microsteps, tail)) = rest.split_first_chunk::<2>() {
+            checkpoint = checkpoint.with_microsteps(u16::from_le_bytes(*microsteps));
+            rest = tail;
+        }
+        match rest {
+            [] => {}
+            [0] => checkpoint = checkpoint.with_engaged(Direction::Normal),
+            [1] => checkpoint = checkpoint.with_engaged(Direction::Reverse),
+            _ => return None,
+        }
+        Some(checkpoint)
+    }
+}
+
+// === PositionStore ===
+
+/// Somewhere to keep a `Checkpoint` that survives a reboot, e.g. flash
+pub trait PositionStore {
+    type Error;
+
+    /// Returns the last saved checkpoint, `None` if nothing was saved yet
+    fn load(&mut self) -> Result<Option<Checkpoint>, Self::Error>;
+    /// Replace the saved checkpoint
+    fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), Self::Error>;
+}
+
+/// Lets a store outlive the `Checkpointer` using it
+impl<S: PositionStore> PositionStore for &mut S {
+    type Error = S::Error;
+
+    fn load(&mut self) -> Result<Option<Checkpoint>, Self::Error> {
+        (**self).load()
+    }
+
+    fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), Self::Error> {
+        (**self).save(checkpoint)
+    }
+}
+
+/// Keeps the checkpoint in RAM, for host tests and boards without flash to spare
+#[derive(Debug, Default, Clone)]
+pub struct MemoryStore {
+    checkpoint: Option<Checkpoint>,
+    writes: u32,
+}
+
+impl MemoryStore {
+    /// Create a new `MemoryStore` with nothing saved yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how often the checkpoint was saved
+    pub const fn writes(&self) -> u32 {
+        self.writes
+    }
+}
+
+impl PositionStore for MemoryStore {
+    type Error = Infallible;
+
+    fn load(&mut self) -> Result<Option<Checkpoint>, Infallible> {
+        Ok(self.checkpoint)
+    }
+
+    fn save(&mut self, checkpoint: &Checkpoint) -> Result<(), Infallible> {
+        self.checkpoint = Some(*checkpoint);
+        self.writes = self.writes.saturating_add(1);
+        Ok(())
+    }
+}
+
+// === Checkpointer ===
+
+/// Saves the checkpoint of a motor to a `PositionStore`, sparing the flash
+///
+/// `update` is meant to be called after every move, or from the firmware main
+/// loop while the motor is at rest. It only writes if the checkpoint changed,
+/// and at most once per interval, a change within the interval is written by
+/// a later `update` once it is over. A reboot loses the steps made since the
+/// last write, so `flush` before a planned restart or deep sleep.
+pub struct Checkpointer<S: PositionStore> {
+    store: S,
+    interval: Duration,
+    saved: Option<Checkpoint>,
+    last_write: Option<Duration>,
+}
+
+impl<S: PositionStore> Checkpointer<S> {
+    /// Create a new `Checkpointer` writing at most once per `interval`
+    pub const fn new(store: S, interval: Duration) -> Self {
+        Self {
+            store,
+            interval,
+            saved: None,
+            last_write: None,
+        }
+    }
+
+    /// Put `motor` back where the saved checkpoint says it is, e.g. on boot.
+    /// Returns false and leaves the motor as it is if nothing was saved yet.
+    pub fn restore<M: StepperMotor>(&mut self, motor: &mut M) -> Result<bool, S::Error> {
+        let Some(checkpoint) = self.store.load()? else {
+            return Ok(false);
+        };
+        motor.restore(&checkpoint);
+        self.saved = Some(checkpoint);
+        Ok(true)
+    }
+
+    /// Save the checkpoint of `motor` if it changed and the interval is over since
+    /// the last write. Returns true if it was written.
+    pub fn update<M: StepperMotor>(&mut self, motor: &M, now: Duration) -> Result<bool, S::Error> {
+        if self
+            .last_write
+            .is_some_and(|last| now.saturating_sub(last) < self.interval)
+        {
+            return Ok(false);
+        }
+        let written = self.flush(motor)?;
+        if written {
+            self.last_write = Some(now);
+        }
+        Ok(written)
+    }
+
+    /// Save the checkpoint of `motor` right away if it changed. Returns true if it was written.
+    pub fn flush<M: StepperMotor>(&mut self, motor: &M) -> Result<bool, S::Error> {
+        let checkpoint = motor.checkpoint();
+        if self.saved == Some(checkpoint) {
+            return Ok(false);
+        }
+        self.store.save(&checkpoint)?;
+        self.saved = Some(checkpoint);
+        Ok(true)
+    }
+
+    /// Returns true if the motor was moved since the last write
+    pub fn is_dirty<M: StepperMotor>(&self, motor: &M) -> bool {
+        self.saved != Some(motor.checkpoint())
+    }
+
+    /// Returns the store the checkpoints are written to
+    pub const fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Stop checkpointing and get the store back
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+}
@@ -0,0 +1,452 @@
+//! Acceleration profiles turning a move into per-step intervals
+//!
+//! Speeds are in steps/s, acceleration in steps/s² and jerk in steps/s³.
+//! Nothing in here touches hardware, a driver only has to issue a step and
+//! wait for the interval returned by `Ramp` before issuing the next one.
+
+use core::time::Duration;
+
+// === MotionProfile ===
+
+/// Speed limits for a move
+///
+/// Limits below `MIN_LIMIT`, including 0, negative and NaN ones, are raised
+/// to it, so a bad configuration moves slowly instead of waiting forever.
+///
+/// A trapezoidal profile accelerates at a constant rate up to the maximum
+/// speed, cruises and decelerates symmetrically. An S-curve profile also
+/// limits the jerk, so the acceleration itself ramps up and down, which is
+/// gentler on geared motors and belts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionProfile {
+    max_speed: f32,
+    acceleration: f32,
+    jerk: Option<f32>,
+}
+
+impl MotionProfile {
+    /// Lowest speed, acceleration and jerk a profile runs with
+    pub const MIN_LIMIT: f32 = 0.01;
+
+    /// Accelerate and decelerate at a constant rate
+    pub const fn trapezoidal(max_speed: f32, acceleration: f32) -> Self {
+        Self {
+            max_speed: limit(max_speed),
+            acceleration: limit(acceleration),
+            jerk: None,
+        }
+    }
+
+    /// Accelerate and decelerate with the change in acceleration limited by `jerk`.
+    /// An infinite jerk gives a trapezoidal profile.
+    pub const fn s_curve(max_speed: f32, acceleration: f32, jerk: f32) -> Self {
+        Self {
+            max_speed: limit(max_speed),
+            acceleration: limit(acceleration),
+            jerk: if jerk == f32::INFINITY {
+                None
+            } else {
+                Some(limit(jerk))
+            },
+        }
+    }
+
+    /// Run at a constant speed from the first step, like a fixed step delay
+    pub const fn constant(speed: f32) -> Self {
+        Self {
+            max_speed: limit(speed),
+            acceleration: f32::INFINITY,
+            jerk: None,
+        }
+    }
+
+    /// Same profile with a different maximum speed
+    #[must_use]
+    pub const fn with_max_speed(mut self, max_speed: f32) -> Self {
+        self.max_speed = limit(max_speed);
+        self
+    }
+
+    /// Same profile counted in other steps, every limit multiplied by `factor`,
+    /// e.g. 16 after switching from full steps to 1/16 microsteps
+    #[must_use]
+    pub fn scaled(self, factor: f32) -> Self {
+        Self {
+            max_speed: limit(self.max_speed * factor),
+            acceleration: limit(self.acceleration * factor),
+            jerk: self.jerk.map(|jerk| limit(jerk * factor)),
+        }
+    }
+
+    /// Returns the maximum speed, in steps/s
+    pub const fn max_speed(&self) -> f32 {
+        self.max_speed
+    }
+
+    /// Returns the maximum acceleration, in steps/s²
+    pub const fn acceleration(&self) -> f32 {
+        self.acceleration
+    }
+
+    /// Returns the jerk limit of an S-curve profile, in steps/s³
+    pub const fn jerk(&self) -> Option<f32> {
+        self.jerk
+    }
+
+    /// Returns the intervals for a move of `steps` steps
+    pub fn ramp(&self, steps: u32) -> Ramp {
+        let mut ramp = Ramp::new(*self);
+        ramp.set_remaining(steps);
+        ramp
+    }
+
+    /// Steps needed to come to a stop from `speed`
+    pub fn stopping_distance(&self, speed: f32) -> f32 {
+        let Some(jerk) = self.jerk else {
+            return speed * speed / (2.0 * self.acceleration);
+        };
+        // time to stop is v/A + A/j when max acceleration is reached, 2*sqrt(v/j) when not
+        let a = self.acceleration;
+        if speed >= a * a / jerk {
+            speed / 2.0 * (speed / a + a / jerk)
+        } else {
+            speed * (speed / jerk).sqrt()
+        }
+    }
+
+    /// Speed reached after the first step from standstill, ramps never go slower than this
+    pub(crate) fn start_speed(&self) -> f32 {
+        let Some(jerk) = self.jerk else {
+            return (2.0 * self.acceleration).sqrt().min(self.max_speed);
+        };
+        // distance under constant jerk is j*t³/6, speed j*t²/2
+        let t = (6.0 / jerk).cbrt();
+        (jerk * t * t / 2.0).min(self.max_speed)
+    }
+}
+
+/// Raise `value` to `MotionProfile::MIN_LIMIT`, NaN included
+const fn limit(value: f32) -> f32 {
+    if value >= MotionProfile::MIN_LIMIT {
+        value
+    } else {
+        MotionProfile::MIN_LIMIT
+    }
+}
+
+// === Ramp ===
+
+/// Generates the time each step of a move takes
+///
+/// The ramp works in the distance domain: every step decides whether to
+/// speed up, cruise or slow down based on the steps left, so the remaining
+/// distance can be changed mid-move and the ramp blends into it.
+#[derive(Debug, Clone)]
+pub struct Ramp {
+    profile: MotionProfile,
+    remaining: u32,
+    speed: f32,
+    acceleration: f32,
+}
+
+impl Ramp {
+    /// Create an idle `Ramp`, standing still with nothing to do
+    pub const fn new(profile: MotionProfile) -> Self {
+        Self {
+            profile,
+            remaining: 0,
+            speed: 0.0,
+            acceleration: 0.0,
+        }
+    }
+
+    /// Change the steps left in the move, keeping the current speed
+    pub fn set_remaining(&mut self, steps: u32) {
+        self.remaining = steps;
+    }
+
+    /// Drop the remaining steps and the current speed, e.g. after the motor was stopped
+    pub fn reset(&mut self) {
+        self.remaining = 0;
+        self.speed = 0.0;
+        self.acceleration = 0.0;
+    }
+
+    /// Shorten the move to come to a stop as quickly as the profile allows
+    pub fn brake(&mut self) {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let stopping = self.profile.stopping_distance(self.speed).ceil() as u32;
+        self.remaining = self.remaining.min(stopping);
+    }
+
+    /// Returns the steps left in the move
+    pub const fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    /// Returns the speed reached by the last step, in steps/s
+    pub const fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Returns the profile the ramp follows
+    pub const fn profile(&self) -> &MotionProfile {
+        &self.profile
+    }
+
+    /// Replace the profile, keeping the current speed so a running move can be retuned
+    pub fn set_profile(&mut self, profile: MotionProfile) {
+        self.profile = profile;
+    }
+
+    /// Returns true if the remaining steps are only just enough to come to a stop
+    fn must_decelerate(&self) -> bool {
+        #[allow(clippy::cast_precision_loss)]
+        let remaining = self.remaining as f32;
+        let stopping = match self.profile.jerk {
+            // a positive acceleration has to be ramped down before slowing down, during
+            // which the motor gains a²/2j in speed and covers v*a/j + a³/3j² steps
+            Some(jerk) if self.acceleration > 0.0 => {
+                let a = self.acceleration;
+                let peak = a.mul_add(a / (2.0 * jerk), self.speed);
+                self.profile.stopping_distance(peak)
+                    + self.speed * a / jerk
+                    + a * a * a / (3.0 * jerk * jerk)
+            }
+            _ => self.profile.stopping_distance(self.speed),
+        };
+        // half a step of slack so float rounding cannot flip the decision back and forth
+        stopping + 0.5 >= remaining
+    }
+
+    /// Speed at the end of the next step and how long the step takes, with constant acceleration
+    fn trapezoidal_step(&self) -> (f32, f32) {
+        let profile = &self.profile;
+        if profile.acceleration.is_infinite() {
+            return (profile.max_speed, 1.0 / profile.max_speed);
+        }
+
+        let change = 2.0 * profile.acceleration;
+        let squared = self.speed * self.speed;
+        let next = if self.must_decelerate() {
+            // v² falls linearly to 0 on the last step, so the move ends at rest on its target
+            #[allow(clippy::cast_precision_loss)]
+            let remaining = self.remaining as f32;
+            self.speed * ((remaining - 1.0) / remaining).sqrt()
+        } else if self.speed > profile.max_speed {
+            // max speed was lowered mid-move
+            (squared - change)
+                .max(profile.max_speed * profile.max_speed)
+                .sqrt()
+        } else {
+            (squared + change)
+                .min(profile.max_speed * profile.max_speed)
+                .sqrt()
+        };
+        // v² changes linearly with distance, so the average speed over the step is the mean
+        (next, 2.0 / (self.speed + next))
+    }
+
+    /// Speed at the end of the next step and how long the step takes, with limited jerk
+    fn s_curve_step(&mut self, jerk: f32) -> (f32, f32) {
+        let profile = self.profile;
+        let start_speed = profile.start_speed();
+        if self.speed < start_speed {
+            self.acceleration = (jerk * (6.0 / jerk).cbrt()).min(profile.acceleration);
+            return (start_speed, (6.0 / jerk).cbrt());
+        }
+
+        let dt = 1.0 / self.speed;
+        // speed gained or lost while ramping the current acceleration back to 0
+        let settle = self.acceleration * self.acceleration / (2.0 * jerk);
+        let target = if self.must_decelerate() {
+            if self.acceleration < 0.0 && self.speed <= settle {
+                0.0
+            } else {
+                -profile.acceleration
+            }
+        } else if self.speed > profile.max_speed {
+            -profile.acceleration
+        } else if profile.max_speed - self.speed <= settle {
+            0.0
+        } else {
+            profile.acceleration
+        };
+
+        let max_change = jerk * dt;
+        self.acceleration += (target - self.acceleration).clamp(-max_change, max_change);
+        let next = self
+            .acceleration
+            .mul_add(dt, self.speed)
+            .clamp(start_speed, profile.max_speed.max(start_speed));
+        (next, dt)
+    }
+
+    /// Returns how long the next step takes, `None` once the move is done.
+    /// Drivers issue the step and then wait for the interval.
+    pub fn next_interval(&mut self) -> Option<Duration> {
+        if self.remaining == 0 {
+            self.reset();
+            return None;
+        }
+
+        let (speed, interval) = match self.profile.jerk {
+            None => self.trapezoidal_step(),
+            Some(jerk) => self.s_curve_step(jerk),
+        };
+        self.remaining -= 1;
+        // the last step ends at rest, slow enough to stop dead on the target
+        self.speed = if self.remaining == 0 { 0.0 } else { speed };
+        // the limits of the profile keep the interval finite, this is only a fallback
+        Some(Duration::try_from_secs_f32(interval).unwrap_or(Duration::MAX))
+    }
+}
+
+impl Iterator for Ramp {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        self.next_interval()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intervals(profile: MotionProfile, steps: u32) -> Vec<f32> {
+        profile.ramp(steps).map(|step| step.as_secs_f32()).collect()
+    }
+
+    #[test]
+    fn ramps_take_exactly_the_requested_steps() {
+        for profile in [
+            MotionProfile::trapezoidal(500.0, 1000.0),
+            MotionProfile::s_curve(500.0, 1000.0, 5000.0),
+            MotionProfile::constant(100.0),
+        ] {
+            for steps in [0, 1, 2, 3, 10, 999] {
+                assert_eq!(profile.ramp(steps).count(), steps as usize, "{profile:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn constant_profile_runs_at_its_speed() {
+        let intervals = intervals(MotionProfile::constant(100.0), 5);
+        assert!(intervals
+            .iter()
+            .all(|&interval| (interval - 0.01).abs() < 1e-6));
+    }
+
+    #[test]
+    fn long_moves_cruise_at_max_speed() {
+        let intervals = intervals(MotionProfile::trapezoidal(500.0, 1000.0), 600);
+        assert!((intervals[300] - 1.0 / 500.0).abs() < 1e-6);
+        assert!(intervals
+            .iter()
+            .all(|&interval| interval >= 1.0 / 500.0 - 1e-6));
+        // v² = 2as, so max speed is reached after 125 steps
+        assert!(intervals[120] > intervals[130]);
+        assert!((intervals[130] - intervals[140]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn short_moves_are_triangular() {
+        let intervals = intervals(MotionProfile::trapezoidal(500.0, 1000.0), 40);
+        let fastest = intervals.iter().copied().fold(f32::INFINITY, f32::min);
+        // 20 steps of acceleration reach sqrt(2 * 1000 * 20) = 200 steps/s
+        assert!(fastest > 1.0 / 210.0 && fastest < 1.0 / 190.0);
+        let peak = intervals
+            .iter()
+            .position(|&interval| interval <= fastest)
+            .unwrap();
+        assert!((18..=21).contains(&peak), "peak at step {peak}");
+        assert!(intervals[..=peak].windows(2).all(|w| w[1] <= w[0]));
+        assert!(intervals[peak..].windows(2).all(|w| w[1] >= w[0]));
+    }
+
+    #[test]
+    fn deceleration_mirrors_acceleration() {
+        let intervals = intervals(MotionProfile::trapezoidal(500.0, 1000.0), 400);
+        for step in 0..100 {
+            let up = intervals[step];
+            let down = intervals[intervals.len() - 1 - step];
+            assert!((up - down).abs() / up < 0.05, "step {step}: {up} vs {down}");
+        }
+    }
+
+    #[test]
+    fn braking_stops_within_the_stopping_distance() {
+        let profile = MotionProfile::trapezoidal(500.0, 1000.0);
+        let mut ramp = profile.ramp(1000);
+        ramp.by_ref().take(200).for_each(drop);
+        assert!((ramp.speed() - 500.0).abs() < 1e-3);
+
+        ramp.brake();
+        // 500² / (2 * 1000) = 125 steps
+        assert_eq!(ramp.remaining(), 125);
+        let rest: Vec<f32> = ramp.by_ref().map(|step| step.as_secs_f32()).collect();
+        assert_eq!(rest.len(), 125);
+        assert!(rest.windows(2).all(|w| w[1] >= w[0]));
+        assert!(ramp.speed().abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn braking_never_lengthens_a_move() {
+        let mut ramp = MotionProfile::trapezoidal(500.0, 1000.0).ramp(1000);
+        ramp.by_ref().take(990).for_each(drop);
+        ramp.brake();
+        assert_eq!(ramp.remaining(), 10);
+    }
+
+    #[test]
+    fn s_curve_limits_the_jerk() {
+        let (max_speed, acceleration, jerk) = (500.0, 1000.0, 5000.0);
+        let mut ramp = MotionProfile::s_curve(max_speed, acceleration, jerk).ramp(800);
+        let mut previous = 0.0_f32;
+        let mut cruised = false;
+        while let Some(interval) = ramp.next_interval() {
+            let change = (ramp.acceleration - previous).abs();
+            assert!(change <= jerk * interval.as_secs_f32() * 1.001);
+            assert!(ramp.acceleration.abs() <= acceleration);
+            assert!(ramp.speed() <= max_speed);
+            cruised |= ramp.speed() >= max_speed;
+            previous = ramp.acceleration;
+        }
+        assert!(cruised);
+    }
+
+    #[test]
+    fn s_curve_starts_gentler_than_trapezoidal() {
+        let trapezoidal = intervals(MotionProfile::trapezoidal(500.0, 1000.0), 600);
+        let s_curve = intervals(MotionProfile::s_curve(500.0, 1000.0, 5000.0), 600);
+        assert!(s_curve[..10].iter().sum::<f32>() > trapezoidal[..10].iter().sum::<f32>());
+        assert!((s_curve[300] - trapezoidal[300]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn invalid_limits_are_raised_to_the_minimum() {
+        for limit in [0.0, -10.0, f32::NAN] {
+            for profile in [
+                MotionProfile::trapezoidal(limit, limit),
+                MotionProfile::s_curve(limit, limit, limit),
+                MotionProfile::constant(limit),
+                MotionProfile::trapezoidal(500.0, 1000.0).with_max_speed(limit),
+            ] {
+                assert!(profile.max_speed() >= MotionProfile::MIN_LIMIT);
+                // the first step from standstill takes twice as long as one at full speed
+                for interval in profile.ramp(3) {
+                    assert!(
+                        interval <= Duration::from_secs(200),
+                        "{profile:?}: {interval:?}"
+                    );
+                }
+            }
+        }
+        assert_eq!(
+            MotionProfile::s_curve(500.0, 1000.0, f32::INFINITY).jerk(),
+            None
+        );
+    }
+}
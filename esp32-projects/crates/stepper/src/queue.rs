@@ -0,0 +1,645 @@
+//! A bounded queue of moves executed back-to-back from a firmware main loop
+
+use core::time::Duration;
+
+use crate::{profile::MotionProfile, runner::StepperRunner, StepError, StepperMotor};
+
+// === Command ===
+
+/// A single entry of a `MoveQueue`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Move to an absolute position
+    MoveTo(i32),
+    /// Move by a signed number of steps from where the previous command ended
+    MoveBy(i32),
+    /// Hold still for a while
+    Wait(Duration),
+    /// Run at `speed` steps/s for `duration`, then decelerate to a stop,
+    /// negative speeds run in `Direction::Reverse`. Completes once at rest,
+    /// a run never passes the soft limits.
+    RunAt { speed: f32, duration: Duration },
+}
+
+/// Reported by `MoveQueue::poll` with the id `enqueue` returned for the command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueEvent {
+    /// The command ran to its end
+    Completed(u32),
+    /// The command was stopped by `clear` or the stop token of the motor
+    Cancelled(u32),
+}
+
+/// The command being executed
+#[derive(Debug, Clone, Copy)]
+struct Current {
+    id: u32,
+    step: Step,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Move(i32),
+    Wait(Duration),
+    Run {
+        speed: f32,
+        until: Duration,
+    },
+    /// a wait or run interrupted by `pause` with the time left, and the speed of a run
+    Held {
+        speed: Option<f32>,
+        left: Duration,
+    },
+    /// a run whose time is up, waiting for the motor to come to rest
+    Ending,
+    /// cancelled, waiting for the motor to come to rest
+    Stopping,
+}
+
+// === MoveQueue ===
+
+/// Executes up to `N` queued commands one after the other
+///
+/// Like the `StepperRunner` it is built on, `poll` is called as often as
+/// possible with the current time. With blending on, a move followed by
+/// another move in the same direction does not stop in between, the motor
+/// carries its speed into the next move instead.
+pub struct MoveQueue<M: StepperMotor, const N: usize> {
+    runner: StepperRunner<M>,
+    commands: [Option<(u32, Command)>; N],
+    head: usize,
+    len: usize,
+    next_id: u32,
+    current: Option<Current>,
+    paused: bool,
+    blend: bool,
+    /// error of a command that failed to start right after the previous one completed
+    error: Option<StepError>,
+}
+
+impl<M: StepperMotor, const N: usize> MoveQueue<M, N> {
+    /// Create a new, empty `MoveQueue` with blending on
+    pub fn new(motor: M, profile: MotionProfile) -> Self {
+        Self {
+            runner: StepperRunner::new(motor, profile),
+            commands: [None; N],
+            head: 0,
+            len: 0,
+            next_id: 0,
+            current: None,
+            paused: false,
+            blend: true,
+            error: None,
+        }
+    }
+
+    /// Come to a stop at the end of every move, even if the next one continues in the same direction
+    #[must_use]
+    pub const fn without_blending(mut self) -> Self {
+        self.blend = false;
+        self
+    }
+
+    /// Add a command to the end of the queue, returns the id its `QueueEvent` reports.
+    /// Hands the command back if the queue is full.
+    pub fn enqueue(&mut self, command: Command) -> Result<u32, Command> {
+        if self.len == N {
+            return Err(command);
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.commands[(self.head + self.len) % N] = Some((id, command));
+        self.len += 1;
+        Ok(id)
+    }
+
+    /// Drop all queued commands and bring the running one to a stop.
+    /// The running command reports `QueueEvent::Cancelled` once the motor is at rest.
+    pub fn clear(&mut self) {
+        self.commands = [None; N];
+        self.head = 0;
+        self.len = 0;
+        if let Some(current) = &mut self.current {
+            current.step = Step::Stopping;
+            self.runner.decelerate();
+        }
+    }
+
+    /// Decelerate to a stop and hold the queue, including a running wait
+    pub fn pause(&mut self) {
+        if !self.paused {
+            self.paused = true;
+            self.runner.decelerate();
+        }
+    }
+
+    /// Continue the interrupted command and the rest of the queue
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Returns true while paused
+    pub const fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Returns the number of commands waiting, not counting the running one
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if no commands are waiting
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true while a command is running or waiting
+    pub const fn is_busy(&self) -> bool {
+        self.current.is_some() || self.len > 0
+    }
+
+    /// Returns the id of the running command
+    pub fn current(&self) -> Option<u32> {
+        self.current.map(|current| current.id)
+    }
+
+    /// Returns the runner moving the motor
+    pub const fn runner(&self) -> &StepperRunner<M> {
+        &self.runner
+    }
+
+    /// Stop using the queue and get the motor back
+    pub fn into_inner(self) -> M {
+        self.runner.into_inner()
+    }
+
+    /// Move the running command on and start the next one once it is done.
+    /// Returns an event when a command completes or is cancelled.
+    ///
+    /// A stop requested through the stop token of the motor cancels the running
+    /// command and drops the queued ones. A queued command that cannot start,
+    /// e.g. a target outside the soft limits, is dropped and returns its error.
+    /// If the previous command completed in the same call, its event is returned
+    /// first and the error by the next `poll`.
+    pub fn poll(&mut self, now: Duration) -> Result<Option<QueueEvent>, StepError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        let running = match self.runner.poll(now) {
+            Ok(running) => running,
+            Err(StepError::Cancelled { .. }) => {
+                self.clear();
+                return Ok(self.finish(QueueEvent::Cancelled));
+            }
+            Err(err) => return Err(err),
+        };
+
+        let Some(current) = &mut self.current else {
+            if !self.paused {
+                self.start_next(now)?;
+            }
+            return Ok(None);
+        };
+        if self.paused {
+            match current.step {
+                Step::Wait(until) => {
+                    current.step = Step::Held {
+                        speed: None,
+                        left: until.saturating_sub(now),
+                    };
+                }
+                Step::Run { speed, until } => {
+                    current.step = Step::Held {
+                        speed: Some(speed),
+                        left: until.saturating_sub(now),
+                    };
+                }
+                Step::Stopping if !running => return Ok(self.finish(QueueEvent::Cancelled)),
+                _ => {}
+            }
+            return Ok(None);
+        }
+
+        match current.step {
+            Step::Move(target) if self.runner.target() != target => {
+                // resumed after a pause
+                self.runner.move_to(target)?;
+                Ok(None)
+            }
+            Step::Move(_) if running => Ok(self.blend()),
+            Step::Wait(until) | Step::Run { until, .. } if now < until => Ok(None),
+            Step::Run { .. } => {
+                self.runner.decelerate();
+                current.step = Step::Ending;
+                Ok(None)
+            }
+            Step::Held { speed: None, left } => {
+                current.step = Step::Wait(now + left);
+                Ok(None)
+            }
+            Step::Held {
+                speed: Some(speed),
+                left,
+            } => {
+                self.runner.run_at_speed(speed)?;
+                current.step = Step::Run {
+                    speed,
+                    until: now + left,
+                };
+                Ok(None)
+            }
+            Step::Ending | Step::Stopping if running => Ok(None),
+            Step::Stopping => Ok(self.finish(QueueEvent::Cancelled)),
+            Step::Move(_) | Step::Wait(_) | Step::Ending => {
+                let event = self.finish(QueueEvent::Completed);
+                self.error = self.start_next(now).err();
+                Ok(event)
+            }
+        }
+    }
+
+    /// Drop the running command, returning its event
+    fn finish(&mut self, event: fn(u32) -> QueueEvent) -> Option<QueueEvent> {
+        let current = self.current.take()?;
+        Some(event(current.id))
+    }
+
+    fn pop(&mut self) -> Option<(u32, Command)> {
+        if self.len == 0 {
+            return None;
+        }
+        let entry = self.commands[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        entry
+    }
+
+    fn start_next(&mut self, now: Duration) -> Result<(), StepError> {
+        let Some((id, command)) = self.pop() else {
+            return Ok(());
+        };
+        let position = self.runner.motor().current_position();
+        let step = match command {
+            Command::MoveTo(target) => Step::Move(target),
+            Command::MoveBy(delta) => Step::Move(position.wrapping_add(delta)),
+            Command::Wait(duration) => Step::Wait(now + duration),
+            Command::RunAt { speed, duration } => Step::Run {
+                speed,
+                until: now + duration,
+            },
+        };
+        self.current = Some(Current { id, step });
+        let started = match step {
+            Step::Move(target) => self.runner.move_to(target),
+            Step::Run { speed, .. } => self.runner.run_at_speed(speed),
+            _ => Ok(()),
+        };
+        if let Err(err) = started {
+            self.finish(QueueEvent::Cancelled);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Hand over to the next move early if it continues in the same direction,
+    /// so the motor does not decelerate in between
+    fn blend(&mut self) -> Option<QueueEvent> {
+        let current = self.current.filter(|_| self.blend)?;
+        let Step::Move(target) = current.step else {
+            return None;
+        };
+        let next = match self.commands[self.head] {
+            Some((_, Command::MoveTo(next))) => next,
+            Some((_, Command::MoveBy(delta))) => target.wrapping_add(delta),
+            _ => return None,
+        };
+
+        let position = self.runner.motor().current_position();
+        let ahead = target.wrapping_sub(position);
+        let beyond = next.wrapping_sub(target);
+        let stopping = self
+            .runner
+            .profile()
+            .stopping_distance(self.runner.speed().abs());
+        #[allow(clippy::cast_precision_loss)]
+        let decelerating = ahead.unsigned_abs() as f32 <= stopping + 1.0;
+        if ahead.signum() * beyond.signum() <= 0 || !decelerating {
+            return None;
+        }
+
+        let (id, _) = self.pop()?;
+        let event = self.finish(QueueEvent::Completed);
+        if let Err(err) = self.runner.move_to(next) {
+            // dropped like a command failing in `start_next`, the motor stops at `target`
+            self.error = Some(err);
+            return event;
+        }
+        self.current = Some(Current {
+            id,
+            step: Step::Move(next),
+        });
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock::MockMotor, SoftLimits};
+
+    const PROFILE: MotionProfile = MotionProfile::trapezoidal(500.0, 1000.0);
+    const TICK: Duration = Duration::from_micros(100);
+
+    /// Poll every tick from `start` until the queue is idle, collecting the events and errors
+    fn run<const N: usize>(
+        queue: &mut MoveQueue<MockMotor, N>,
+        start: Duration,
+    ) -> Vec<Result<QueueEvent, StepError>> {
+        let mut now = start;
+        let mut events = Vec::new();
+        let mut idle = false;
+        for _ in 0..1_000_000 {
+            // one more poll once idle, for an error left by the last one
+            let done = idle;
+            if let Some(event) = queue.poll(now).transpose() {
+                events.push(event);
+            }
+            idle = !queue.is_busy() && !queue.runner().is_running();
+            if done && idle {
+                return events;
+            }
+            now += TICK;
+        }
+        panic!("queue never ran empty");
+    }
+
+    /// Poll until `duration` has passed
+    fn run_for<const N: usize>(
+        queue: &mut MoveQueue<MockMotor, N>,
+        now: &mut Duration,
+        duration: Duration,
+    ) -> Vec<QueueEvent> {
+        let end = *now + duration;
+        let mut events = Vec::new();
+        while *now < end {
+            events.extend(queue.poll(*now).unwrap());
+            *now += TICK;
+        }
+        events
+    }
+
+    #[test]
+    fn runs_commands_in_order() {
+        let mut queue = MoveQueue::<_, 4>::new(MockMotor::new(), PROFILE);
+        let ids = [
+            Command::MoveTo(100),
+            Command::MoveBy(-50),
+            Command::Wait(Duration::from_millis(10)),
+            Command::MoveBy(20),
+        ]
+        .map(|command| queue.enqueue(command).unwrap());
+        assert_eq!(ids, [0, 1, 2, 3]);
+
+        let events = run(&mut queue, Duration::ZERO);
+        assert_eq!(events, ids.map(|id| Ok(QueueEvent::Completed(id))));
+        assert_eq!(queue.runner().motor().position, 70);
+    }
+
+    #[test]
+    fn waits_for_the_given_time() {
+        let mut queue = MoveQueue::<_, 2>::new(MockMotor::new(), PROFILE);
+        queue
+            .enqueue(Command::Wait(Duration::from_millis(5)))
+            .unwrap();
+        let mut now = Duration::ZERO;
+        assert_eq!(queue.poll(now), Ok(None));
+        now += Duration::from_micros(4_900);
+        assert_eq!(queue.poll(now), Ok(None));
+        now += TICK;
+        assert_eq!(queue.poll(now), Ok(Some(QueueEvent::Completed(0))));
+    }
+
+    /// Lowest speed between two moves, while passing `position`
+    fn speed_at(mut queue: MoveQueue<MockMotor, 2>, position: i32) -> f32 {
+        queue.enqueue(Command::MoveTo(position)).unwrap();
+        queue.enqueue(Command::MoveTo(2 * position)).unwrap();
+        let mut now = Duration::ZERO;
+        let mut slowest = f32::INFINITY;
+        while queue.runner().motor().position < 2 * position {
+            queue.poll(now).unwrap();
+            if (position - 5..=position + 5).contains(&queue.runner().motor().position) {
+                slowest = slowest.min(queue.runner().speed().abs());
+            }
+            now += TICK;
+        }
+        slowest
+    }
+
+    #[test]
+    fn blends_moves_in_the_same_direction() {
+        let blended = speed_at(MoveQueue::new(MockMotor::new(), PROFILE), 300);
+        assert!(blended > 400.0, "slowed down to {blended}");
+
+        let stopped = speed_at(
+            MoveQueue::new(MockMotor::new(), PROFILE).without_blending(),
+            300,
+        );
+        assert!(stopped < 1.0, "only slowed down to {stopped}");
+    }
+
+    #[test]
+    fn does_not_blend_a_reversal() {
+        let mut queue = MoveQueue::<_, 2>::new(MockMotor::new(), PROFILE);
+        queue.enqueue(Command::MoveTo(300)).unwrap();
+        queue.enqueue(Command::MoveTo(0)).unwrap();
+        let mut now = Duration::ZERO;
+        let mut furthest = 0;
+        while queue.is_busy() {
+            queue.poll(now).unwrap();
+            furthest = furthest.max(queue.runner().motor().position);
+            now += TICK;
+        }
+        assert_eq!(furthest, 300);
+        assert_eq!(queue.runner().motor().position, 0);
+    }
+
+    #[test]
+    fn pausing_holds_a_wait() {
+        let mut queue = MoveQueue::<_, 2>::new(MockMotor::new(), PROFILE);
+        queue
+            .enqueue(Command::Wait(Duration::from_millis(100)))
+            .unwrap();
+        queue.enqueue(Command::MoveTo(10)).unwrap();
+
+        let mut now = Duration::ZERO;
+        assert!(run_for(&mut queue, &mut now, Duration::from_millis(60)).is_empty());
+        queue.pause();
+        assert!(queue.is_paused());
+        // 40 ms were left, but nothing happens while paused
+        assert!(run_for(&mut queue, &mut now, Duration::from_millis(500)).is_empty());
+        assert_eq!(queue.current(), Some(0));
+
+        queue.resume();
+        let events = run_for(&mut queue, &mut now, Duration::from_millis(39));
+        assert!(events.is_empty());
+        let events = run_for(&mut queue, &mut now, Duration::from_millis(2));
+        assert_eq!(events, [QueueEvent::Completed(0)]);
+        assert_eq!(run(&mut queue, now), [Ok(QueueEvent::Completed(1))]);
+    }
+
+    #[test]
+    fn pausing_stops_and_resumes_a_move() {
+        let mut queue = MoveQueue::<_, 2>::new(MockMotor::new(), PROFILE);
+        queue.enqueue(Command::MoveTo(1000)).unwrap();
+        let mut now = Duration::ZERO;
+        run_for(&mut queue, &mut now, Duration::from_millis(500));
+        queue.pause();
+        run_for(&mut queue, &mut now, Duration::from_secs(1));
+        let held = queue.runner().motor().position;
+        assert!(!queue.runner().is_running());
+        assert!(held < 1000);
+
+        queue.resume();
+        assert_eq!(run(&mut queue, now), [Ok(QueueEvent::Completed(0))]);
+        assert_eq!(queue.runner().motor().position, 1000);
+    }
+
+    #[test]
+    fn clear_cancels_the_running_command_and_drops_the_rest() {
+        let mut queue = MoveQueue::<_, 4>::new(MockMotor::new(), PROFILE);
+        queue.enqueue(Command::MoveTo(1000)).unwrap();
+        queue.enqueue(Command::MoveTo(2000)).unwrap();
+        let mut now = Duration::ZERO;
+        run_for(&mut queue, &mut now, Duration::from_millis(500));
+        queue.clear();
+        assert!(queue.is_empty());
+
+        assert_eq!(run(&mut queue, now), [Ok(QueueEvent::Cancelled(0))]);
+        let position = queue.runner().motor().position;
+        assert!(position > 0 && position < 1000, "stopped at {position}");
+    }
+
+    #[test]
+    fn hands_back_commands_while_full() {
+        let mut queue = MoveQueue::<_, 2>::new(MockMotor::new(), PROFILE);
+        assert_eq!(queue.enqueue(Command::MoveTo(10)), Ok(0));
+        assert_eq!(queue.enqueue(Command::MoveTo(20)), Ok(1));
+        assert_eq!(queue.enqueue(Command::MoveTo(30)), Err(Command::MoveTo(30)));
+        assert_eq!(queue.len(), 2);
+
+        // the running command does not take a slot
+        queue.poll(Duration::ZERO).unwrap();
+        assert_eq!(queue.current(), Some(0));
+        assert_eq!(queue.enqueue(Command::MoveTo(30)), Ok(2));
+    }
+
+    #[test]
+    fn reports_the_completion_before_the_next_command_fails() {
+        for blend in [true, false] {
+            let mut motor = MockMotor::new();
+            motor.limits = Some(SoftLimits::new(0, 100));
+            let mut queue = MoveQueue::<_, 2>::new(motor, PROFILE);
+            if !blend {
+                queue = queue.without_blending();
+            }
+            queue.enqueue(Command::MoveTo(50)).unwrap();
+            queue.enqueue(Command::MoveTo(200)).unwrap();
+
+            let events = run(&mut queue, Duration::ZERO);
+            assert!(
+                matches!(
+                    events[..],
+                    [
+                        Ok(QueueEvent::Completed(0)),
+                        Err(StepError::LimitViolation { target: 200, .. })
+                    ]
+                ),
+                "{events:?}"
+            );
+            assert_eq!(queue.runner().motor().position, 50);
+        }
+    }
+
+    #[test]
+    fn settles_on_every_target_with_s_curves() {
+        for profile in [PROFILE, MotionProfile::s_curve(500.0, 1000.0, 5000.0)] {
+            for blend in [true, false] {
+                let mut queue = MoveQueue::<_, 4>::new(MockMotor::new(), profile);
+                if !blend {
+                    queue = queue.without_blending();
+                }
+                let ids = [
+                    Command::MoveTo(37),
+                    Command::MoveBy(-74),
+                    Command::MoveTo(1),
+                    Command::MoveBy(400),
+                ]
+                .map(|command| queue.enqueue(command).unwrap());
+
+                let events = run(&mut queue, Duration::ZERO);
+                assert_eq!(events, ids.map(|id| Ok(QueueEvent::Completed(id))));
+                assert_eq!(queue.runner().motor().position, 401, "{profile:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn runs_at_speed_for_the_duration() {
+        for profile in [PROFILE, MotionProfile::s_curve(500.0, 1000.0, 5000.0)] {
+            let mut queue = MoveQueue::<_, 2>::new(MockMotor::new(), profile);
+            let run_at = Command::RunAt {
+                speed: -300.0,
+                duration: Duration::from_secs(1),
+            };
+            queue.enqueue(run_at).unwrap();
+
+            let mut now = Duration::ZERO;
+            assert!(run_for(&mut queue, &mut now, Duration::from_millis(990)).is_empty());
+            let speed = queue.runner().speed();
+            assert!((speed + 300.0).abs() < 1.0, "{profile:?}: {speed}");
+
+            // still at speed when the time is up, then braking takes as long as speeding up
+            let mut stopped = None;
+            while stopped.is_none() {
+                if queue.poll(now).unwrap() == Some(QueueEvent::Completed(0)) {
+                    stopped = Some(now);
+                }
+                now += TICK;
+            }
+            let stopped = stopped.unwrap();
+            assert!(
+                stopped > Duration::from_millis(1100),
+                "{profile:?}: {stopped:?}"
+            );
+            assert!(
+                stopped < Duration::from_millis(1600),
+                "{profile:?}: {stopped:?}"
+            );
+            assert!(!queue.runner().is_running());
+            assert_eq!(queue.runner().profile(), &profile);
+        }
+    }
+
+    #[test]
+    fn pausing_holds_a_run() {
+        let mut queue = MoveQueue::<_, 2>::new(MockMotor::new(), PROFILE);
+        let run_at = Command::RunAt {
+            speed: 300.0,
+            duration: Duration::from_millis(500),
+        };
+        queue.enqueue(run_at).unwrap();
+
+        let mut now = Duration::ZERO;
+        run_for(&mut queue, &mut now, Duration::from_millis(300));
+        queue.pause();
+        assert!(run_for(&mut queue, &mut now, Duration::from_secs(1)).is_empty());
+        assert!(!queue.runner().is_running());
+
+        // 200 ms of the run were left
+        queue.resume();
+        run_for(&mut queue, &mut now, Duration::from_millis(190));
+        assert!(queue.runner().is_running());
+        assert_eq!(queue.current(), Some(0));
+        assert_eq!(run(&mut queue, now), [Ok(QueueEvent::Completed(0))]);
+    }
+}
@@ -0,0 +1,413 @@
+//! Non-blocking motion for a firmware main loop
+
+use core::time::Duration;
+
+use crate::{
+    idle,
+    profile::{MotionProfile, Ramp},
+    stop::StopMode,
+    Direction, SoftLimits, StepError, StepperMotor,
+};
+
+/// Drives a `StepperMotor` from a loop without blocking
+///
+/// Instead of sleeping between steps, `poll` is called as often as possible
+/// with the current time and issues a step only once the next one is due.
+/// This leaves the loop free to serve requests, read sensors or poll other
+/// runners in between. The time can be any monotonic clock, e.g. time since boot.
+/// Besides moving to targets it can turn at a set speed until told otherwise.
+pub struct StepperRunner<M: StepperMotor> {
+    motor: M,
+    profile: MotionProfile,
+    ramp: Ramp,
+    target: i32,
+    /// signed speed set by `run_at_speed`, moves the target ahead on every poll
+    jog: Option<f32>,
+    dir: Direction,
+    next_step: Option<Duration>,
+    /// steps made since the last `move_to`, and whether a stop is decelerating the motor
+    executed: u32,
+    stopping: bool,
+    /// when the motor came to rest, and whether the idle policy was applied since
+    idle_since: Option<Duration>,
+    idle_applied: bool,
+}
+
+impl<M: StepperMotor> StepperRunner<M> {
+    /// Create a new `StepperRunner`, holding the motor at its current position
+    pub fn new(motor: M, profile: MotionProfile) -> Self {
+        let target = motor.current_position();
+        Self {
+            motor,
+            profile,
+            ramp: Ramp::new(profile),
+            target,
+            jog: None,
+            dir: Direction::Normal,
+            next_step: None,
+            executed: 0,
+            stopping: false,
+            idle_since: None,
+            idle_applied: false,
+        }
+    }
+
+    /// Set a new absolute target. Takes over smoothly from a running move,
+    /// decelerating first if the new target is behind the motor.
+    /// Fails and keeps the old target if the new one is outside the soft limits,
+    /// or while the stop token of the motor is set.
+    pub fn move_to(&mut self, target: i32) -> Result<(), StepError> {
+        if let Some(limits) = self.motor.soft_limits() {
+            limits.check(target)?;
+        }
+        if self.motor.stop_requested().is_some() {
+            return Err(StepError::Cancelled { executed: 0 });
+        }
+        self.end_jog();
+        self.target = target;
+        self.executed = 0;
+        Ok(())
+    }
+
+    /// Set a new target relative to the current position
+    pub fn move_by(&mut self, delta: i32) -> Result<(), StepError> {
+        self.move_to(self.motor.current_position().wrapping_add(delta))
+    }
+
+    /// Turn at a signed speed in steps/s until told otherwise, e.g. for a turntable.
+    /// Speeds up or slows down from the current speed as the profile allows,
+    /// coming to a stop first if the direction changes, so it can be called
+    /// again at any time to change the speed. Stops at the soft limits if set.
+    ///
+    /// A speed of 0 decelerates to a stop, as does `decelerate`, while `move_to`
+    /// takes over smoothly. Fails while the stop token of the motor is set, or
+    /// with `StepError::InvalidSpeed` for a speed that is not finite.
+    pub fn run_at_speed(&mut self, speed: f32) -> Result<(), StepError> {
+        if !speed.is_finite() {
+            return Err(StepError::InvalidSpeed);
+        }
+        if speed == 0.0 {
+            self.decelerate();
+            return Ok(());
+        }
+        if self.motor.stop_requested().is_some() {
+            return Err(StepError::Cancelled { executed: 0 });
+        }
+        self.jog = Some(speed);
+        self.ramp
+            .set_profile(self.profile.with_max_speed(speed.abs()));
+        self.executed = 0;
+        Ok(())
+    }
+
+    /// Returns the speed set by `run_at_speed`, `None` while moving to a target
+    pub const fn jog_speed(&self) -> Option<f32> {
+        self.jog
+    }
+
+    /// Change the speed limits, takes effect from the next step.
+    /// A running `run_at_speed` keeps its speed.
+    pub fn set_profile(&mut self, profile: MotionProfile) {
+        self.profile = profile;
+        let profile = self
+            .jog
+            .map_or(profile, |speed| profile.with_max_speed(speed.abs()));
+        self.ramp.set_profile(profile);
+    }
+
+    /// Returns the speed limits
+    pub const fn profile(&self) -> &MotionProfile {
+        self.ramp.profile()
+    }
+
+    /// Come to a stop as quickly as the profile allows, replacing the target
+    pub fn decelerate(&mut self) {
+        self.end_jog();
+        let position = self.motor.current_position();
+        self.target = stopping_target(&self.ramp, self.dir, position, position);
+    }
+
+    /// Returns the target position
+    pub const fn target(&self) -> i32 {
+        self.target
+    }
+
+    /// Returns true while the motor is moving or has steps left
+    pub fn is_running(&self) -> bool {
+        self.next_step.is_some() || self.remaining_steps() > 0
+    }
+
+    /// Returns the steps left to the target
+    pub fn remaining_steps(&self) -> u32 {
+        self.target
+            .wrapping_sub(self.motor.current_position())
+            .unsigned_abs()
+    }
+
+    /// Returns the current speed in steps/s, negative in `Direction::Reverse`
+    pub fn speed(&self) -> f32 {
+        match self.dir {
+            Direction::Normal => self.ramp.speed(),
+            Direction::Reverse => -self.ramp.speed(),
+        }
+    }
+
+    /// Returns the driven motor, e.g. to read its position
+    pub const fn motor(&self) -> &M {
+        &self.motor
+    }
+
+    /// Returns the driven motor mutably. Moving it directly while the runner
+    /// is running makes the runner continue from wherever it ends up.
+    pub fn motor_mut(&mut self) -> &mut M {
+        &mut self.motor
+    }
+
+    /// Stop using the runner and get the motor back
+    pub fn into_inner(self) -> M {
+        self.motor
+    }
+
+    /// Issue a step if one is due. Returns true while the motor is still running.
+    /// Keep polling at rest as well, so the idle policy of the motor is applied.
+    ///
+    /// A move cancelled through the stop token of the motor returns
+    /// `StepError::Cancelled` once, when the motor has come to a stop.
+    pub fn poll(&mut self, now: Duration) -> Result<bool, StepError> {
+        let position = self.motor.current_position();
+        match self.motor.stop_requested() {
+            Some(StopMode::Halt) if self.is_running() => {
+                self.end_jog();
+                self.target = position;
+                self.ramp.reset();
+                self.next_step = None;
+                self.stopping = false;
+                return Err(StepError::Cancelled {
+                    executed: self.executed,
+                });
+            }
+            Some(StopMode::Decelerate) if !self.stopping && self.is_running() => {
+                self.end_jog();
+                self.target = stopping_target(&self.ramp, self.dir, position, self.target);
+                self.stopping = true;
+            }
+            _ => {}
+        }
+        if let Some(speed) = self.jog {
+            self.target = jog_target(self.motor.soft_limits(), position, speed);
+        }
+
+        let due = self.next_step;
+        if due.is_some_and(|due| now < due) {
+            return Ok(true);
+        }
+
+        let delta = self.target.wrapping_sub(position);
+        let Some((dir, interval)) = plan_step(&mut self.ramp, self.dir, delta) else {
+            self.next_step = None;
+            if self.stopping {
+                self.stopping = false;
+                return Err(StepError::Cancelled {
+                    executed: self.executed,
+                });
+            }
+            self.poll_idle(now)?;
+            return Ok(false);
+        };
+        self.idle_since = None;
+        self.idle_applied = false;
+
+        self.motor.set_direction(dir);
+        self.motor.step()?;
+        self.dir = dir;
+        self.executed = self.executed.saturating_add(1);
+
+        // schedule from the due time so late polls do not add up, unless we fell a whole step behind
+        let base = match due {
+            Some(due) if now.saturating_sub(due) < interval => due,
+            _ => now,
+        };
+        self.next_step = Some(base + interval);
+        Ok(true)
+    }
+
+    /// Go back to the speed limits of the profile after `run_at_speed`
+    fn end_jog(&mut self) {
+        if self.jog.take().is_some() {
+            self.ramp.set_profile(self.profile);
+        }
+    }
+
+    fn poll_idle(&mut self, now: Duration) -> Result<(), StepError> {
+        let since = *self.idle_since.get_or_insert(now);
+        let policy = self.motor.idle_policy();
+        if !self.idle_applied
+            && policy
+                .delay()
+                .is_some_and(|delay| now.saturating_sub(since) >= delay)
+        {
+            idle::apply(&mut self.motor)?;
+            self.idle_applied = true;
+        }
+        Ok(())
+    }
+}
+
+/// Picks the direction and interval of the next step towards a target `delta` steps away,
+/// `None` once at rest on the target. `dir` is the direction of the previous step.
+pub(crate) fn plan_step(
+    ramp: &mut Ramp,
+    dir: Direction,
+    delta: i32,
+) -> Option<(Direction, Duration)> {
+    let wanted = if delta < 0 {
+        Direction::Reverse
+    } else {
+        Direction::Normal
+    };
+
+    if delta == 0 || wanted != dir {
+        // a motor no faster than its first step counts as at rest, it can stop dead
+        if ramp.speed() <= ramp.profile().start_speed() {
+            ramp.reset();
+        } else {
+            // overshooting or reversing: brake to a stop before heading back. Only
+            // ever shortening the steps left lets the brake ramp run to its end.
+            ramp.brake();
+            return ramp.next_interval().map(|interval| (dir, interval));
+        }
+    }
+
+    ramp.set_remaining(delta.unsigned_abs());
+    ramp.next_interval().map(|interval| (wanted, interval))
+}
+
+/// Target that brings the motor at `position` to a stop as quickly as the profile allows,
+/// or `target` if that is closer ahead
+pub(crate) fn stopping_target(ramp: &Ramp, dir: Direction, position: i32, target: i32) -> i32 {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let stopping = ramp.profile().stopping_distance(ramp.speed()).ceil() as u32;
+    let delta = target.wrapping_sub(position);
+    let ahead = match dir {
+        Direction::Normal => delta > 0,
+        Direction::Reverse => delta < 0,
+    };
+    let steps = if ahead {
+        delta.unsigned_abs().min(stopping)
+    } else {
+        stopping
+    };
+    let steps = i32::try_from(steps).unwrap_or(i32::MAX);
+    match dir {
+        Direction::Normal => position.wrapping_add(steps),
+        Direction::Reverse => position.wrapping_sub(steps),
+    }
+}
+
+/// Target of a motor at `position` turning at a signed `speed`: the soft limit in that
+/// direction, or so far ahead that the ramp never starts decelerating
+pub(crate) fn jog_target(limits: Option<SoftLimits>, position: i32, speed: f32) -> i32 {
+    match limits {
+        Some(limits) if speed < 0.0 => limits.min(),
+        Some(limits) => limits.max(),
+        None if speed < 0.0 => position.wrapping_sub(i32::MAX),
+        None => position.wrapping_add(i32::MAX),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockMotor;
+
+    const PROFILE: MotionProfile = MotionProfile::trapezoidal(500.0, 1000.0);
+    const PROFILES: [MotionProfile; 4] = [
+        PROFILE,
+        MotionProfile::trapezoidal(100.0, 50.0),
+        MotionProfile::s_curve(500.0, 1000.0, 5000.0),
+        MotionProfile::s_curve(200.0, 400.0, 800.0),
+    ];
+    const TARGETS: [i32; 9] = [1, 2, 5, 37, 400, -1, -2, -37, -123];
+
+    /// Poll every 100 µs until the runner is at rest, returns the lowest and
+    /// highest position passed on the way
+    fn run(runner: &mut StepperRunner<MockMotor>) -> Result<(i32, i32), StepError> {
+        let mut now = Duration::ZERO;
+        let mut range = (runner.motor().position, runner.motor().position);
+        for _ in 0..1_000_000 {
+            let running = runner.poll(now)?;
+            let position = runner.motor().position;
+            range = (range.0.min(position), range.1.max(position));
+            if !running {
+                return Ok(range);
+            }
+            now += Duration::from_micros(100);
+        }
+        panic!("still running at {now:?}");
+    }
+
+    #[test]
+    fn settles_on_the_target_without_overshoot() {
+        for profile in PROFILES {
+            for target in TARGETS {
+                let mut runner = StepperRunner::new(MockMotor::new(), profile);
+                runner.move_to(target).unwrap();
+                let range = run(&mut runner).unwrap();
+                assert_eq!(
+                    range,
+                    (target.min(0), target.max(0)),
+                    "{profile:?} to {target}"
+                );
+                assert_eq!(runner.motor().position, target);
+                assert!(!runner.is_running());
+                assert!(runner.speed().abs() < f32::EPSILON);
+            }
+        }
+    }
+
+    #[test]
+    fn settles_after_reversing_mid_move() {
+        for profile in PROFILES {
+            for target in TARGETS {
+                let mut runner = StepperRunner::new(MockMotor::new(), profile);
+                runner.move_to(1000).unwrap();
+                let mut now = Duration::ZERO;
+                while runner.motor().position < 150 {
+                    runner.poll(now).unwrap();
+                    now += Duration::from_micros(100);
+                }
+                runner.move_to(target).unwrap();
+                run(&mut runner).unwrap();
+                assert_eq!(runner.motor().position, target, "{profile:?} to {target}");
+                assert!(!runner.is_running());
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_speeds_that_are_not_finite() {
+        let mut runner = StepperRunner::new(MockMotor::new(), PROFILE);
+        for speed in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+            assert_eq!(runner.run_at_speed(speed), Err(StepError::InvalidSpeed));
+        }
+        assert_eq!(runner.jog_speed(), None);
+    }
+
+    #[test]
+    fn runs_up_to_the_soft_limits() {
+        for profile in [PROFILE, MotionProfile::s_curve(500.0, 1000.0, 5000.0)] {
+            let mut motor = MockMotor::new();
+            motor.limits = Some(SoftLimits::new(-40, 250));
+            let mut runner = StepperRunner::new(motor, profile);
+
+            runner.run_at_speed(300.0).unwrap();
+            assert_eq!(run(&mut runner).unwrap(), (0, 250), "{profile:?}");
+            assert!(!runner.is_running(), "{profile:?}");
+
+            runner.run_at_speed(-300.0).unwrap();
+            assert_eq!(run(&mut runner).unwrap(), (-40, 250), "{profile:?}");
+            assert!(!runner.is_running(), "{profile:?}");
+            assert_eq!(runner.motor().position, -40, "{profile:?}");
+        }
+    }
+}
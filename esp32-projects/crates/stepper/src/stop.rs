@@ -0,0 +1,57 @@
+//! Cancelling moves from another thread or an interrupt
+
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+const RUN: u8 = 0;
+const DECELERATE: u8 = 1;
+const HALT: u8 = 2;
+
+/// How a move should come to a stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopMode {
+    /// Decelerate as set by the profile, the position stays exact
+    Decelerate,
+    /// Stop dead before the next step, steps can be lost at high speed
+    Halt,
+}
+
+/// Shared flag that cancels the running move of the motors holding a clone
+///
+/// Triggering it only sets an atomic, so it is safe to call from an
+/// interrupt. The flag stays set and blocks further moves until `reset`.
+/// Cancelled moves return `StepError::Cancelled` with the steps they made.
+#[derive(Debug, Clone, Default)]
+pub struct StopToken(Arc<AtomicU8>);
+
+impl StopToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the running move decelerate to a stop
+    pub fn stop(&self) {
+        self.0.fetch_max(DECELERATE, Ordering::Release);
+    }
+
+    /// Make the running move stop dead, for emergencies
+    pub fn halt(&self) {
+        self.0.store(HALT, Ordering::Release);
+    }
+
+    /// Allow moves again
+    pub fn reset(&self) {
+        self.0.store(RUN, Ordering::Release);
+    }
+
+    /// Returns the requested stop, `None` while moves may run
+    pub fn requested(&self) -> Option<StopMode> {
+        match self.0.load(Ordering::Acquire) {
+            RUN => None,
+            DECELERATE => Some(StopMode::Decelerate),
+            _ => Some(StopMode::Halt),
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Assertions on recorded pin patterns, panicking with the first violation found
+
+// panicking is the whole point of an assertion
+#![allow(clippy::missing_panics_doc)]
+
+use core::time::Duration;
+
+use crate::{Pattern, Recorder};
+
+/// Asserts that the patterns step through `table` one entry at a time
+///
+/// Every pattern has to be an entry of the table and be followed by a
+/// neighbouring entry, wrapping around at the ends. Returns the number of
+/// entries walked through, negative when walking the table backwards.
+#[track_caller]
+pub fn assert_walks_table<const N: usize>(patterns: &[Pattern<N>], table: &[[bool; N]]) -> i32 {
+    let index = |pattern: &Pattern<N>| {
+        table
+            .iter()
+            .position(|entry| *entry == pattern.levels)
+            .unwrap_or_else(|| {
+                panic!(
+                    "pattern {:?} at {:?} is not in the table",
+                    pattern.levels, pattern.at
+                )
+            })
+    };
+
+    let mut walked = 0;
+    for pair in patterns.windows(2) {
+        let (from, to) = (index(&pair[0]), index(&pair[1]));
+        if to == (from + 1) % table.len() {
+            walked += 1;
+        } else if from == (to + 1) % table.len() {
+            walked -= 1;
+        } else {
+            panic!(
+                "jumped from entry {from} to {to} of the table at {:?}",
+                pair[1].at
+            );
+        }
+    }
+    walked
+}
+
+/// Asserts that the energised coils of every pattern are neighbours,
+/// with the first and last coil counting as neighbours as well
+#[track_caller]
+pub fn assert_adjacent_coils<const N: usize>(patterns: &[Pattern<N>]) {
+    for pattern in patterns {
+        let on: Vec<usize> = (0..N).filter(|&coil| pattern.levels[coil]).collect();
+        let adjacent = match on.as_slice() {
+            [] | [_] => true,
+            [a, b] => b - a == 1 || (*a == 0 && *b == N - 1),
+            _ => false,
+        };
+        assert!(
+            adjacent,
+            "coils {on:?} energised at {:?} are not neighbours",
+            pattern.at
+        );
+    }
+}
+
+/// Asserts that `pins` did not change from `from` to `to` and kept at least `min_on`
+/// of them high, so the motor held its position with torque
+#[track_caller]
+pub fn assert_holds<const N: usize>(
+    recorder: &Recorder,
+    pins: [&'static str; N],
+    from: Duration,
+    to: Duration,
+    min_on: usize,
+) {
+    let levels = recorder.levels_at(pins, from);
+    let on = levels.iter().filter(|&&high| high).count();
+    assert!(
+        on >= min_on,
+        "only {on} of {min_on} coils energised at {from:?}: {levels:?}"
+    );
+    if let Some(change) = recorder
+        .transitions()
+        .iter()
+        .find(|t| t.at > from && t.at <= to && pins.contains(&t.pin))
+    {
+        panic!("{} changed at {:?} while holding", change.pin, change.at);
+    }
+}
+
+/// Returns the time between consecutive patterns
+pub fn intervals<const N: usize>(patterns: &[Pattern<N>]) -> Vec<Duration> {
+    patterns
+        .windows(2)
+        .map(|pair| pair[1].at.saturating_sub(pair[0].at))
+        .collect()
+}
+
+/// Asserts that consecutive patterns are at least `min` apart, e.g. to check a speed limit
+#[track_caller]
+pub fn assert_min_interval<const N: usize>(patterns: &[Pattern<N>], min: Duration) {
+    for pair in patterns.windows(2) {
+        let interval = pair[1].at.saturating_sub(pair[0].at);
+        assert!(
+            interval >= min,
+            "only {interval:?} between the patterns at {:?} and {:?}, expected at least {min:?}",
+            pair[0].at,
+            pair[1].at
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Helpers to test drivers on the host instead of on a board
+//!
+//! A `Recorder` hands out output pins and a delay sharing one virtual clock.
+//! The pins log every transition with the virtual time it happened at, the
+//! delay only advances the clock, so a test of a slow move runs instantly.
+//! Run the tests for the host target, e.g.
+//! `cargo test -p test-support --target x86_64-unknown-linux-gnu`.
+
+use core::{cell::RefCell, convert::Infallible, time::Duration};
+use std::rc::Rc;
+
+use embedded_hal::{
+    delay::DelayNs,
+    digital::{ErrorType, OutputPin, PinState, StatefulOutputPin},
+};
+
+pub mod check;
+pub mod sequences;
+
+// === Recorder ===
+
+/// A pin changing its level at a point in virtual time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub at: Duration,
+    pub pin: &'static str,
+    pub state: PinState,
+}
+
+/// The levels of a group of pins, from the time `at` until the next pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pattern<const N: usize> {
+    pub at: Duration,
+    pub levels: [bool; N],
+}
+
+#[derive(Debug, Default)]
+struct Log {
+    now: Duration,
+    levels: Vec<(&'static str, bool)>,
+    transitions: Vec<Transition>,
+}
+
+/// Shared log and clock of all pins and delays created from it
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    log: Rc<RefCell<Log>>,
+}
+
+impl Recorder {
+    /// Create a new `Recorder` at virtual time 0
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an output pin called `name`, starting low
+    ///
+    /// # Panics
+    ///
+    /// Panics if a pin with the same name already exists
+    pub fn pin(&self, name: &'static str) -> RecordingPin {
+        let mut log = self.log.borrow_mut();
+        assert!(
+            log.levels.iter().all(|(pin, _)| *pin != name),
+            "pin {name} created twice"
+        );
+        log.levels.push((name, false));
+        RecordingPin {
+            name,
+            log: self.log.clone(),
+        }
+    }
+
+    /// Create a delay advancing the virtual clock
+    pub fn delay(&self) -> VirtualDelay {
+        VirtualDelay {
+            log: self.log.clone(),
+        }
+    }
+
+    /// Returns the virtual time
+    pub fn now(&self) -> Duration {
+        self.log.borrow().now
+    }
+
+    /// Move the virtual clock on, e.g. to model time spent outside the driver
+    pub fn advance(&self, duration: Duration) {
+        self.log.borrow_mut().now += duration;
+    }
+
+    /// Returns true while the pin called `name` is high
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no pin called `name`
+    pub fn is_high(&self, name: &str) -> bool {
+        self.log
+            .borrow()
+            .levels
+            .iter()
+            .find(|(pin, _)| *pin == name)
+            .unwrap_or_else(|| panic!("no pin called {name}"))
+            .1
+    }
+
+    /// Returns the current levels of `pins`
+    pub fn levels<const N: usize>(&self, pins: [&str; N]) -> [bool; N] {
+        pins.map(|pin| self.is_high(pin))
+    }
+
+    /// Returns the levels `pins` had at virtual time `at`, after any transitions at that time
+    pub fn levels_at<const N: usize>(&self, pins: [&str; N], at: Duration) -> [bool; N] {
+        let mut levels = self.levels(pins);
+        let log = self.log.borrow();
+        for transition in log.transitions.iter().rev().take_while(|t| t.at > at) {
+            if let Some(index) = pins.iter().position(|pin| *pin == transition.pin) {
+                levels[index] = transition.state == PinState::Low;
+            }
+        }
+        levels
+    }
+
+    /// Returns every transition since the recorder was created or cleared
+    pub fn transitions(&self) -> Vec<Transition> {
+        self.log.borrow().transitions.clone()
+    }
+
+    /// Returns the levels of `pins` after every change, from the first recorded transition on.
+    /// Transitions at the same virtual time are merged, as no time passed for the
+    /// hardware to see the levels in between.
+    pub fn patterns<const N: usize>(&self, pins: [&'static str; N]) -> Vec<Pattern<N>> {
+        let transitions = self.transitions();
+        // undo the transitions to get the levels before the first one
+        let mut levels = self.levels(pins);
+        for transition in transitions.iter().rev() {
+            if let Some(index) = pins.iter().position(|pin| *pin == transition.pin) {
+                levels[index] = transition.state == PinState::Low;
+            }
+        }
+
+        let mut patterns: Vec<Pattern<N>> = Vec::new();
+        for group in transitions.chunk_by(|a, b| a.at == b.at) {
+            for transition in group {
+                if let Some(index) = pins.iter().position(|pin| *pin == transition.pin) {
+                    levels[index] = transition.state == PinState::High;
+                }
+            }
+            if patterns.last().is_none_or(|last| last.levels != levels) {
+                patterns.push(Pattern {
+                    at: group[0].at,
+                    levels,
+                });
+            }
+        }
+        patterns
+    }
+
+    /// Forget the recorded transitions, keeping the levels and the clock
+    pub fn clear(&self) {
+        self.log.borrow_mut().transitions.clear();
+    }
+}
+
+// === RecordingPin ===
+
+/// An output pin logging its transitions to a `Recorder`
+#[derive(Debug)]
+pub struct RecordingPin {
+    name: &'static str,
+    log: Rc<RefCell<Log>>,
+}
+
+impl RecordingPin {
+    /// Returns the name the pin was created with
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl ErrorType for RecordingPin {
+    type Error = Infallible;
+}
+
+impl OutputPin for RecordingPin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.set_state(PinState::Low)
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.set_state(PinState::High)
+    }
+
+    fn set_state(&mut self, state: PinState) -> Result<(), Infallible> {
+        let mut log = self.log.borrow_mut();
+        let high = state == PinState::High;
+        let Some(level) = log.levels.iter_mut().find(|(pin, _)| *pin == self.name) else {
+            unreachable!("pins are registered when created")
+        };
+        // setting the level a pin already has is not a transition
+        if level.1 == high {
+            return Ok(());
+        }
+        level.1 = high;
+        let at = log.now;
+        log.transitions.push(Transition {
+            at,
+            pin: self.name,
+            state,
+        });
+        Ok(())
+    }
+}
+
+impl StatefulOutputPin for RecordingPin {
+    fn is_set_high(&mut self) -> Result<bool, Infallible> {
+        let log = self.log.borrow();
+        Ok(log
+            .levels
+            .iter()
+            .any(|(pin, high)| *pin == self.name && *high))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Infallible> {
+        self.is_set_high().map(|high| !high)
+    }
+}
+
+// === VirtualDelay ===
+
+/// A delay that returns at once and only moves the virtual clock of its `Recorder`
+#[derive(Debug)]
+pub struct VirtualDelay {
+    log: Rc<RefCell<Log>>,
+}
+
+impl DelayNs for VirtualDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        self.log.borrow_mut().now += Duration::from_nanos(u64::from(ns));
+    }
+}
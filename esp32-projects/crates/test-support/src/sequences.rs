@@ -0,0 +1,41 @@
+//! Coil sequences of a 4 wire unipolar stepper like the 28BYJ-48, wired IN1 to IN4,
+//! and of a bipolar stepper
+//!
+//! These are written down independently of the driver, so a test comparing
+//! the driver against them catches a typo in either.
+
+/// One coil at a time
+pub const WAVE: [[bool; 4]; 4] = [
+    [false, false, false, true],
+    [false, false, true, false],
+    [false, true, false, false],
+    [true, false, false, false],
+];
+
+/// Two adjacent coils at a time
+pub const FULL_STEP: [[bool; 4]; 4] = [
+    [false, false, true, true],
+    [false, true, true, false],
+    [true, true, false, false],
+    [true, false, false, true],
+];
+
+/// Alternating one and two coils
+pub const HALF_STEP: [[bool; 4]; 8] = [
+    [false, false, false, true],
+    [false, false, true, true],
+    [false, false, true, false],
+    [false, true, true, false],
+    [false, true, false, false],
+    [true, true, false, false],
+    [true, false, false, false],
+    [true, false, false, true],
+];
+
+/// Full steps of a bipolar stepper on two H-bridges, wired A+, A-, B+, B-
+pub const BIPOLAR_FULL_STEP: [[bool; 4]; 4] = [
+    [true, false, true, false],
+    [false, true, true, false],
+    [false, true, false, true],
+    [true, false, false, true],
+];
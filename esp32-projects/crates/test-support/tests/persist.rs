@@ -0,0 +1,120 @@
+//! Host tests of restoring the ULN2003 driver from a checkpoint after a reboot
+
+use core::time::Duration;
+
+use motor_controller_uln2003::{
+    persist::{Checkpoint, Checkpointer, MemoryStore},
+    Direction, StepMode, StepperMotor, ULN2003,
+};
+use test_support::{
+    check::assert_walks_table,
+    sequences::{FULL_STEP, HALF_STEP},
+    Recorder, RecordingPin, VirtualDelay,
+};
+
+type Motor = ULN2003<RecordingPin, RecordingPin, RecordingPin, RecordingPin, VirtualDelay>;
+
+const PINS: [&str; 4] = ["in1", "in2", "in3", "in4"];
+
+/// A freshly booted motor on new pins, as after a reset
+fn boot(mode: StepMode) -> (Recorder, Motor) {
+    let recorder = Recorder::new();
+    let motor = ULN2003::new(
+        recorder.pin(PINS[0]),
+        recorder.pin(PINS[1]),
+        recorder.pin(PINS[2]),
+        recorder.pin(PINS[3]),
+        Some(recorder.delay()),
+    )
+    .with_step_mode(mode);
+    (recorder, motor)
+}
+
+#[test]
+fn resumes_position_and_coils_after_a_reboot() {
+    let (recorder, mut motor) = boot(StepMode::HalfStep);
+    let mut checkpoints = Checkpointer::new(MemoryStore::new(), Duration::from_secs(10));
+    motor.move_by(13, 1).unwrap();
+    assert!(checkpoints.flush(&motor).unwrap());
+    let last = recorder.patterns(PINS)[12];
+    let store = checkpoints.into_inner();
+
+    let (recorder, mut motor) = boot(StepMode::HalfStep);
+    let mut checkpoints = Checkpointer::new(store, Duration::from_secs(10));
+    assert!(checkpoints.restore(&mut motor).unwrap());
+    assert_eq!(motor.current_position(), 13);
+    assert!(!checkpoints.is_dirty(&motor));
+
+    motor.move_by(-2, 1).unwrap();
+    let mut patterns = vec![last];
+    patterns.extend(recorder.patterns(PINS));
+    assert_eq!(assert_walks_table(&patterns, &HALF_STEP), -2);
+    assert_eq!(motor.current_position(), 11);
+}
+
+#[test]
+fn restores_into_a_different_step_mode() {
+    let (recorder, mut motor) = boot(StepMode::FullStep);
+    let mut store = MemoryStore::new();
+    motor.move_by(6, 1).unwrap();
+    let last = recorder.patterns(PINS)[5];
+    Checkpointer::new(&mut store, Duration::ZERO)
+        .flush(&motor)
+        .unwrap();
+
+    let (recorder, mut motor) = boot(StepMode::HalfStep);
+    Checkpointer::new(&mut store, Duration::ZERO)
+        .restore(&mut motor)
+        .unwrap();
+    // 6 full steps are 12 half steps
+    assert_eq!(motor.current_position(), 12);
+    motor.move_by(1, 1).unwrap();
+
+    // a full step state is also a half step state, the first half step leaves one of its coils on
+    let first = recorder.patterns(PINS)[0];
+    assert_eq!(last.levels, FULL_STEP[1]);
+    assert_eq!(assert_walks_table(&[last, first], &HALF_STEP), 1);
+}
+
+#[test]
+fn throttles_writes() {
+    let (_recorder, mut motor) = boot(StepMode::Wave);
+    let mut checkpoints = Checkpointer::new(MemoryStore::new(), Duration::from_secs(10));
+
+    motor.move_by(5, 1).unwrap();
+    assert!(checkpoints.update(&motor, Duration::from_secs(1)).unwrap());
+    // unchanged, nothing to write
+    assert!(!checkpoints.update(&motor, Duration::from_secs(20)).unwrap());
+
+    motor.move_by(5, 1).unwrap();
+    assert!(checkpoints.update(&motor, Duration::from_secs(25)).unwrap());
+    motor.move_by(5, 1).unwrap();
+    assert!(!checkpoints.update(&motor, Duration::from_secs(30)).unwrap());
+    assert!(checkpoints.is_dirty(&motor));
+    assert!(checkpoints.update(&motor, Duration::from_secs(35)).unwrap());
+
+    assert_eq!(checkpoints.store().writes(), 3);
+}
+
+#[test]
+fn checkpoints_survive_encoding() {
+    for checkpoint in [
+        Checkpoint::new(-123_456),
+        Checkpoint::new(i32::MAX).with_phase(0b1001),
+        Checkpoint::new(-7).with_microsteps(16),
+        Checkpoint::new(42).with_phase(0b0011).with_microsteps(2),
+        Checkpoint::new(5).with_engaged(Direction::Reverse),
+        Checkpoint::new(-9)
+            .with_phase(0b0110)
+            .with_microsteps(2)
+            .with_engaged(Direction::Normal),
+    ] {
+        let mut buf = [0; 11];
+        assert_eq!(
+            Checkpoint::from_bytes(checkpoint.to_bytes(&mut buf)),
+            Some(checkpoint)
+        );
+    }
+    assert_eq!(Checkpoint::from_bytes(&[1, 2, 3]), None);
+    assert_eq!(Checkpoint::from_bytes(&[1, 2, 3, 4, 2]), None);
+}
@@ -0,0 +1,172 @@
+//! Host tests of the STEP/DIR timing and microstep pins of the step/dir driver
+
+use core::time::Duration;
+
+use embedded_hal::digital::PinState;
+use motor_controller_step_dir::{Chip, Direction, NoPin, StepDir, StepError, StepperMotor, Timing};
+use test_support::{Recorder, RecordingPin, VirtualDelay};
+
+type Motor = StepDir<RecordingPin, RecordingPin, VirtualDelay, NoPin, RecordingPin>;
+
+const CHIPS: [Chip; 3] = [Chip::A4988, Chip::Drv8825, Chip::Tmc2208];
+
+fn motor(chip: Chip) -> (Recorder, Motor) {
+    let recorder = Recorder::new();
+    let motor = StepDir::new(
+        chip,
+        recorder.pin("step"),
+        recorder.pin("dir"),
+        recorder.delay(),
+    )
+    .with_microstep_pins(
+        recorder.pin("ms1"),
+        recorder.pin("ms2"),
+        Some(recorder.pin("ms3")),
+    );
+    (recorder, motor)
+}
+
+fn ns(nanos: u32) -> Duration {
+    Duration::from_nanos(nanos.into())
+}
+
+/// Returns the times `pin` went to `state`
+fn edges(recorder: &Recorder, pin: &str, state: PinState) -> Vec<Duration> {
+    recorder
+        .transitions()
+        .iter()
+        .filter(|t| t.pin == pin && t.state == state)
+        .map(|t| t.at)
+        .collect()
+}
+
+#[test]
+fn step_pulses_keep_the_minimum_high_and_low_times() {
+    for chip in CHIPS {
+        let (recorder, mut motor) = motor(chip);
+        let timing = chip.timing();
+        for _ in 0..5 {
+            motor.step().unwrap();
+        }
+
+        let rising = edges(&recorder, "step", PinState::High);
+        let falling = edges(&recorder, "step", PinState::Low);
+        assert_eq!((rising.len(), falling.len()), (5, 5), "{chip:?}");
+        for (high, low) in rising.iter().zip(&falling) {
+            assert_eq!(*low - *high, ns(timing.step_high()), "{chip:?}");
+        }
+        for (low, next) in falling.iter().zip(&rising[1..]) {
+            assert!(*next - *low >= ns(timing.step_low()), "{chip:?}");
+        }
+        assert_eq!(motor.current_position(), 5);
+    }
+}
+
+#[test]
+fn dir_is_set_up_before_and_held_after_the_step() {
+    for chip in CHIPS {
+        let (recorder, mut motor) = motor(chip);
+        let timing = chip.timing();
+        motor.step().unwrap();
+        motor.set_direction(Direction::Reverse);
+        motor.step().unwrap();
+        motor.step().unwrap();
+
+        // DIR is high for Normal, so it changes before the first and the second step
+        let rising = edges(&recorder, "step", PinState::High);
+        let dir = [
+            edges(&recorder, "dir", PinState::High)[0],
+            edges(&recorder, "dir", PinState::Low)[0],
+        ];
+        assert!(rising[0] - dir[0] >= ns(timing.dir_setup()), "{chip:?}");
+        assert!(dir[1] - rising[0] >= ns(timing.dir_hold()), "{chip:?}");
+        assert!(rising[1] - dir[1] >= ns(timing.dir_setup()), "{chip:?}");
+        // without a change there is nothing to wait for
+        assert_eq!(
+            recorder
+                .transitions()
+                .iter()
+                .filter(|t| t.pin == "dir")
+                .count(),
+            2
+        );
+        assert_eq!(motor.current_position(), -1);
+    }
+}
+
+#[test]
+fn holds_dir_longer_than_the_step_pulse() {
+    let (recorder, motor) = motor(Chip::A4988);
+    let mut motor = motor.with_timing(Timing::new(100, 100, 500, 800));
+    motor.step().unwrap();
+    motor.set_direction(Direction::Reverse);
+    motor.step().unwrap();
+
+    let rising = edges(&recorder, "step", PinState::High);
+    let falling = edges(&recorder, "dir", PinState::Low);
+    assert_eq!(falling[0] - rising[0], ns(800));
+    assert_eq!(rising[1] - falling[0], ns(500));
+}
+
+#[test]
+fn microstep_pins_select_the_resolution() {
+    let high = true;
+    let low = false;
+    for (chip, settings) in [
+        (
+            Chip::A4988,
+            &[
+                (1, [low, low, low]),
+                (2, [high, low, low]),
+                (4, [low, high, low]),
+                (8, [high, high, low]),
+                (16, [high, high, high]),
+            ][..],
+        ),
+        (
+            Chip::Drv8825,
+            &[
+                (1, [low, low, low]),
+                (2, [high, low, low]),
+                (4, [low, high, low]),
+                (8, [high, high, low]),
+                (16, [low, low, high]),
+                (32, [high, low, high]),
+            ][..],
+        ),
+        (
+            Chip::Tmc2208,
+            &[
+                (2, [high, low, low]),
+                (4, [low, high, low]),
+                (8, [low, low, low]),
+                (16, [high, high, low]),
+            ][..],
+        ),
+    ] {
+        let (recorder, mut motor) = motor(chip);
+        for &(microsteps, levels) in settings {
+            let changed = recorder.now();
+            recorder.clear();
+            motor.set_microsteps(microsteps).unwrap();
+            assert_eq!(
+                recorder.levels(["ms1", "ms2", "ms3"]),
+                levels,
+                "{chip:?} 1/{microsteps}"
+            );
+            assert_eq!(motor.microsteps(), microsteps);
+
+            // the next step waits out the setup time after the microstep pins changed
+            motor.step().unwrap();
+            let rising = edges(&recorder, "step", PinState::High)[0];
+            assert!(
+                rising - changed >= ns(chip.timing().dir_setup()),
+                "{chip:?}"
+            );
+        }
+        assert!(matches!(
+            motor.set_microsteps(64),
+            Err(StepError::UnsupportedMicrosteps { microsteps: 64 })
+        ));
+    }
+}
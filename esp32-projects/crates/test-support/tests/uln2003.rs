@@ -0,0 +1,249 @@
+//! Host tests of the coil sequences and timing of the ULN2003 driver
+
+use core::time::Duration;
+
+use embedded_hal::digital::PinState::{self, High, Low};
+use motor_controller_uln2003::{
+    idle::IdlePolicy,
+    profile::MotionProfile,
+    stop::{StopMode, StopToken},
+    CoilStepper, Sequence, SequenceError, SoftLimits, StepError, StepMode, StepperMotor, ULN2003,
+};
+use test_support::{
+    check::{
+        assert_adjacent_coils, assert_holds, assert_min_interval, assert_walks_table, intervals,
+    },
+    sequences::{BIPOLAR_FULL_STEP, FULL_STEP, HALF_STEP, WAVE},
+    Pattern, Recorder, RecordingPin, VirtualDelay,
+};
+
+type Motor = ULN2003<RecordingPin, RecordingPin, RecordingPin, RecordingPin, VirtualDelay>;
+
+const PINS: [&str; 4] = ["in1", "in2", "in3", "in4"];
+
+fn motor(mode: StepMode) -> (Recorder, Motor) {
+    let recorder = Recorder::new();
+    let motor = ULN2003::new(
+        recorder.pin(PINS[0]),
+        recorder.pin(PINS[1]),
+        recorder.pin(PINS[2]),
+        recorder.pin(PINS[3]),
+        Some(recorder.delay()),
+    )
+    .with_step_mode(mode);
+    (recorder, motor)
+}
+
+#[test]
+fn sequences_match_the_tables() {
+    for (mode, table) in [
+        (StepMode::Wave, &WAVE[..]),
+        (StepMode::FullStep, &FULL_STEP[..]),
+        (StepMode::HalfStep, &HALF_STEP[..]),
+    ] {
+        let (recorder, mut motor) = motor(mode);
+        motor.move_by(20, 2).unwrap();
+
+        let patterns = recorder.patterns(PINS);
+        assert_eq!(patterns.len(), 20, "{mode:?}");
+        assert_eq!(patterns[0].levels, table[0], "{mode:?}");
+        assert_eq!(assert_walks_table(&patterns, table), 19, "{mode:?}");
+        assert_adjacent_coils(&patterns);
+        assert_eq!(motor.current_position(), 20);
+    }
+}
+
+#[test]
+fn reverse_walks_the_table_backwards() {
+    let (recorder, mut motor) = motor(StepMode::HalfStep);
+    motor.move_by(-12, 1).unwrap();
+
+    let patterns = recorder.patterns(PINS);
+    assert_eq!(patterns[0].levels, HALF_STEP[HALF_STEP.len() - 1]);
+    assert_eq!(assert_walks_table(&patterns, &HALF_STEP), -11);
+    assert_eq!(motor.current_position(), -12);
+}
+
+#[test]
+fn steps_are_spaced_by_the_delay() {
+    let (recorder, mut motor) = motor(StepMode::FullStep);
+    motor.move_by(10, 5).unwrap();
+
+    let patterns = recorder.patterns(PINS);
+    assert!(intervals(&patterns)
+        .iter()
+        .all(|interval| *interval == Duration::from_millis(5)));
+    assert_eq!(recorder.now(), Duration::from_millis(50));
+}
+
+#[test]
+fn holds_two_coils_after_a_full_step_move() {
+    let (recorder, mut motor) = motor(StepMode::FullStep);
+    motor.move_by(7, 2).unwrap();
+    let done = recorder.now();
+    recorder.advance(Duration::from_secs(1));
+
+    assert_holds(&recorder, PINS, done, recorder.now(), 2);
+}
+
+#[test]
+fn released_motor_continues_from_its_phase() {
+    let (recorder, mut motor) = motor(StepMode::HalfStep);
+    motor.set_idle_policy(IdlePolicy::ReleaseAfter(Duration::ZERO));
+    motor.move_by(5, 2).unwrap();
+    assert_eq!(recorder.levels(PINS), [false; 4]);
+
+    let last = recorder.patterns(PINS)[4];
+    recorder.clear();
+    motor.move_by(3, 2).unwrap();
+
+    let mut patterns = vec![last];
+    patterns.extend(recorder.patterns(PINS).into_iter().take(3));
+    assert_eq!(assert_walks_table(&patterns, &HALF_STEP), 3);
+}
+
+#[test]
+fn profile_ramps_the_step_rate() {
+    let (recorder, mut motor) = motor(StepMode::HalfStep);
+    let profile = MotionProfile::trapezoidal(500.0, 1000.0);
+    motor.move_by_profile(400, &profile).unwrap();
+
+    let patterns: Vec<Pattern<4>> = recorder.patterns(PINS);
+    assert_eq!(assert_walks_table(&patterns, &HALF_STEP), 399);
+    assert_min_interval(&patterns, Duration::from_millis(2));
+
+    let intervals = intervals(&patterns);
+    let fastest = intervals.iter().min().unwrap();
+    assert!(intervals[0] > *fastest * 5);
+    assert!(intervals[intervals.len() - 1] > *fastest * 5);
+}
+
+#[test]
+fn halted_token_cancels_before_the_first_step() {
+    let (recorder, mut motor) = motor(StepMode::Wave);
+    let token = StopToken::new();
+    token.halt();
+    motor.set_stop_token(Some(token));
+
+    assert_eq!(
+        motor.move_by(10, 1),
+        Err(StepError::Cancelled { executed: 0 })
+    );
+    assert!(recorder.transitions().is_empty());
+}
+
+#[test]
+fn cancelled_moves_apply_the_idle_policy() {
+    let profile = MotionProfile::trapezoidal(500.0, 1000.0);
+    for mode in [StopMode::Halt, StopMode::Decelerate] {
+        for profiled in [false, true] {
+            let (recorder, mut motor) = motor(StepMode::FullStep);
+            motor.move_by(3, 1).unwrap();
+            assert_eq!(recorder.levels(PINS).iter().filter(|on| **on).count(), 2);
+
+            let token = StopToken::new();
+            match mode {
+                StopMode::Halt => token.halt(),
+                StopMode::Decelerate => token.stop(),
+            }
+            motor.set_stop_token(Some(token));
+            motor.set_idle_policy(IdlePolicy::ReleaseAfter(Duration::ZERO));
+            let result = if profiled {
+                motor.move_by_profile(10, &profile)
+            } else {
+                motor.move_by(10, 1)
+            };
+            assert_eq!(result, Err(StepError::Cancelled { executed: 0 }));
+            assert_eq!(recorder.levels(PINS), [false; 4], "{mode:?}");
+        }
+    }
+}
+
+#[test]
+fn custom_sequence_drives_any_pins() {
+    const BIPOLAR: [[PinState; 4]; 4] = [
+        [High, Low, High, Low],
+        [Low, High, High, Low],
+        [Low, High, Low, High],
+        [High, Low, Low, High],
+    ];
+    const PINS: [&str; 4] = ["a+", "a-", "b+", "b-"];
+    let recorder = Recorder::new();
+    let sequence = Sequence::new(&BIPOLAR, 2).unwrap();
+    let mut motor = CoilStepper::from_coils(
+        PINS.map(|name| recorder.pin(name)),
+        sequence,
+        Some(recorder.delay()),
+    );
+    motor.move_by(9, 1).unwrap();
+    motor.move_by(-3, 1).unwrap();
+
+    let patterns = recorder.patterns(PINS);
+    assert_eq!(patterns[0].levels, BIPOLAR_FULL_STEP[0]);
+    assert_eq!(assert_walks_table(&patterns[..9], &BIPOLAR_FULL_STEP), 8);
+    assert_eq!(assert_walks_table(&patterns[8..], &BIPOLAR_FULL_STEP), -3);
+    assert_eq!(motor.current_position(), 6);
+}
+
+#[test]
+fn rejects_tables_switching_too_many_coils() {
+    static WRAP: [[PinState; 4]; 3] = [
+        [High, Low, Low, Low],
+        [High, High, Low, Low],
+        [Low, High, High, Low],
+    ];
+    static JUMP: [[PinState; 4]; 3] = [
+        [High, Low, Low, Low],
+        [Low, High, Low, Low],
+        [High, Low, High, High],
+    ];
+    assert_eq!(
+        Sequence::new(&JUMP, 2),
+        Err(SequenceError::TooManyChanges {
+            step: 2,
+            changes: 4,
+            max: 2
+        })
+    );
+    // the wrap from the last step back to the first counts as well
+    assert_eq!(
+        Sequence::new(&WRAP, 2),
+        Err(SequenceError::TooManyChanges {
+            step: 0,
+            changes: 3,
+            max: 2
+        })
+    );
+    assert!(Sequence::new(&WRAP, 3).is_ok());
+    assert_eq!(Sequence::<4>::new(&[], 2), Err(SequenceError::Empty));
+}
+
+#[test]
+fn changing_the_sequence_keeps_the_coils() {
+    let (recorder, mut motor) = motor(StepMode::FullStep);
+    motor.move_by(3, 1).unwrap();
+    motor.set_step_mode(StepMode::HalfStep);
+    assert_eq!(motor.step_mode(), Some(StepMode::HalfStep));
+    motor.move_by(4, 1).unwrap();
+
+    let patterns = recorder.patterns(PINS);
+    assert_eq!(patterns[2].levels, FULL_STEP[2]);
+    assert_eq!(assert_walks_table(&patterns[2..], &HALF_STEP), 4);
+}
+
+#[test]
+fn changing_the_sequence_rescales_the_position() {
+    let (_recorder, mut motor) = motor(StepMode::FullStep);
+    motor.set_soft_limits(Some(SoftLimits::new(-100, 100)));
+    motor.move_by(3, 1).unwrap();
+
+    motor.set_step_mode(StepMode::HalfStep);
+    assert_eq!(motor.current_position(), 6);
+    assert_eq!(motor.soft_limits(), Some(SoftLimits::new(-200, 200)));
+
+    // a half step has no full step position, it is rounded toward zero
+    motor.move_by(-1, 1).unwrap();
+    motor.set_step_mode(StepMode::Wave);
+    assert_eq!(motor.current_position(), 2);
+    assert_eq!(motor.soft_limits(), Some(SoftLimits::new(-100, 100)));
+}
@@ -0,0 +1,6 @@
+//! crate tmc2209
+
+use embuild::espidf;
+fn main() {
+    espidf::sysenv::output();
+}
@@ -0,0 +1,146 @@
+//! Encoding of the single wire UART datagrams
+//!
+//! A write is 8 bytes: sync, node address, register with the write bit set,
+//! 4 data bytes MSB first and a CRC. A read request is 4 bytes: sync, node
+//! address, register and CRC, answered by 8 bytes addressed to the master.
+
+/// Sync nibble and the reserved bits that start every datagram
+const SYNC: u8 = 0x05;
+/// Node address of the replies
+const MASTER_ADDRESS: u8 = 0xFF;
+/// Set on the register address of writes
+const WRITE: u8 = 0x80;
+
+/// Length of a write datagram and of a reply
+pub const DATAGRAM_LEN: usize = 8;
+/// Length of a read request
+pub const READ_REQUEST_LEN: usize = 4;
+
+/// Ways a reply can be corrupted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyError {
+    /// The CRC did not match the data
+    Crc,
+    /// The sync, master address or register did not match the request
+    Header,
+}
+
+/// CRC8 with the polynomial x⁸ + x² + x + 1, feeding each byte LSB first as in the datasheet
+pub fn crc8(bytes: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in bytes {
+        let mut byte = byte;
+        for _ in 0..8 {
+            crc = if (crc >> 7) ^ (byte & 1) == 0 {
+                crc << 1
+            } else {
+                (crc << 1) ^ 0x07
+            };
+            byte >>= 1;
+        }
+    }
+    crc
+}
+
+/// Encode a write of `value` to `register` of the driver at `node`
+pub fn write(node: u8, register: u8, value: u32) -> [u8; DATAGRAM_LEN] {
+    let [b3, b2, b1, b0] = value.to_be_bytes();
+    let mut datagram = [SYNC, node, register | WRITE, b3, b2, b1, b0, 0];
+    datagram[DATAGRAM_LEN - 1] = crc8(&datagram[..DATAGRAM_LEN - 1]);
+    datagram
+}
+
+/// Encode a request to read `register` of the driver at `node`
+pub fn read_request(node: u8, register: u8) -> [u8; READ_REQUEST_LEN] {
+    let mut datagram = [SYNC, node, register & !WRITE, 0];
+    datagram[READ_REQUEST_LEN - 1] = crc8(&datagram[..READ_REQUEST_LEN - 1]);
+    datagram
+}
+
+/// Decode the reply to a read request of `register`
+pub fn parse_reply(reply: &[u8; DATAGRAM_LEN], register: u8) -> Result<u32, ReplyError> {
+    if crc8(&reply[..DATAGRAM_LEN - 1]) != reply[DATAGRAM_LEN - 1] {
+        return Err(ReplyError::Crc);
+    }
+    // only the low nibble is sync, the reserved bits are not guaranteed
+    if reply[0] & 0x0F != SYNC || reply[1] != MASTER_ADDRESS || reply[2] != register & !WRITE {
+        return Err(ReplyError::Header);
+    }
+    Ok(u32::from_be_bytes([reply[3], reply[4], reply[5], reply[6]]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reply of the driver with `value` in `register`
+    fn reply(register: u8, value: u32) -> [u8; DATAGRAM_LEN] {
+        let [b3, b2, b1, b0] = value.to_be_bytes();
+        let mut reply = [SYNC, MASTER_ADDRESS, register, b3, b2, b1, b0, 0];
+        reply[DATAGRAM_LEN - 1] = crc8(&reply[..DATAGRAM_LEN - 1]);
+        reply
+    }
+
+    #[test]
+    fn crc_matches_known_datagrams() {
+        // read requests of GCONF and IOIN to node 0
+        assert_eq!(crc8(&[0x05, 0x00, 0x00]), 0x48);
+        assert_eq!(crc8(&[0x05, 0x00, 0x06]), 0x6F);
+        assert_eq!(crc8(&[]), 0);
+    }
+
+    #[test]
+    fn encodes_writes() {
+        let datagram = write(3, 0x6C, 0x1234_5678);
+        assert_eq!(&datagram[..7], &[0x05, 3, 0xEC, 0x12, 0x34, 0x56, 0x78]);
+        assert_eq!(datagram[7], crc8(&datagram[..7]));
+    }
+
+    #[test]
+    fn encodes_read_requests() {
+        assert_eq!(read_request(0, 0x06), [0x05, 0x00, 0x06, 0x6F]);
+        // the write bit is never set on a read
+        assert_eq!(read_request(1, 0x80 | 0x41), read_request(1, 0x41));
+    }
+
+    #[test]
+    fn parses_replies() {
+        for value in [0, 1, 0x1000_0053, u32::MAX] {
+            assert_eq!(parse_reply(&reply(0x6C, value), 0x6C), Ok(value));
+        }
+        // the reserved bits next to the sync nibble are ignored
+        let mut reply = reply(0x02, 7);
+        reply[0] = 0xA5;
+        reply[7] = crc8(&reply[..7]);
+        assert_eq!(parse_reply(&reply, 0x02), Ok(7));
+    }
+
+    #[test]
+    fn rejects_a_bad_crc() {
+        let mut reply = reply(0x6C, 0x1000_0053);
+        reply[7] ^= 1;
+        assert_eq!(parse_reply(&reply, 0x6C), Err(ReplyError::Crc));
+        let mut reply = self::reply(0x6C, 0x1000_0053);
+        reply[4] ^= 0x10;
+        assert_eq!(parse_reply(&reply, 0x6C), Err(ReplyError::Crc));
+    }
+
+    #[test]
+    fn rejects_a_reply_of_another_register() {
+        assert_eq!(parse_reply(&reply(0x6F, 0), 0x6C), Err(ReplyError::Header));
+    }
+
+    #[test]
+    fn rejects_a_wrong_header() {
+        let mut reply = reply(0x6C, 0);
+        reply[0] = 0x0A;
+        reply[7] = crc8(&reply[..7]);
+        assert_eq!(parse_reply(&reply, 0x6C), Err(ReplyError::Header));
+
+        // the echo of the own request on the single wire, addressed to the node
+        let mut reply = self::reply(0x6C, 0);
+        reply[1] = 0x00;
+        reply[7] = crc8(&reply[..7]);
+        assert_eq!(parse_reply(&reply, 0x6C), Err(ReplyError::Header));
+    }
+}
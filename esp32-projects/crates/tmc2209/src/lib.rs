@@ -0,0 +1,220 @@
+use core::{f32::consts::SQRT_2, fmt};
+use embedded_hal::delay::DelayNs;
+use embedded_io::{Read, ReadReady, Write};
+
+pub mod datagram;
+pub mod registers;
+mod stallguard;
+
+use datagram::{ReplyError, DATAGRAM_LEN};
+use registers::{Chopconf, Gconf, Gstat, IholdIrun, Ioin, Readable, Writable};
+
+pub use stallguard::StallGuard;
+
+/// Time to wait for a reply, the driver answers after 8 bit times plus the datagram itself
+const REPLY_TIMEOUT_US: u32 = 10_000;
+const POLL_INTERVAL_US: u32 = 50;
+
+// === TmcError ===
+
+/// A type detailing various errors talking to the driver can return
+#[derive(Debug, Clone)]
+pub enum TmcError<E> {
+    /// No reply within the timeout, check the wiring, baud rate and node address
+    Timeout,
+    /// The reply was corrupted on the line
+    InvalidReply(ReplyError),
+    /// The echo on the single wire did not match what was sent
+    EchoMismatch,
+    /// The microsteps are not a power of two up to 256
+    UnsupportedMicrosteps(u16),
+    /// Received a low-level error from the HAL while talking over UART
+    UartError(E),
+}
+
+impl<E> From<E> for TmcError<E> {
+    fn from(error: E) -> Self {
+        Self::UartError(error)
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for TmcError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => f.write_str("Timed out waiting for a reply"),
+            Self::InvalidReply(ReplyError::Crc) => f.write_str("Reply had a wrong CRC"),
+            Self::InvalidReply(ReplyError::Header) => f.write_str("Reply was not for the request"),
+            Self::EchoMismatch => f.write_str("Echo did not match the datagram sent"),
+            Self::UnsupportedMicrosteps(microsteps) => {
+                write!(f, "Driver does not support 1/{microsteps} microsteps")
+            }
+            Self::UartError(err) => write!(f, "HAL uart error: {:?}", err),
+        }
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for TmcError<E> {}
+
+// === Tmc2209 ===
+
+/// A TMC2209 configured over its single wire UART
+///
+/// The UART runs at 9600 to 500k baud 8N1, with TX connected to `PDN_UART`
+/// through a 1 kΩ resistor and RX directly, so everything sent is echoed back.
+/// Stepping itself still happens on the STEP and DIR pins, e.g. with a
+/// `motor-controller-step-dir` driver.
+pub struct Tmc2209<U: Read + Write + ReadReady, D: DelayNs> {
+    uart: U,
+    delay: D,
+    node: u8,
+    echo: bool,
+    sense_resistor: f32,
+}
+
+impl<U: Read + Write + ReadReady, D: DelayNs> Tmc2209<U, D> {
+    /// Create a new `Tmc2209`, the node address 0-3 is set by the MS1 and MS2 pins.
+    /// Assumes the usual 110 mΩ sense resistors.
+    pub const fn new(uart: U, delay: D, node: u8) -> Self {
+        Self {
+            uart,
+            delay,
+            node,
+            echo: true,
+            sense_resistor: 0.11,
+        }
+    }
+
+    /// Set whether TX is looped back onto RX, true with the usual single wire setup
+    #[must_use]
+    pub const fn with_echo(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Set the value of the sense resistors in Ω, used to calculate currents
+    #[must_use]
+    pub const fn with_sense_resistor(mut self, ohms: f32) -> Self {
+        self.sense_resistor = ohms;
+        self
+    }
+
+    /// Take over the configuration from the pins: microsteps from `Chopconf`,
+    /// `PDN_UART` used for UART only, StealthChop on. Clears the status flags.
+    pub fn init(&mut self) -> Result<(), TmcError<U::Error>> {
+        let gconf = self
+            .read::<Gconf>()?
+            .with_pdn_disable(true)
+            .with_mstep_reg_select(true)
+            .with_multistep_filt(true)
+            .with_en_spread_cycle(false);
+        self.write(gconf)?;
+        self.write(Gstat::CLEAR_ALL)
+    }
+
+    /// Returns the chip version, `Ioin::VERSION` for a TMC2209
+    pub fn version(&mut self) -> Result<u8, TmcError<U::Error>> {
+        Ok(self.read::<Ioin>()?.version())
+    }
+
+    /// Set the microsteps per full step, a power of two up to 256
+    pub fn set_microsteps(&mut self, microsteps: u16) -> Result<(), TmcError<U::Error>> {
+        let chopconf = self.read::<Chopconf>()?;
+        let Some(chopconf) = chopconf.with_microsteps(microsteps) else {
+            return Err(TmcError::UnsupportedMicrosteps(microsteps));
+        };
+        self.write(chopconf)
+    }
+
+    /// Set the motor current in mA RMS, reduced to `hold_percent` of it at standstill
+    pub fn set_current(&mut self, run_ma: u16, hold_percent: u8) -> Result<(), TmcError<U::Error>> {
+        let (vsense, run) = current_scale(run_ma, self.sense_resistor);
+        let chopconf = self.read::<Chopconf>()?.with_vsense(vsense);
+        self.write(chopconf)?;
+
+        let hold = (u16::from(run) + 1) * u16::from(hold_percent.min(100)) / 100;
+        #[allow(clippy::cast_possible_truncation)]
+        let hold = hold.saturating_sub(1) as u8;
+        self.write(IholdIrun::new(hold, run, 8))
+    }
+
+    /// Use the silent StealthChop, or SpreadCycle for more torque at speed
+    pub fn set_stealth_chop(&mut self, on: bool) -> Result<(), TmcError<U::Error>> {
+        let gconf = self.read::<Gconf>()?.with_en_spread_cycle(!on);
+        self.write(gconf)
+    }
+
+    /// Read a typed register
+    pub fn read<R: Readable>(&mut self) -> Result<R, TmcError<U::Error>> {
+        self.read_raw(R::ADDRESS).map(R::from_bits)
+    }
+
+    /// Write a typed register
+    pub fn write<R: Writable>(&mut self, register: R) -> Result<(), TmcError<U::Error>> {
+        self.write_raw(R::ADDRESS, register.bits())
+    }
+
+    /// Read a register by address
+    pub fn read_raw(&mut self, register: u8) -> Result<u32, TmcError<U::Error>> {
+        self.send(&datagram::read_request(self.node, register))?;
+        let mut reply = [0; DATAGRAM_LEN];
+        self.receive(&mut reply)?;
+        datagram::parse_reply(&reply, register).map_err(TmcError::InvalidReply)
+    }
+
+    /// Write a register by address. Writes are not acknowledged,
+    /// `registers::Ifcnt` counts the ones that arrived.
+    pub fn write_raw(&mut self, register: u8, value: u32) -> Result<(), TmcError<U::Error>> {
+        self.send(&datagram::write(self.node, register, value))
+    }
+
+    /// Stop using the driver and get the UART and delay back
+    pub fn into_inner(self) -> (U, D) {
+        (self.uart, self.delay)
+    }
+
+    fn send(&mut self, datagram: &[u8]) -> Result<(), TmcError<U::Error>> {
+        self.uart.write_all(datagram)?;
+        self.uart.flush()?;
+        if self.echo {
+            let mut echo = [0; DATAGRAM_LEN];
+            let echo = &mut echo[..datagram.len()];
+            self.receive(echo)?;
+            if echo != datagram {
+                return Err(TmcError::EchoMismatch);
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Result<(), TmcError<U::Error>> {
+        let mut received = 0;
+        let mut waited = 0;
+        while received < buf.len() {
+            if self.uart.read_ready()? {
+                received += self.uart.read(&mut buf[received..])?;
+            } else if waited < REPLY_TIMEOUT_US {
+                self.delay.delay_us(POLL_INTERVAL_US);
+                waited += POLL_INTERVAL_US;
+            } else {
+                return Err(TmcError::Timeout);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Current range and scale 0-31 for a current in mA RMS.
+/// `I_rms = (CS + 1) / 32 * V_fs / (R_sense + 20 mΩ) / √2`
+fn current_scale(ma: u16, sense_resistor: f32) -> (bool, u8) {
+    let scale = |full_scale: f32| {
+        32.0 * f32::from(ma) / 1000.0 * (sense_resistor + 0.02) * SQRT_2 / full_scale - 1.0
+    };
+    // the high sensitivity range gives a finer resolution for small currents
+    let (vsense, cs) = match scale(0.325) {
+        cs if cs < 16.0 => (true, scale(0.180)),
+        cs => (false, cs),
+    };
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let cs = cs.round().clamp(0.0, 31.0) as u8;
+    (vsense, cs)
+}
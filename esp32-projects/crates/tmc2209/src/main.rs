@@ -0,0 +1,71 @@
+use anyhow::Result;
+use esp_idf_svc::hal::{
+    delay::Delay,
+    gpio::{AnyIOPin, OutputPin, PinDriver},
+    prelude::Peripherals,
+    uart::{config::Config, UartDriver},
+    units::Hertz,
+};
+use log::info;
+
+use motor_controller_step_dir::{
+    homing::{home, HomingConfig},
+    Chip, Direction, StepDir, StepperMotor,
+};
+use tmc2209::{registers::DrvStatus, StallGuard, Tmc2209};
+
+fn main() -> Result<()> {
+    esp_idf_svc::sys::link_patches();
+    esp_idf_svc::log::EspLogger::initialize_default();
+
+    let peripherals = Peripherals::take().unwrap();
+
+    let uart = UartDriver::new(
+        peripherals.uart1,
+        peripherals.pins.gpio16,
+        peripherals.pins.gpio17,
+        Option::<AnyIOPin>::None,
+        Option::<AnyIOPin>::None,
+        &Config::new().baudrate(Hertz(115_200)),
+    )?;
+    let mut driver = Tmc2209::new(uart, Delay::new_default(), 0);
+    driver.init()?;
+    info!("TMC2209 version {:#x}", driver.version()?);
+
+    driver.set_current(600, 30)?;
+    driver.set_microsteps(16)?;
+
+    // the TMC2209 has the same step timing as the TMC2208
+    let mut motor = StepDir::new(
+        Chip::Tmc2208,
+        PinDriver::output(peripherals.pins.gpio2.downgrade_output())?,
+        PinDriver::output(peripherals.pins.gpio3.downgrade_output())?,
+        Delay::new_default(),
+    )
+    .with_enable_pin(PinDriver::output(
+        peripherals.pins.gpio4.downgrade_output(),
+    )?);
+    motor.set_microsteps(16).unwrap();
+
+    // home the blinds against the top, at most 20 revolutions of a 200 step motor away
+    let config = HomingConfig::new(Direction::Reverse, 20 * 200 * 16)
+        .with_speeds(1600.0, 1600.0)
+        .with_back_off(400);
+    let mut stall_guard = StallGuard::new(&mut driver, 60)?;
+    home(
+        &mut motor,
+        &mut stall_guard,
+        &mut Delay::new_default(),
+        &config,
+    )?;
+    info!("Homed, at position {}", motor.current_position());
+
+    let status = driver.read::<DrvStatus>()?;
+    info!(
+        "Current scale {}, overtemperature warning {}",
+        status.cs_actual(),
+        status.otpw()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,591 @@
+//! Typed views of the registers used to configure the driver
+//!
+//! Only the registers and fields needed for current, microstep, chopper and
+//! StallGuard configuration are covered, the rest can be accessed with
+//! `Tmc2209::read_raw` and `Tmc2209::write_raw`.
+
+#![allow(clippy::cast_possible_truncation)]
+
+/// A driver register with its address
+pub trait Register: Copy {
+    const ADDRESS: u8;
+
+    /// Create the register from its raw value
+    fn from_bits(bits: u32) -> Self;
+    /// Returns the raw value
+    fn bits(self) -> u32;
+}
+
+/// A register that can be read over UART
+pub trait Readable: Register {}
+
+/// A register that can be written over UART
+pub trait Writable: Register {}
+
+const fn bit(bits: u32, n: u32) -> bool {
+    bits & (1 << n) != 0
+}
+
+const fn with_bit(bits: u32, n: u32, on: bool) -> u32 {
+    if on {
+        bits | (1 << n)
+    } else {
+        bits & !(1 << n)
+    }
+}
+
+const fn field(bits: u32, shift: u32, width: u32) -> u32 {
+    (bits >> shift) & ((1 << width) - 1)
+}
+
+const fn with_field(bits: u32, shift: u32, width: u32, value: u32) -> u32 {
+    let mask = ((1 << width) - 1) << shift;
+    (bits & !mask) | ((value << shift) & mask)
+}
+
+// === GCONF ===
+
+/// Global configuration
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Gconf(u32);
+
+impl Gconf {
+    /// Returns true if VREF scales the current
+    pub const fn i_scale_analog(self) -> bool {
+        bit(self.0, 0)
+    }
+
+    /// Scale the current with the voltage on VREF
+    #[must_use]
+    pub const fn with_i_scale_analog(self, on: bool) -> Self {
+        Self(with_bit(self.0, 0, on))
+    }
+
+    /// Returns true if SpreadCycle is used instead of StealthChop
+    pub const fn en_spread_cycle(self) -> bool {
+        bit(self.0, 2)
+    }
+
+    /// Use SpreadCycle instead of the silent StealthChop
+    #[must_use]
+    pub const fn with_en_spread_cycle(self, on: bool) -> Self {
+        Self(with_bit(self.0, 2, on))
+    }
+
+    /// Returns true if the motor direction is inverted
+    pub const fn shaft(self) -> bool {
+        bit(self.0, 3)
+    }
+
+    /// Invert the motor direction
+    #[must_use]
+    pub const fn with_shaft(self, on: bool) -> Self {
+        Self(with_bit(self.0, 3, on))
+    }
+
+    /// Returns true if the `PDN_UART` pin is only used for UART
+    pub const fn pdn_disable(self) -> bool {
+        bit(self.0, 6)
+    }
+
+    /// Use `PDN_UART` only for UART, has to be set to keep standstill current reduction working
+    #[must_use]
+    pub const fn with_pdn_disable(self, on: bool) -> Self {
+        Self(with_bit(self.0, 6, on))
+    }
+
+    /// Returns true if the microsteps are set by `Chopconf` instead of the MS pins
+    pub const fn mstep_reg_select(self) -> bool {
+        bit(self.0, 7)
+    }
+
+    /// Take the microsteps from `Chopconf` instead of the MS pins, which select the node address
+    #[must_use]
+    pub const fn with_mstep_reg_select(self, on: bool) -> Self {
+        Self(with_bit(self.0, 7, on))
+    }
+
+    /// Returns true if the step input is filtered
+    pub const fn multistep_filt(self) -> bool {
+        bit(self.0, 8)
+    }
+
+    /// Filter the step input, smoothing the step rate above ~750 Hz
+    #[must_use]
+    pub const fn with_multistep_filt(self, on: bool) -> Self {
+        Self(with_bit(self.0, 8, on))
+    }
+}
+
+impl Register for Gconf {
+    const ADDRESS: u8 = 0x00;
+
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Readable for Gconf {}
+impl Writable for Gconf {}
+
+// === GSTAT ===
+
+/// Global status flags, writing a flag back clears it
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Gstat(u32);
+
+impl Gstat {
+    /// Flags that clear every status flag when written
+    pub const CLEAR_ALL: Self = Self(0b111);
+
+    /// Returns true if the driver was reset since the flag was last cleared
+    pub const fn reset(self) -> bool {
+        bit(self.0, 0)
+    }
+
+    /// Returns true if the driver shut down due to overtemperature or a short
+    pub const fn drv_err(self) -> bool {
+        bit(self.0, 1)
+    }
+
+    /// Returns true if the charge pump had an undervoltage
+    pub const fn uv_cp(self) -> bool {
+        bit(self.0, 2)
+    }
+}
+
+impl Register for Gstat {
+    const ADDRESS: u8 = 0x01;
+
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Readable for Gstat {}
+impl Writable for Gstat {}
+
+// === IFCNT ===
+
+/// Counter of successful UART writes, wrapping at 255
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Ifcnt(u32);
+
+impl Ifcnt {
+    /// Returns the number of writes received
+    pub const fn count(self) -> u8 {
+        field(self.0, 0, 8) as u8
+    }
+}
+
+impl Register for Ifcnt {
+    const ADDRESS: u8 = 0x02;
+
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Readable for Ifcnt {}
+
+// === IOIN ===
+
+/// Input pin states and the chip version
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Ioin(u32);
+
+impl Ioin {
+    /// Version of the TMC2209, 0x21
+    pub const VERSION: u8 = 0x21;
+
+    /// Returns true while the ENN pin disables the outputs
+    pub const fn enn(self) -> bool {
+        bit(self.0, 0)
+    }
+
+    /// Returns the level of the DIAG pin
+    pub const fn diag(self) -> bool {
+        bit(self.0, 4)
+    }
+
+    /// Returns the chip version
+    pub const fn version(self) -> u8 {
+        field(self.0, 24, 8) as u8
+    }
+}
+
+impl Register for Ioin {
+    const ADDRESS: u8 = 0x06;
+
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Readable for Ioin {}
+
+// === IHOLD_IRUN ===
+
+/// Motor current while running and at standstill
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IholdIrun(u32);
+
+impl IholdIrun {
+    /// Create new currents, as scales 0-31 of the full scale current.
+    /// `hold_delay` sets how gradually the current drops to `hold`, 0-15.
+    pub const fn new(hold: u8, run: u8, hold_delay: u8) -> Self {
+        let bits = with_field(0, 0, 5, hold as u32);
+        let bits = with_field(bits, 8, 5, run as u32);
+        Self(with_field(bits, 16, 4, hold_delay as u32))
+    }
+
+    /// Returns the standstill current scale
+    pub const fn hold(self) -> u8 {
+        field(self.0, 0, 5) as u8
+    }
+
+    /// Returns the running current scale
+    pub const fn run(self) -> u8 {
+        field(self.0, 8, 5) as u8
+    }
+
+    /// Returns the delay of the current reduction
+    pub const fn hold_delay(self) -> u8 {
+        field(self.0, 16, 4) as u8
+    }
+}
+
+impl Register for IholdIrun {
+    const ADDRESS: u8 = 0x10;
+
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Writable for IholdIrun {}
+
+// === TPWMTHRS ===
+
+/// Step period below which the driver switches from StealthChop to SpreadCycle,
+/// in clock cycles of 1/12 MHz, 0 to always use StealthChop
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tpwmthrs(pub u32);
+
+impl Register for Tpwmthrs {
+    const ADDRESS: u8 = 0x13;
+
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Writable for Tpwmthrs {}
+
+// === TCOOLTHRS ===
+
+/// Step period below which CoolStep and the StallGuard output on DIAG are enabled,
+/// in clock cycles of 1/12 MHz
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Tcoolthrs(pub u32);
+
+impl Tcoolthrs {
+    /// Enables StallGuard at any speed
+    pub const ALWAYS: Self = Self(0xF_FFFF);
+}
+
+impl Register for Tcoolthrs {
+    const ADDRESS: u8 = 0x14;
+
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Writable for Tcoolthrs {}
+
+// === SGTHRS ===
+
+/// StallGuard threshold, a stall is detected when `SgResult` drops to twice this value
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Sgthrs(pub u8);
+
+impl Register for Sgthrs {
+    const ADDRESS: u8 = 0x40;
+
+    fn from_bits(bits: u32) -> Self {
+        Self(field(bits, 0, 8) as u8)
+    }
+
+    fn bits(self) -> u32 {
+        u32::from(self.0)
+    }
+}
+
+impl Writable for Sgthrs {}
+
+// === SG_RESULT ===
+
+/// StallGuard load measurement, lower values mean more load
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SgResult(u32);
+
+impl SgResult {
+    /// Returns the load measurement, 0-510
+    pub const fn result(self) -> u16 {
+        field(self.0, 0, 10) as u16
+    }
+}
+
+impl Register for SgResult {
+    const ADDRESS: u8 = 0x41;
+
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Readable for SgResult {}
+
+// === CHOPCONF ===
+
+/// Chopper and microstep configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chopconf(u32);
+
+impl Default for Chopconf {
+    /// Reset value: 1/256 microsteps interpolated, toff 3, hstrt 5, tbl 2
+    fn default() -> Self {
+        Self(0x1000_0053)
+    }
+}
+
+impl Chopconf {
+    /// Returns the off time setting, 0 disables the driver
+    pub const fn toff(self) -> u8 {
+        field(self.0, 0, 4) as u8
+    }
+
+    /// Set the off time 0-15, 0 disables the driver
+    #[must_use]
+    pub const fn with_toff(self, toff: u8) -> Self {
+        Self(with_field(self.0, 0, 4, toff as u32))
+    }
+
+    /// Returns true if the high sensitivity current range is used
+    pub const fn vsense(self) -> bool {
+        bit(self.0, 17)
+    }
+
+    /// Use the high sensitivity current range, for better resolution at low currents
+    #[must_use]
+    pub const fn with_vsense(self, on: bool) -> Self {
+        Self(with_bit(self.0, 17, on))
+    }
+
+    /// Returns the microsteps per full step
+    pub const fn microsteps(self) -> u16 {
+        256 >> field(self.0, 24, 4)
+    }
+
+    /// Set the microsteps per full step, `None` unless a power of two up to 256
+    pub const fn with_microsteps(self, microsteps: u16) -> Option<Self> {
+        if microsteps == 0 || microsteps > 256 || !microsteps.is_power_of_two() {
+            return None;
+        }
+        let mres = 8 - microsteps.trailing_zeros();
+        Some(Self(with_field(self.0, 24, 4, mres)))
+    }
+
+    /// Returns true if microsteps are interpolated to 1/256
+    pub const fn intpol(self) -> bool {
+        bit(self.0, 28)
+    }
+
+    /// Interpolate the microsteps to 1/256 for smoother motion
+    #[must_use]
+    pub const fn with_intpol(self, on: bool) -> Self {
+        Self(with_bit(self.0, 28, on))
+    }
+}
+
+impl Register for Chopconf {
+    const ADDRESS: u8 = 0x6C;
+
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Readable for Chopconf {}
+impl Writable for Chopconf {}
+
+// === DRV_STATUS ===
+
+/// Driver status and error flags
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DrvStatus(u32);
+
+impl DrvStatus {
+    /// Returns true above the overtemperature pre-warning threshold
+    pub const fn otpw(self) -> bool {
+        bit(self.0, 0)
+    }
+
+    /// Returns true if the driver shut down from overtemperature
+    pub const fn ot(self) -> bool {
+        bit(self.0, 1)
+    }
+
+    /// Returns true if phase A or B is shorted to ground
+    pub const fn short_to_ground(self) -> bool {
+        bit(self.0, 2) || bit(self.0, 3)
+    }
+
+    /// Returns true if phase A or B is open, e.g. a loose motor cable
+    pub const fn open_load(self) -> bool {
+        bit(self.0, 6) || bit(self.0, 7)
+    }
+
+    /// Returns the actual current scale 0-31
+    pub const fn cs_actual(self) -> u8 {
+        field(self.0, 16, 5) as u8
+    }
+
+    /// Returns true while running in StealthChop
+    pub const fn stealth(self) -> bool {
+        bit(self.0, 30)
+    }
+
+    /// Returns true at standstill
+    pub const fn stst(self) -> bool {
+        bit(self.0, 31)
+    }
+}
+
+impl Register for DrvStatus {
+    const ADDRESS: u8 = 0x6F;
+
+    fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl Readable for DrvStatus {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_gconf() {
+        let gconf = Gconf::default()
+            .with_i_scale_analog(true)
+            .with_en_spread_cycle(true)
+            .with_shaft(true)
+            .with_pdn_disable(true)
+            .with_mstep_reg_select(true)
+            .with_multistep_filt(true);
+        assert_eq!(gconf.bits(), 0b1_1100_1101);
+        assert!(gconf.shaft() && gconf.pdn_disable() && gconf.multistep_filt());
+
+        let gconf = gconf.with_shaft(false).with_i_scale_analog(false);
+        assert_eq!(gconf.bits(), 0b1_1100_0100);
+        assert!(!gconf.shaft() && !gconf.i_scale_analog() && gconf.en_spread_cycle());
+    }
+
+    #[test]
+    fn packs_chopconf() {
+        let chopconf = Chopconf::default();
+        assert_eq!(
+            (chopconf.toff(), chopconf.microsteps(), chopconf.intpol()),
+            (3, 256, true)
+        );
+
+        let chopconf = chopconf
+            .with_microsteps(16)
+            .unwrap()
+            .with_toff(5)
+            .with_vsense(true)
+            .with_intpol(false);
+        assert_eq!(chopconf.bits(), 0x0402_0055);
+        assert_eq!((chopconf.toff(), chopconf.microsteps()), (5, 16));
+        assert!(chopconf.vsense() && !chopconf.intpol());
+
+        for microsteps in [1, 2, 4, 8, 16, 32, 64, 128, 256] {
+            let chopconf = chopconf.with_microsteps(microsteps).unwrap();
+            assert_eq!(chopconf.microsteps(), microsteps);
+        }
+        for microsteps in [0, 3, 12, 512] {
+            assert_eq!(chopconf.with_microsteps(microsteps), None);
+        }
+    }
+
+    #[test]
+    fn packs_ihold_irun() {
+        let currents = IholdIrun::new(8, 31, 10);
+        assert_eq!(currents.bits(), 0x000A_1F08);
+        assert_eq!(
+            (currents.hold(), currents.run(), currents.hold_delay()),
+            (8, 31, 10)
+        );
+
+        // out of range values do not spill into the next field
+        let currents = IholdIrun::new(0xFF, 0, 0xFF);
+        assert_eq!(currents.bits(), 0x000F_001F);
+    }
+
+    #[test]
+    fn unpacks_status_registers() {
+        assert_eq!(Ioin::from_bits(0x2100_0011).version(), Ioin::VERSION);
+        assert!(Ioin::from_bits(0x2100_0011).diag());
+
+        let status = DrvStatus::from_bits(0x8014_0081);
+        assert!(status.stst() && status.otpw() && status.open_load());
+        assert!(!status.ot() && !status.short_to_ground() && !status.stealth());
+        assert_eq!(status.cs_actual(), 20);
+
+        assert_eq!(SgResult::from_bits(0xFFFF_F3FF).result(), 0x3FF);
+        assert_eq!(Sgthrs::from_bits(0x1_0032), Sgthrs(0x32));
+    }
+}
@@ -0,0 +1,55 @@
+use embedded_hal::delay::DelayNs;
+use embedded_io::{Read, ReadReady, Write};
+use stepper::homing::Endstop;
+
+use crate::{
+    registers::{SgResult, Sgthrs, Tcoolthrs},
+    Tmc2209, TmcError,
+};
+
+/// Sensorless endstop, detecting the motor stalling at the end of travel
+///
+/// StallGuard only works in StealthChop and only measures the load while the
+/// motor turns, so low readings are ignored until the motor has been seen
+/// running freely. Use the same fast and slow `HomingConfig` speed, as it is
+/// unreliable at low speeds. The load is read over UART before every step,
+/// wiring DIAG to an input and using `homing::Switch::active_high` instead
+/// reacts faster with the same threshold.
+pub struct StallGuard<'a, U: Read + Write + ReadReady, D: DelayNs> {
+    driver: &'a mut Tmc2209<U, D>,
+    threshold: u8,
+    armed: bool,
+}
+
+impl<'a, U: Read + Write + ReadReady, D: DelayNs> StallGuard<'a, U, D> {
+    /// Enable StallGuard with a threshold 0-255, higher values detect a stall more easily
+    pub fn new(driver: &'a mut Tmc2209<U, D>, threshold: u8) -> Result<Self, TmcError<U::Error>> {
+        driver.write(Sgthrs(threshold))?;
+        driver.write(Tcoolthrs::ALWAYS)?;
+        Ok(Self {
+            driver,
+            threshold,
+            armed: false,
+        })
+    }
+
+    /// Returns the last load measurement, lower values mean more load
+    pub fn load(&mut self) -> Result<u16, TmcError<U::Error>> {
+        Ok(self.driver.read::<SgResult>()?.result())
+    }
+}
+
+impl<U: Read + Write + ReadReady, D: DelayNs> Endstop for StallGuard<'_, U, D> {
+    type Error = TmcError<U::Error>;
+
+    fn is_triggered(&mut self) -> Result<bool, Self::Error> {
+        let stalled = self.load()? <= 2 * u16::from(self.threshold);
+        if !self.armed {
+            self.armed = !stalled;
+            return Ok(false);
+        }
+        // has to run freely again before the next stall counts
+        self.armed = !stalled;
+        Ok(stalled)
+    }
+}